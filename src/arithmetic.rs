@@ -0,0 +1,533 @@
+use assignment::element_mut;
+use expansion::{find_closing_bracket, lookup_element};
+use parser::is_valid_name;
+use shell::Shell;
+use shell::ShellError;
+
+#[derive(PartialEq, Debug, Clone)]
+enum ArithmeticToken {
+    Number(i64),
+    Name(String),
+    Subscript(String),
+    Operator(&'static str),
+}
+
+/* Longer operators come first so that `<<=` isn't read as `<` `<=`. */
+const OPERATORS: [&str; 39] = [
+    "<<=", ">>=", "**", "++", "--", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "+=", "-=",
+    "*=", "/=", "%=", "&=", "|=", "^=", "+", "-", "*", "/", "%", "<", ">", "&", "|", "^", "!", "~",
+    "?", ":", "=", "(", ")", ",",
+];
+
+const ASSIGNMENT_OPERATORS: [&str; 11] = [
+    "=", "+=", "-=", "*=", "/=", "%=", "<<=", ">>=", "&=", "|=", "^=",
+];
+
+/* Binary operators from the loosest to the tightest binding, below the
+logical `&&` and `||` which short-circuit and are handled separately. */
+const BINARY_PRECEDENCE: [&[&str]; 8] = [
+    &["|"],
+    &["^"],
+    &["&"],
+    &["==", "!="],
+    &["<", "<=", ">", ">="],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+/* Variables holding expressions are evaluated recursively, up to this
+depth. */
+const MAX_RECURSION: usize = 64;
+
+/* Evaluates an arithmetic expression whose parameters have already been
+expanded, like the inside of `$((...))`. An empty expression is zero. */
+pub fn evaluate_arithmetic(shell: &mut Shell, expression: &str) -> Result<i64, ShellError> {
+    evaluate_nested(shell, expression, 0)
+        .map_err(|message| ShellError::Arithmetic(expression.trim().to_string(), message))
+}
+
+fn evaluate_nested(shell: &mut Shell, expression: &str, depth: usize) -> Result<i64, String> {
+    if depth > MAX_RECURSION {
+        return Err("expression recursion level exceeded".to_string());
+    }
+    let tokens = tokenize_arithmetic(expression)?;
+    if tokens.is_empty() {
+        return Ok(0);
+    }
+    let mut evaluator = Evaluator {
+        shell,
+        tokens,
+        position: 0,
+        skip: false,
+        depth,
+    };
+    let value = evaluator.parse_comma()?;
+    match evaluator.tokens.get(evaluator.position) {
+        None => Ok(value),
+        Some(token) => Err(format!(
+            "syntax error in expression (error token is \"{}\")",
+            describe_arithmetic_token(token)
+        )),
+    }
+}
+
+fn tokenize_arithmetic(expression: &str) -> Result<Vec<ArithmeticToken>, String> {
+    let characters: Vec<char> = expression.chars().collect();
+    let mut tokens: Vec<ArithmeticToken> = Vec::new();
+    let mut position = 0;
+    while position < characters.len() {
+        let character = characters[position];
+        if character.is_whitespace() {
+            position += 1;
+        } else if character.is_ascii_alphanumeric() || character == '_' {
+            let length = characters[position..]
+                .iter()
+                .take_while(|c| c.is_ascii_alphanumeric() || **c == '_' || **c == '#')
+                .count();
+            let word: String = characters[position..(position + length)].iter().collect();
+            position += length;
+            if character.is_ascii_digit() {
+                tokens.push(ArithmeticToken::Number(parse_number(&word)?));
+                continue;
+            }
+            tokens.push(ArithmeticToken::Name(word));
+            if characters.get(position) == Some(&'[') {
+                let rest: String = characters[position..].iter().collect();
+                let end = find_closing_bracket(&rest).ok_or_else(|| "missing `]'".to_string())?;
+                tokens.push(ArithmeticToken::Subscript(rest[1..end].to_string()));
+                position += rest[..=end].chars().count();
+            }
+        } else {
+            let rest: String = characters[position..].iter().collect();
+            match OPERATORS
+                .iter()
+                .find(|operator| rest.starts_with(*operator))
+            {
+                Some(operator) => {
+                    tokens.push(ArithmeticToken::Operator(operator));
+                    position += operator.len();
+                }
+                None => {
+                    return Err(format!(
+                        "syntax error: invalid arithmetic operator (error token is \"{}\")",
+                        rest
+                    ))
+                }
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/* Numbers are decimal, octal with a leading 0, hexadecimal with a
+leading 0x, or written as base#digits for bases up to 36. */
+fn parse_number(word: &str) -> Result<i64, String> {
+    let (base, digits) = if let Some(position) = word.find('#') {
+        match word[..position].parse::<u32>() {
+            Ok(base) if (2..=36).contains(&base) => (base, &word[(position + 1)..]),
+            _ => {
+                return Err(format!(
+                    "invalid arithmetic base (error token is \"{}\")",
+                    word
+                ))
+            }
+        }
+    } else if word.starts_with("0x") || word.starts_with("0X") {
+        (16, &word[2..])
+    } else if word.len() > 1 && word.starts_with('0') {
+        (8, &word[1..])
+    } else {
+        (10, word)
+    };
+    let mut value: i64 = 0;
+    for character in digits.chars() {
+        match character.to_digit(base) {
+            Some(digit) => value = value.wrapping_mul(base as i64).wrapping_add(digit as i64),
+            None => {
+                return Err(format!(
+                    "value too great for base (error token is \"{}\")",
+                    word
+                ))
+            }
+        }
+    }
+    if digits.is_empty() {
+        return Err(format!("invalid number (error token is \"{}\")", word));
+    }
+    Ok(value)
+}
+
+fn describe_arithmetic_token(token: &ArithmeticToken) -> String {
+    match token {
+        ArithmeticToken::Number(number) => number.to_string(),
+        ArithmeticToken::Name(name) => name.clone(),
+        ArithmeticToken::Subscript(subscript) => format!("[{}]", subscript),
+        ArithmeticToken::Operator(operator) => operator.to_string(),
+    }
+}
+
+/* Evaluates while parsing. When `skip` is set, the expression is still
+parsed but has no side effects, which is how the unused operands of
+`&&`, `||` and `?:` are handled. */
+struct Evaluator<'a> {
+    shell: &'a mut Shell,
+    tokens: Vec<ArithmeticToken>,
+    position: usize,
+    skip: bool,
+    depth: usize,
+}
+
+impl<'a> Evaluator<'a> {
+    fn peek_operator(&self, operators: &[&str]) -> Option<&'static str> {
+        match self.tokens.get(self.position) {
+            Some(ArithmeticToken::Operator(operator)) if operators.contains(operator) => {
+                Some(operator)
+            }
+            _ => None,
+        }
+    }
+
+    fn expect_operator(&mut self, operator: &str) -> Result<(), String> {
+        if self.peek_operator(&[operator]).is_some() {
+            self.position += 1;
+            Ok(())
+        } else {
+            Err(self.unexpected())
+        }
+    }
+
+    fn unexpected(&self) -> String {
+        match self.tokens.get(self.position) {
+            Some(token) => format!(
+                "syntax error: operand expected (error token is \"{}\")",
+                describe_arithmetic_token(token)
+            ),
+            None => "syntax error: operand expected".to_string(),
+        }
+    }
+
+    fn parse_comma(&mut self) -> Result<i64, String> {
+        let mut value = self.parse_assignment()?;
+        while self.peek_operator(&[","]).is_some() {
+            self.position += 1;
+            value = self.parse_assignment()?;
+        }
+        Ok(value)
+    }
+
+    /* Takes a variable name and the subscript following it, if any. */
+    fn take_variable(&mut self) -> Option<(String, Option<String>)> {
+        let name = match self.tokens.get(self.position) {
+            Some(ArithmeticToken::Name(name)) => name.clone(),
+            _ => return None,
+        };
+        self.position += 1;
+        let subscript = match self.tokens.get(self.position) {
+            Some(ArithmeticToken::Subscript(subscript)) => Some(subscript.clone()),
+            _ => None,
+        };
+        if subscript.is_some() {
+            self.position += 1;
+        }
+        Some((name, subscript))
+    }
+
+    fn parse_assignment(&mut self) -> Result<i64, String> {
+        let start = self.position;
+        if let Some((name, subscript)) = self.take_variable() {
+            if let Some(operator) = self.peek_operator(&ASSIGNMENT_OPERATORS) {
+                self.position += 1;
+                let right = self.parse_assignment()?;
+                let value = if operator == "=" {
+                    right
+                } else {
+                    let left = self.variable_value(&name, &subscript)?;
+                    apply_binary(&operator[..(operator.len() - 1)], left, right)?
+                };
+                return self.assign(&name, &subscript, value);
+            }
+            self.position = start;
+        }
+        self.parse_conditional()
+    }
+
+    fn parse_conditional(&mut self) -> Result<i64, String> {
+        let condition = self.parse_logical_or()?;
+        if self.peek_operator(&["?"]).is_none() {
+            return Ok(condition);
+        }
+        self.position += 1;
+        let skip = self.skip;
+        self.skip = skip || condition == 0;
+        let if_true = self.parse_comma()?;
+        self.expect_operator(":")?;
+        self.skip = skip || condition != 0;
+        let if_false = self.parse_conditional()?;
+        self.skip = skip;
+        Ok(if condition != 0 { if_true } else { if_false })
+    }
+
+    fn parse_logical_or(&mut self) -> Result<i64, String> {
+        let mut value = self.parse_logical_and()?;
+        while self.peek_operator(&["||"]).is_some() {
+            self.position += 1;
+            let skip = self.skip;
+            self.skip = skip || value != 0;
+            let right = self.parse_logical_and()?;
+            self.skip = skip;
+            value = (value != 0 || right != 0) as i64;
+        }
+        Ok(value)
+    }
+
+    fn parse_logical_and(&mut self) -> Result<i64, String> {
+        let mut value = self.parse_binary(0)?;
+        while self.peek_operator(&["&&"]).is_some() {
+            self.position += 1;
+            let skip = self.skip;
+            self.skip = skip || value == 0;
+            let right = self.parse_binary(0)?;
+            self.skip = skip;
+            value = (value != 0 && right != 0) as i64;
+        }
+        Ok(value)
+    }
+
+    fn parse_binary(&mut self, level: usize) -> Result<i64, String> {
+        if level == BINARY_PRECEDENCE.len() {
+            return self.parse_power();
+        }
+        let mut value = self.parse_binary(level + 1)?;
+        while let Some(operator) = self.peek_operator(BINARY_PRECEDENCE[level]) {
+            self.position += 1;
+            let right = self.parse_binary(level + 1)?;
+            value = if self.skip {
+                0
+            } else {
+                apply_binary(operator, value, right)?
+            };
+        }
+        Ok(value)
+    }
+
+    /* `**` binds tighter than the other binary operators and groups to
+    the right. */
+    fn parse_power(&mut self) -> Result<i64, String> {
+        let base = self.parse_unary()?;
+        if self.peek_operator(&["**"]).is_none() {
+            return Ok(base);
+        }
+        self.position += 1;
+        let exponent = self.parse_power()?;
+        if self.skip {
+            return Ok(0);
+        }
+        apply_binary("**", base, exponent)
+    }
+
+    fn parse_unary(&mut self) -> Result<i64, String> {
+        if let Some(operator) = self.peek_operator(&["++", "--"]) {
+            self.position += 1;
+            let (name, subscript) = match self.take_variable() {
+                Some(variable) => variable,
+                None => return Err(self.unexpected()),
+            };
+            let value = self.variable_value(&name, &subscript)?;
+            let value = if operator == "++" {
+                value.wrapping_add(1)
+            } else {
+                value.wrapping_sub(1)
+            };
+            return self.assign(&name, &subscript, value);
+        }
+        if let Some(operator) = self.peek_operator(&["!", "~", "-", "+"]) {
+            self.position += 1;
+            let value = self.parse_unary()?;
+            return Ok(match operator {
+                "!" => (value == 0) as i64,
+                "~" => !value,
+                "-" => value.wrapping_neg(),
+                _ => value,
+            });
+        }
+        self.parse_postfix()
+    }
+
+    fn parse_postfix(&mut self) -> Result<i64, String> {
+        match self.tokens.get(self.position).cloned() {
+            Some(ArithmeticToken::Number(number)) => {
+                self.position += 1;
+                Ok(number)
+            }
+            Some(ArithmeticToken::Name(_)) => {
+                let (name, subscript) = self.take_variable().unwrap_or_default();
+                let value = self.variable_value(&name, &subscript)?;
+                if let Some(operator) = self.peek_operator(&["++", "--"]) {
+                    self.position += 1;
+                    let new_value = if operator == "++" {
+                        value.wrapping_add(1)
+                    } else {
+                        value.wrapping_sub(1)
+                    };
+                    self.assign(&name, &subscript, new_value)?;
+                }
+                Ok(value)
+            }
+            Some(ArithmeticToken::Operator("(")) => {
+                self.position += 1;
+                let value = self.parse_comma()?;
+                self.expect_operator(")")?;
+                Ok(value)
+            }
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    /* Unset and empty variables count as zero. Anything else is itself
+    evaluated as an expression, so a variable can refer to another. Array
+    elements are looked up like `${name[subscript]}`. */
+    fn variable_value(&mut self, name: &str, subscript: &Option<String>) -> Result<i64, String> {
+        if self.skip {
+            return Ok(0);
+        }
+        let value = match subscript {
+            None => self.shell.get_variable(name).map(|value| value.to_string()),
+            Some(subscript) => {
+                lookup_element(self.shell, name, Some(subscript)).map_err(|err| err.to_string())?
+            }
+        };
+        let value = match value {
+            Some(value) => value,
+            None => return Ok(0),
+        };
+        let value = value.trim();
+        if value.is_empty() {
+            return Ok(0);
+        }
+        if let Ok(number) = value.parse::<i64>() {
+            return Ok(number);
+        }
+        evaluate_nested(self.shell, value, self.depth + 1)
+    }
+
+    fn assign(
+        &mut self,
+        name: &str,
+        subscript: &Option<String>,
+        value: i64,
+    ) -> Result<i64, String> {
+        if self.skip || !is_valid_name(name) {
+            return Ok(value);
+        }
+        match subscript {
+            None => self.shell.set_variable(name, value.to_string()),
+            Some(subscript) => {
+                *element_mut(self.shell, name, subscript).map_err(|err| err.to_string())? =
+                    value.to_string()
+            }
+        }
+        Ok(value)
+    }
+}
+
+fn apply_binary(operator: &str, left: i64, right: i64) -> Result<i64, String> {
+    Ok(match operator {
+        "|" => left | right,
+        "^" => left ^ right,
+        "&" => left & right,
+        "==" => (left == right) as i64,
+        "!=" => (left != right) as i64,
+        "<" => (left < right) as i64,
+        "<=" => (left <= right) as i64,
+        ">" => (left > right) as i64,
+        ">=" => (left >= right) as i64,
+        "<<" => left.wrapping_shl(right as u32),
+        ">>" => left.wrapping_shr(right as u32),
+        "+" => left.wrapping_add(right),
+        "-" => left.wrapping_sub(right),
+        "*" => left.wrapping_mul(right),
+        "/" | "%" if right == 0 => return Err("division by 0".to_string()),
+        "/" => left.wrapping_div(right),
+        "%" => left.wrapping_rem(right),
+        "**" if right < 0 => return Err("exponent less than 0".to_string()),
+        "**" => left.wrapping_pow(right as u32),
+        _ => return Err(format!("unknown operator {}", operator)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shell::Value;
+    use std::collections::BTreeMap;
+
+    fn evaluate(shell: &mut Shell, expression: &str) -> i64 {
+        evaluate_arithmetic(shell, expression).unwrap()
+    }
+
+    #[test]
+    fn evaluate_arithmetic_test() {
+        let mut shell = Shell::new();
+        assert_eq!(evaluate(&mut shell, "1 + 2 * 3"), 7);
+        assert_eq!(evaluate(&mut shell, "(1 + 2) * 3"), 9);
+        assert_eq!(evaluate(&mut shell, "2 ** 3 ** 2"), 512);
+        assert_eq!(evaluate(&mut shell, "-7 / 2, -7 % 2"), -1);
+        assert_eq!(evaluate(&mut shell, "1 < 2 && 2 <= 1 || !0"), 1);
+        assert_eq!(evaluate(&mut shell, "0x1f + 010 + 2#101"), 44);
+        assert_eq!(evaluate(&mut shell, "~0 << 4 >> 2"), -4);
+        assert_eq!(evaluate(&mut shell, ""), 0);
+
+        assert!(evaluate_arithmetic(&mut shell, "1 / 0").is_err());
+        assert!(evaluate_arithmetic(&mut shell, "1 +").is_err());
+        assert!(evaluate_arithmetic(&mut shell, "(1").is_err());
+        assert!(evaluate_arithmetic(&mut shell, "08").is_err());
+    }
+
+    #[test]
+    fn arithmetic_variables_test() {
+        let mut shell = Shell::new();
+        assert_eq!(evaluate(&mut shell, "i = 5, i += 2, i++"), 7);
+        assert_eq!(shell.get_variable("i"), Some("8"));
+        assert_eq!(evaluate(&mut shell, "--i + unset_variable"), 7);
+
+        shell.set_variable("j", "i * 2".to_string());
+        assert_eq!(evaluate(&mut shell, "j + 1"), 15);
+
+        assert_eq!(
+            evaluate(&mut shell, "0 && (k = 1), 1 || (k = 2), i ? 3 : (k = 4)"),
+            3
+        );
+        assert_eq!(shell.get_variable("k"), None);
+
+        shell.set_variable("loop", "loop".to_string());
+        assert!(evaluate_arithmetic(&mut shell, "loop").is_err());
+    }
+
+    #[test]
+    fn arithmetic_arrays_test() {
+        let mut shell = Shell::new();
+        let elements = (0..4).map(|index| (index, ((index + 1) * 10).to_string()));
+        shell.set_value("arr", Value::Indexed(elements.collect()));
+        shell.set_variable("i", "1".to_string());
+        assert_eq!(evaluate(&mut shell, "arr[i] + arr[2]"), 50);
+        assert_eq!(evaluate(&mut shell, "arr[arr[0] / 10] + arr[-1]"), 60);
+        assert_eq!(
+            evaluate(&mut shell, "arr[i + 1] += 5, arr[5]++, ++arr[5]"),
+            2
+        );
+        assert_eq!(
+            shell.get_value("arr").unwrap().elements(),
+            vec!["10", "20", "35", "40", "2"]
+        );
+        assert_eq!(evaluate(&mut shell, "missing[3] + 0 && (arr[0] = 1)"), 0);
+        assert_eq!(shell.get_value("arr").unwrap().elements()[0], "10");
+
+        shell.set_value("h", Value::Associative(BTreeMap::new()));
+        assert_eq!(evaluate(&mut shell, "h[a] = 5, h[b]++, h[a] * 2"), 10);
+        assert_eq!(shell.get_value("h").unwrap().keys(), vec!["a", "b"]);
+
+        assert!(evaluate_arithmetic(&mut shell, "arr[1").is_err());
+        assert!(evaluate_arithmetic(&mut shell, "arr[-9]").is_err());
+    }
+}
@@ -0,0 +1,229 @@
+use expansion::expand_arithmetic;
+use expansion::expand_word_to_string;
+use expansion::expand_words;
+use parser::Assignment;
+use parser::AssignmentValue;
+use shell::Shell;
+use shell::ShellError;
+use shell::Value;
+use std::collections::BTreeMap;
+use std::slice;
+
+/* Performs an assignment that stays in the shell, as opposed to one that
+only applies to the environment of a single command. */
+pub fn assign(shell: &mut Shell, assignment: &Assignment) -> Result<(), ShellError> {
+    let name = &assignment.name;
+    match (&assignment.subscript, &assignment.value) {
+        (None, AssignmentValue::Scalar(word)) => {
+            let value = expand_word_to_string(shell, word)?;
+            let value = if assignment.append {
+                format!("{}{}", shell.get_variable(name).unwrap_or(""), value)
+            } else {
+                value
+            };
+            shell.set_variable(name, value);
+        }
+        (Some(subscript), AssignmentValue::Scalar(word)) => {
+            let value = expand_word_to_string(shell, word)?;
+            let element = element_mut(shell, name, subscript)?;
+            if assignment.append {
+                element.push_str(&value);
+            } else {
+                *element = value;
+            }
+        }
+        (_, AssignmentValue::Array(words)) => assign_array(shell, name, words, assignment.append)?,
+    }
+    Ok(())
+}
+
+/* The current value of a variable as an array. A scalar becomes the
+element at index 0. */
+fn array_value(shell: &Shell, name: &str) -> Value {
+    match shell.get_value(name) {
+        Some(Value::Scalar(value)) => {
+            let mut elements = BTreeMap::new();
+            elements.insert(0, value.clone());
+            Value::Indexed(elements)
+        }
+        Some(value) => value.clone(),
+        None => Value::Indexed(BTreeMap::new()),
+    }
+}
+
+/* The element a subscripted assignment writes to, created if needed. A
+scalar is turned into an indexed array first. */
+pub fn element_mut<'a>(
+    shell: &'a mut Shell,
+    name: &str,
+    subscript: &str,
+) -> Result<&'a mut String, ShellError> {
+    if let Some(Value::Associative(_)) = shell.get_value(name) {
+        let key = expand_word_to_string(shell, subscript)?;
+        return match shell.get_value_mut(name) {
+            Some(Value::Associative(elements)) => Ok(elements.entry(key).or_default()),
+            _ => Err(ShellError::BadArraySubscript(format!(
+                "{}[{}]",
+                name, subscript
+            ))),
+        };
+    }
+    let index = expand_arithmetic(shell, subscript)?;
+    match shell.get_value(name) {
+        Some(Value::Indexed(_)) => (),
+        _ => {
+            let array = array_value(shell, name);
+            shell.set_value(name, array);
+        }
+    }
+    match shell.get_value_mut(name) {
+        Some(Value::Indexed(elements)) => {
+            let index = resolve_index(name, subscript, index, elements)?;
+            Ok(elements.entry(index).or_default())
+        }
+        _ => Err(ShellError::BadArraySubscript(format!(
+            "{}[{}]",
+            name, subscript
+        ))),
+    }
+}
+
+/* `name=(...)` replaces the whole array, and `name+=(...)` adds to it.
+Elements written as `[subscript]=value` go to that subscript, others to
+the index after the previous element. An associative array needs a
+subscript for every element. */
+fn assign_array(
+    shell: &mut Shell,
+    name: &str,
+    words: &[String],
+    append: bool,
+) -> Result<(), ShellError> {
+    let mut array = match shell.get_value(name) {
+        _ if append => array_value(shell, name),
+        Some(Value::Associative(_)) => Value::Associative(BTreeMap::new()),
+        _ => Value::Indexed(BTreeMap::new()),
+    };
+    let mut next_index = match array {
+        Value::Indexed(ref elements) => elements.keys().next_back().map_or(0, |index| index + 1),
+        _ => 0,
+    };
+    for word in words {
+        match (split_keyed_element(word), &mut array) {
+            (Some((subscript, value)), Value::Associative(elements)) => {
+                let key = expand_word_to_string(shell, subscript)?;
+                let value = expand_word_to_string(shell, value)?;
+                elements.insert(key, value);
+            }
+            (Some((subscript, value)), Value::Indexed(elements)) => {
+                let index = expand_arithmetic(shell, subscript)?;
+                let index = resolve_index(name, subscript, index, elements)?;
+                let value = expand_word_to_string(shell, value)?;
+                elements.insert(index, value);
+                next_index = index + 1;
+            }
+            (None, Value::Indexed(elements)) => {
+                for value in expand_words(shell, slice::from_ref(word))? {
+                    elements.insert(next_index, value);
+                    next_index += 1;
+                }
+            }
+            _ => return Err(ShellError::MissingSubscript(format!("{}: {}", name, word))),
+        }
+    }
+    shell.set_value(name, array);
+    Ok(())
+}
+
+/* Splits an array element of the form `[subscript]=value`. */
+fn split_keyed_element(word: &str) -> Option<(&str, &str)> {
+    if !word.starts_with('[') {
+        return None;
+    }
+    let mut depth = 0;
+    for (position, character) in word.char_indices() {
+        match character {
+            '[' => depth += 1,
+            ']' => {
+                depth -= 1;
+                if depth == 0 {
+                    return match word[(position + 1)..].strip_prefix('=') {
+                        Some(value) => Some((&word[1..position], value)),
+                        None => None,
+                    };
+                }
+            }
+            _ => (),
+        }
+    }
+    None
+}
+
+/* Subscripts of indexed arrays are arithmetic expressions. Negative
+indexes count back from the end of the array. */
+pub fn resolve_index(
+    name: &str,
+    subscript: &str,
+    index: i64,
+    elements: &BTreeMap<usize, String>,
+) -> Result<usize, ShellError> {
+    if index >= 0 {
+        return Ok(index as usize);
+    }
+    let length = elements.keys().next_back().map_or(0, |index| index + 1) as i64;
+    if length + index < 0 {
+        return Err(ShellError::BadArraySubscript(format!(
+            "{}[{}]",
+            name, subscript
+        )));
+    }
+    Ok((length + index) as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parser::parse_assignment;
+
+    fn run(shell: &mut Shell, word: &str) {
+        assign(shell, &parse_assignment(word).unwrap()).unwrap();
+    }
+
+    fn elements(shell: &Shell, name: &str) -> Vec<String> {
+        shell.get_value(name).unwrap().elements()
+    }
+
+    #[test]
+    fn assign_test() {
+        let mut shell = Shell::new();
+        run(&mut shell, "a=(x \"y z\" [5]=five six)");
+        assert_eq!(elements(&shell, "a"), vec!["x", "y z", "five", "six"]);
+        assert_eq!(
+            shell.get_value("a").unwrap().keys(),
+            vec!["0", "1", "5", "6"]
+        );
+
+        run(&mut shell, "a[-1]+=!");
+        run(&mut shell, "a+=(seven)");
+        run(&mut shell, "a[1+1]=two");
+        assert_eq!(
+            elements(&shell, "a"),
+            vec!["x", "y z", "two", "five", "six!", "seven"]
+        );
+
+        run(&mut shell, "s=1");
+        run(&mut shell, "s+=2");
+        run(&mut shell, "s[1]=3");
+        assert_eq!(elements(&shell, "s"), vec!["12", "3"]);
+
+        shell.set_value("m", Value::Associative(BTreeMap::new()));
+        run(&mut shell, "m=([k]=v [\"a b\"]=c)");
+        run(&mut shell, "m[k]+=w");
+        assert_eq!(shell.get_value("m").unwrap().keys(), vec!["a b", "k"]);
+        assert_eq!(elements(&shell, "m"), vec!["c", "vw"]);
+
+        let assignment = parse_assignment("m=(v)").unwrap();
+        assert!(assign(&mut shell, &assignment).is_err());
+        let assignment = parse_assignment("e[-1]=x").unwrap();
+        assert!(assign(&mut shell, &assignment).is_err());
+    }
+}
@@ -0,0 +1,738 @@
+use arithmetic::evaluate_arithmetic;
+use assignment::resolve_index;
+use conditional::evaluate_test;
+use directory::{builtin_cd, builtin_dirs, builtin_popd, builtin_pushd, builtin_pwd};
+use executor::has_stopped_children;
+use executor::{execute_string, source_script};
+use hash::{builtin_hash, DEFAULT_PATH};
+use lookup::{builtin_builtin, builtin_command, builtin_exec, builtin_type, builtin_which};
+use options::{builtin_set, builtin_shopt};
+use output::{builtin_echo, builtin_printf};
+use parser::is_valid_name;
+use read::builtin_read;
+use shell::describe_io_error;
+use shell::ControlFlow;
+use shell::Shell;
+use shell::ShellError;
+use shell::Value;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::fs;
+use std::io::{Read, Write};
+use std::rc::Rc;
+use trap::builtin_trap;
+
+/* The standard streams of a builtin. Redirections of the command have
+already been applied to them. */
+pub struct Streams<'a> {
+    pub stdin: &'a mut dyn Read,
+    pub stdout: &'a mut dyn Write,
+    pub stderr: &'a mut dyn Write,
+}
+
+impl<'a> Streams<'a> {
+    /* Reports an error the way the shell reports its own. There is
+    nowhere left to report a failure to write it. */
+    pub fn error<T: Display>(&mut self, message: T) {
+        let _ = writeln!(self.stderr, "sheller: {}", message);
+    }
+}
+
+/* A command that runs inside the shell process itself instead of being
+forked and executed, so that it can change the state of the shell. It
+returns the exit status of the command. */
+pub trait Builtin {
+    fn run(&self, shell: &mut Shell, arguments: &[String], streams: &mut Streams) -> i32;
+}
+
+impl<F> Builtin for F
+where
+    F: Fn(&mut Shell, &[String], &mut Streams) -> i32,
+{
+    fn run(&self, shell: &mut Shell, arguments: &[String], streams: &mut Streams) -> i32 {
+        self(shell, arguments, streams)
+    }
+}
+
+/* The builtins known to a shell, by name. It starts out with the ones
+sheller provides, and programs embedding sheller can add their own or
+replace them. */
+pub struct Builtins {
+    builtins: HashMap<String, Rc<dyn Builtin>>,
+}
+
+impl Builtins {
+    pub fn new() -> Builtins {
+        let mut builtins = Builtins {
+            builtins: HashMap::new(),
+        };
+        builtins.register(":", builtin_true);
+        builtins.register("true", builtin_true);
+        builtins.register("false", builtin_false);
+        builtins.register("break", builtin_break);
+        builtins.register("continue", builtin_continue);
+        builtins.register("declare", builtin_declare);
+        builtins.register("typeset", builtin_declare);
+        builtins.register("local", builtin_local);
+        builtins.register("return", builtin_return);
+        builtins.register("source", builtin_source);
+        builtins.register(".", builtin_source);
+        builtins.register("eval", builtin_eval);
+        builtins.register("exit", builtin_exit);
+        builtins.register("test", builtin_test);
+        builtins.register("[", builtin_test);
+        builtins.register("alias", builtin_alias);
+        builtins.register("unalias", builtin_unalias);
+        builtins.register("export", builtin_export);
+        builtins.register("unset", builtin_unset);
+        builtins.register("cd", builtin_cd);
+        builtins.register("pwd", builtin_pwd);
+        builtins.register("pushd", builtin_pushd);
+        builtins.register("popd", builtin_popd);
+        builtins.register("dirs", builtin_dirs);
+        builtins.register("hash", builtin_hash);
+        builtins.register("type", builtin_type);
+        builtins.register("command", builtin_command);
+        builtins.register("builtin", builtin_builtin);
+        builtins.register("which", builtin_which);
+        builtins.register("exec", builtin_exec);
+        builtins.register("trap", builtin_trap);
+        builtins.register("set", builtin_set);
+        builtins.register("shopt", builtin_shopt);
+        builtins.register("read", builtin_read);
+        builtins.register("echo", builtin_echo);
+        builtins.register("printf", builtin_printf);
+        builtins
+    }
+
+    pub fn register<B: Builtin + 'static>(&mut self, name: &str, builtin: B) {
+        self.builtins.insert(name.to_string(), Rc::new(builtin));
+    }
+
+    pub fn unregister(&mut self, name: &str) -> bool {
+        self.builtins.remove(name).is_some()
+    }
+
+    pub fn get(&self, name: &str) -> Option<Rc<dyn Builtin>> {
+        self.builtins.get(name).cloned()
+    }
+}
+
+impl Default for Builtins {
+    fn default() -> Builtins {
+        Builtins::new()
+    }
+}
+
+fn builtin_true(_shell: &mut Shell, _arguments: &[String], _streams: &mut Streams) -> i32 {
+    0
+}
+
+fn builtin_false(_shell: &mut Shell, _arguments: &[String], _streams: &mut Streams) -> i32 {
+    1
+}
+
+fn builtin_break(shell: &mut Shell, arguments: &[String], streams: &mut Streams) -> i32 {
+    request_loop_control(shell, arguments, streams, ControlFlow::Break)
+}
+
+fn builtin_continue(shell: &mut Shell, arguments: &[String], streams: &mut Streams) -> i32 {
+    request_loop_control(shell, arguments, streams, ControlFlow::Continue)
+}
+
+/* `break` and `continue` only record what should happen. The enclosing
+loops act on it once the current command has finished. */
+fn request_loop_control(
+    shell: &mut Shell,
+    arguments: &[String],
+    streams: &mut Streams,
+    control_flow: fn(usize) -> ControlFlow,
+) -> i32 {
+    let count = match arguments.get(1) {
+        None => 1,
+        Some(argument) => match argument.parse::<usize>() {
+            Ok(count) if count > 0 => count,
+            _ => {
+                streams.error(format!(
+                    "{}: {}: loop count out of range",
+                    arguments[0], argument
+                ));
+                return 1;
+            }
+        },
+    };
+    if shell.loop_depth == 0 {
+        streams.error(format!(
+            "{}: only meaningful in a `for', `while', or `until' loop",
+            arguments[0]
+        ));
+        return 0;
+    }
+    shell.control_flow = control_flow(count.min(shell.loop_depth));
+    0
+}
+
+/* `local name[=value]...` gives the running function its own copy of
+the variables, which goes away when the function returns. Without a
+value the local variable starts out unset. */
+fn builtin_local(shell: &mut Shell, arguments: &[String], streams: &mut Streams) -> i32 {
+    if shell.function_depth == 0 {
+        streams.error("local: can only be used in a function");
+        return 1;
+    }
+    declare_variables(shell, arguments, streams, true)
+}
+
+/* `declare [-aAg] name[=value]...` declares variables, as indexed arrays
+with -a or associative arrays with -A. Inside a function they are local
+unless -g is given. */
+fn builtin_declare(shell: &mut Shell, arguments: &[String], streams: &mut Streams) -> i32 {
+    let is_local = shell.function_depth > 0;
+    declare_variables(shell, arguments, streams, is_local)
+}
+
+fn declare_variables(
+    shell: &mut Shell,
+    arguments: &[String],
+    streams: &mut Streams,
+    is_local: bool,
+) -> i32 {
+    let mut kind = None;
+    let mut is_global = false;
+    let mut position = 1;
+    while let Some(argument) = arguments.get(position) {
+        if !argument.starts_with('-') || argument == "-" {
+            break;
+        }
+        position += 1;
+        if argument == "--" {
+            break;
+        }
+        for option in argument[1..].chars() {
+            match option {
+                'a' | 'A' => kind = Some(option),
+                'g' => is_global = true,
+                _ => {
+                    streams.error(format!("{}: -{}: invalid option", arguments[0], option));
+                    return 2;
+                }
+            }
+        }
+    }
+    let mut status = 0;
+    for argument in &arguments[position..] {
+        let (name, value) = match argument.find('=') {
+            Some(position) => (&argument[..position], Some(&argument[(position + 1)..])),
+            None => (argument.as_str(), None),
+        };
+        if !is_valid_name(name) {
+            streams.error(format!(
+                "{}: `{}': not a valid identifier",
+                arguments[0], argument
+            ));
+            status = 1;
+            continue;
+        }
+        if is_local && !is_global {
+            shell.make_local(name);
+            shell.unset_variable(name);
+        }
+        match (kind, shell.get_value(name).cloned()) {
+            (Some('A'), Some(Value::Associative(_))) | (Some('a'), Some(Value::Indexed(_))) => (),
+            (Some('A'), None) => shell.set_value(name, Value::Associative(BTreeMap::new())),
+            (Some('a'), None) => shell.set_value(name, Value::Indexed(BTreeMap::new())),
+            (Some('a'), Some(Value::Scalar(value))) => {
+                let mut elements = BTreeMap::new();
+                elements.insert(0, value);
+                shell.set_value(name, Value::Indexed(elements));
+            }
+            (Some(_), Some(_)) => {
+                streams.error(format!(
+                    "{}: {}: cannot convert between array types",
+                    arguments[0], name
+                ));
+                status = 1;
+                continue;
+            }
+            (_, _) => (),
+        }
+        if let Some(value) = value {
+            shell.set_variable(name, value.to_string());
+        }
+    }
+    status
+}
+
+/* `alias [name[=value]...]` defines aliases, or prints the ones named.
+Without names it prints all of them in a form that can be run again. */
+fn builtin_alias(shell: &mut Shell, arguments: &[String], streams: &mut Streams) -> i32 {
+    let names: Vec<&String> = arguments[1..]
+        .iter()
+        .skip_while(|argument| *argument == "-p" || *argument == "--")
+        .collect();
+    if names.is_empty() {
+        for (name, value) in &shell.aliases {
+            let _ = writeln!(streams.stdout, "alias {}={}", name, single_quote(value));
+        }
+        return 0;
+    }
+    let mut status = 0;
+    for argument in names {
+        match argument.find('=') {
+            Some(position) => {
+                let name = &argument[..position];
+                if name.is_empty() || name.contains(&['/', '$', '`', '\\', '\'', '"'][..]) {
+                    streams.error(format!("alias: `{}': invalid alias name", name));
+                    status = 1;
+                    continue;
+                }
+                shell
+                    .aliases
+                    .insert(name.to_string(), argument[(position + 1)..].to_string());
+            }
+            None => match shell.aliases.get(argument.as_str()) {
+                Some(value) => {
+                    let _ = writeln!(streams.stdout, "alias {}={}", argument, single_quote(value));
+                }
+                None => {
+                    streams.error(format!("alias: {}: not found", argument));
+                    status = 1;
+                }
+            },
+        }
+    }
+    status
+}
+
+/* `unalias [-a] name...` removes the aliases, or all of them with -a. */
+fn builtin_unalias(shell: &mut Shell, arguments: &[String], streams: &mut Streams) -> i32 {
+    if arguments.get(1).map(|argument| argument.as_str()) == Some("-a") {
+        shell.aliases.clear();
+        return 0;
+    }
+    if arguments.len() < 2 {
+        let _ = writeln!(
+            streams.stderr,
+            "unalias: usage: unalias [-a] name [name ...]"
+        );
+        return 2;
+    }
+    let mut status = 0;
+    for name in &arguments[1..] {
+        if shell.aliases.remove(name).is_none() {
+            streams.error(format!("unalias: {}: not found", name));
+            status = 1;
+        }
+    }
+    status
+}
+
+/* Quotes a value in single quotes, so that it reads back unchanged. */
+pub fn single_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/* `export [-n] [-p] name[=value]...` puts variables in the environment
+of the commands the shell runs, or takes them out of it with -n.
+Without names it lists the exported variables. */
+fn builtin_export(shell: &mut Shell, arguments: &[String], streams: &mut Streams) -> i32 {
+    let mut unexport = false;
+    let mut position = 1;
+    while let Some(argument) = arguments.get(position) {
+        if !argument.starts_with('-') || argument == "-" {
+            break;
+        }
+        position += 1;
+        if argument == "--" {
+            break;
+        }
+        for option in argument[1..].chars() {
+            match option {
+                'n' => unexport = true,
+                'p' => (),
+                _ => {
+                    streams.error(format!("export: -{}: invalid option", option));
+                    return 2;
+                }
+            }
+        }
+    }
+    if position == arguments.len() {
+        for name in shell.exported_names() {
+            let _ = match shell.get_variable(&name) {
+                Some(value) => writeln!(
+                    streams.stdout,
+                    "declare -x {}=\"{}\"",
+                    name,
+                    quote_value(value)
+                ),
+                None => writeln!(streams.stdout, "declare -x {}", name),
+            };
+        }
+        return 0;
+    }
+    let mut status = 0;
+    for argument in &arguments[position..] {
+        let (name, value) = match argument.find('=') {
+            Some(position) => (&argument[..position], Some(&argument[(position + 1)..])),
+            None => (argument.as_str(), None),
+        };
+        if !is_valid_name(name) {
+            streams.error(format!("export: `{}': not a valid identifier", argument));
+            status = 1;
+            continue;
+        }
+        if let Some(value) = value {
+            shell.set_variable(name, value.to_string());
+        }
+        shell.set_exported(name, !unexport);
+    }
+    status
+}
+
+/* Escapes a value for the inside of double quotes. */
+fn quote_value(value: &str) -> String {
+    let mut quoted = String::new();
+    for character in value.chars() {
+        if "\\\"$`".contains(character) {
+            quoted.push('\\');
+        }
+        quoted.push(character);
+    }
+    quoted
+}
+
+/* `unset [-f|-v] name...` removes variables with -v and functions with
+-f. Without either it removes the variable, or the function if there
+is no such variable. `name[subscript]` removes a single element of an
+array. */
+fn builtin_unset(shell: &mut Shell, arguments: &[String], streams: &mut Streams) -> i32 {
+    let mut kind = None;
+    let mut position = 1;
+    while let Some(argument) = arguments.get(position) {
+        if !argument.starts_with('-') || argument == "-" {
+            break;
+        }
+        position += 1;
+        if argument == "--" {
+            break;
+        }
+        for option in argument[1..].chars() {
+            match option {
+                'f' | 'v' => kind = Some(option),
+                _ => {
+                    streams.error(format!("unset: -{}: invalid option", option));
+                    return 2;
+                }
+            }
+        }
+    }
+    let mut status = 0;
+    for argument in &arguments[position..] {
+        if kind != Some('f') {
+            if let Some(open) = argument.find('[').filter(|_| argument.ends_with(']')) {
+                let (name, subscript) = (
+                    &argument[..open],
+                    &argument[(open + 1)..(argument.len() - 1)],
+                );
+                if is_valid_name(name) {
+                    if let Err(err) = unset_element(shell, name, subscript) {
+                        streams.error(err);
+                        status = 1;
+                    }
+                    continue;
+                }
+            }
+        }
+        if !is_valid_name(argument) {
+            streams.error(format!("unset: `{}': not a valid identifier", argument));
+            status = 1;
+            continue;
+        }
+        match kind {
+            Some('f') => {
+                shell.functions.remove(argument);
+            }
+            Some(_) => shell.unset_variable(argument),
+            None if shell.get_value(argument).is_some() || shell.is_exported(argument) => {
+                shell.unset_variable(argument)
+            }
+            None => {
+                shell.functions.remove(argument);
+            }
+        }
+    }
+    status
+}
+
+/* Removes a single element of an array, or the whole array for `@` and
+`*`. */
+fn unset_element(shell: &mut Shell, name: &str, subscript: &str) -> Result<(), ShellError> {
+    if subscript == "@" || subscript == "*" {
+        shell.unset_variable(name);
+        return Ok(());
+    }
+    let index = match shell.get_value(name) {
+        Some(Value::Indexed(_)) | Some(Value::Scalar(_)) => {
+            Some(evaluate_arithmetic(shell, subscript)?)
+        }
+        _ => None,
+    };
+    match shell.get_value_mut(name) {
+        Some(Value::Associative(elements)) => {
+            elements.remove(subscript);
+        }
+        Some(Value::Indexed(elements)) => {
+            let index = resolve_index(name, subscript, index.unwrap_or(0), elements)?;
+            elements.remove(&index);
+        }
+        Some(Value::Scalar(_)) if index == Some(0) => shell.unset_variable(name),
+        _ => (),
+    }
+    Ok(())
+}
+
+/* `return [n]` ends the running function with status n, or with the
+status of the last command. */
+fn builtin_return(shell: &mut Shell, arguments: &[String], streams: &mut Streams) -> i32 {
+    let status = match arguments.get(1) {
+        None => shell.last_status,
+        Some(argument) => match argument.parse::<i64>() {
+            Ok(status) => (status & 0xff) as i32,
+            Err(_) => {
+                streams.error(format!("return: {}: numeric argument required", argument));
+                2
+            }
+        },
+    };
+    if shell.function_depth == 0 && shell.source_depth == 0 {
+        streams.error("return: can only `return' from a function or sourced script");
+        return 1;
+    }
+    shell.control_flow = ControlFlow::Return;
+    status
+}
+
+/* Finds the file `source` reads. A name without a slash is looked up in
+PATH, where the file doesn't have to be executable, and then in the
+current directory. */
+fn find_source_file(shell: &Shell, name: &str) -> String {
+    if name.contains('/') {
+        return name.to_string();
+    }
+    let path_variable = shell.get_variable("PATH").unwrap_or(DEFAULT_PATH);
+    path_variable
+        .split(':')
+        .filter(|directory| !directory.is_empty())
+        .map(|directory| format!("{}/{}", directory.trim_end_matches('/'), name))
+        .find(|path| {
+            fs::metadata(path)
+                .map(|metadata| metadata.is_file())
+                .unwrap_or(false)
+        })
+        .unwrap_or_else(|| name.to_string())
+}
+
+/* `source filename [argument...]`, or `. filename [argument...]`, reads
+and runs the commands of a file in the current shell. The arguments
+are the positional parameters while it runs. */
+fn builtin_source(shell: &mut Shell, arguments: &[String], streams: &mut Streams) -> i32 {
+    let operands = match arguments.get(1) {
+        Some(argument) if argument == "--" => &arguments[2..],
+        _ => &arguments[1..],
+    };
+    let name = match operands.first() {
+        Some(name) => name,
+        None => {
+            streams.error(format!("{}: filename argument required", arguments[0]));
+            let _ = writeln!(
+                streams.stderr,
+                "{}: usage: {} filename [arguments]",
+                arguments[0], arguments[0]
+            );
+            return 2;
+        }
+    };
+    let path = find_source_file(shell, name);
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(err) => {
+            streams.error(format!("{}: {}", name, describe_io_error(&err)));
+            return 1;
+        }
+    };
+    let _ = streams.stdout.flush();
+    source_script(shell, &content, &operands[1..])
+}
+
+/* `eval [argument...]` joins its arguments with spaces and runs the
+result as shell commands. */
+fn builtin_eval(shell: &mut Shell, arguments: &[String], streams: &mut Streams) -> i32 {
+    let operands = match arguments.get(1) {
+        Some(argument) if argument == "--" => &arguments[2..],
+        _ => &arguments[1..],
+    };
+    if operands.is_empty() {
+        return 0;
+    }
+    let _ = streams.stdout.flush();
+    execute_string(shell, &operands.join(" "))
+}
+
+/* `exit [n]` ends the shell with status n, or with the status of the
+last command. The first attempt while jobs are stopped only warns about
+them. */
+fn builtin_exit(shell: &mut Shell, arguments: &[String], streams: &mut Streams) -> i32 {
+    if arguments.len() > 2 {
+        streams.error("exit: too many arguments");
+        return 1;
+    }
+    let status = match arguments.get(1) {
+        None => shell.last_status,
+        Some(argument) => match argument.trim().parse::<i64>() {
+            Ok(status) => (status & 0xff) as i32,
+            Err(_) => {
+                streams.error(format!("exit: {}: numeric argument required", argument));
+                2
+            }
+        },
+    };
+    if !shell.exit_warned && has_stopped_children(shell) {
+        let _ = writeln!(streams.stderr, "There are stopped jobs.");
+        shell.exit_warned = true;
+        return 1;
+    }
+    shell.control_flow = ControlFlow::Exit(status);
+    status
+}
+
+/* `test expression` and `[ expression ]` exit with 0 when the expression
+is true, 1 when it is false and 2 when it can't be evaluated. */
+fn builtin_test(shell: &mut Shell, arguments: &[String], streams: &mut Streams) -> i32 {
+    let mut arguments: Vec<&str> = arguments.iter().map(|argument| argument.as_str()).collect();
+    if arguments[0] == "[" {
+        if arguments.last() != Some(&"]") {
+            streams.error("[: missing `]'");
+            return 2;
+        }
+        arguments.pop();
+    }
+    match evaluate_test(shell, &arguments[1..]) {
+        Ok(true) => 0,
+        Ok(false) => 1,
+        Err(message) => {
+            streams.error(format!("{}: {}", arguments[0], message));
+            2
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate nix;
+
+    use self::nix::sys::signal::{kill, Signal};
+    use self::nix::unistd::Pid;
+    use super::*;
+    use std::process;
+    use std::thread;
+    use std::time::Duration;
+
+    fn run(shell: &mut Shell, arguments: Vec<&str>) -> (i32, String, String) {
+        let arguments: Vec<String> = arguments
+            .iter()
+            .map(|argument| argument.to_string())
+            .collect();
+        let builtin = shell.builtins.get(&arguments[0]).unwrap();
+        let mut stdout: Vec<u8> = Vec::new();
+        let mut stderr: Vec<u8> = Vec::new();
+        let status = builtin.run(
+            shell,
+            &arguments,
+            &mut Streams {
+                stdin: &mut "input".as_bytes(),
+                stdout: &mut stdout,
+                stderr: &mut stderr,
+            },
+        );
+        let output = |bytes: Vec<u8>| String::from_utf8(bytes).unwrap();
+        (status, output(stdout), output(stderr))
+    }
+
+    #[test]
+    fn register_builtin_test() {
+        let mut shell = Shell::new();
+        shell.builtins.register(
+            "greet",
+            |shell: &mut Shell, arguments: &[String], streams: &mut Streams| {
+                let mut input = String::new();
+                let _ = streams.stdin.read_to_string(&mut input);
+                let _ = writeln!(streams.stdout, "{} {}", input, arguments[1]);
+                shell.set_variable("greeted", arguments[1].clone());
+                0
+            },
+        );
+        assert_eq!(
+            run(&mut shell, vec!["greet", "you"]),
+            (0, "input you\n".to_string(), String::new())
+        );
+        assert_eq!(shell.get_variable("greeted"), Some("you"));
+
+        let expected = (2, String::new(), "sheller: [: missing `]'\n".to_string());
+        assert_eq!(run(&mut shell, vec!["[", "x"]), expected);
+
+        assert!(shell.builtins.unregister("greet"));
+        assert!(shell.builtins.get("greet").is_none());
+    }
+
+    #[test]
+    fn eval_test() {
+        let mut shell = Shell::new();
+        assert_eq!(
+            run(&mut shell, vec!["eval", "x=1;", "y=$((x", "+", "1))"]).0,
+            0
+        );
+        assert_eq!(shell.get_variable("y"), Some("2"));
+        assert_eq!(run(&mut shell, vec!["eval", "false"]).0, 1);
+        assert_eq!(run(&mut shell, vec!["eval"]).0, 0);
+    }
+
+    #[test]
+    fn exit_test() {
+        let mut shell = Shell::new();
+        assert_eq!(run(&mut shell, vec!["exit", "3"]).0, 3);
+        assert!(shell.control_flow == ControlFlow::Exit(3));
+        assert_eq!(run(&mut shell, vec!["exit", "300"]).0, 44);
+        shell.last_status = 7;
+        assert_eq!(run(&mut shell, vec!["exit"]).0, 7);
+        assert!(shell.control_flow == ControlFlow::Exit(7));
+        assert_eq!(run(&mut shell, vec!["exit", "x"]).0, 2);
+    }
+
+    #[test]
+    fn exit_stopped_jobs_test() {
+        let mut shell = Shell::new();
+        let mut child = process::Command::new("sleep").arg("10").spawn().unwrap();
+        let pid = Pid::from_raw(child.id() as i32);
+        shell.unreaped_children.push(pid);
+        kill(pid, Signal::SIGSTOP).unwrap();
+        for _ in 0..100 {
+            if has_stopped_children(&mut shell) {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        let warning = (1, String::new(), "There are stopped jobs.\n".to_string());
+        assert_eq!(run(&mut shell, vec!["exit", "5"]), warning);
+        assert!(shell.control_flow == ControlFlow::Normal);
+        shell.exit_warned = false;
+        assert_eq!(run(&mut shell, vec!["exit", "5"]), warning);
+        assert_eq!(run(&mut shell, vec!["exit", "5"]).0, 5);
+        assert!(shell.control_flow == ControlFlow::Exit(5));
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+}
@@ -0,0 +1,284 @@
+extern crate nix;
+
+use conditional::nix::libc;
+use conditional::nix::unistd::{getegid, geteuid};
+use shell::Shell;
+use std::ffi::CString;
+use std::fs;
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::fs::MetadataExt;
+use std::time::SystemTime;
+
+/* The operators shared by `[[ ... ]]` and `test`. They only differ in
+how their operands are expanded and how syntax errors are reported. */
+const UNARY_OPERATORS: [&str; 24] = [
+    "-a", "-b", "-c", "-d", "-e", "-f", "-g", "-h", "-k", "-p", "-r", "-s", "-t", "-u", "-w", "-x",
+    "-G", "-L", "-N", "-O", "-S", "-z", "-n", "-v",
+];
+
+const BINARY_OPERATORS: [&str; 14] = [
+    "=", "==", "!=", "<", ">", "-eq", "-ne", "-lt", "-le", "-gt", "-ge", "-nt", "-ot", "-ef",
+];
+
+pub fn is_unary_operator(operator: &str) -> bool {
+    UNARY_OPERATORS.contains(&operator)
+}
+
+pub fn is_binary_operator(operator: &str) -> bool {
+    BINARY_OPERATORS.contains(&operator)
+}
+
+pub fn is_integer_operator(operator: &str) -> bool {
+    ["-eq", "-ne", "-lt", "-le", "-gt", "-ge"].contains(&operator)
+}
+
+pub fn test_unary(shell: &Shell, operator: &str, operand: &str) -> bool {
+    match operator {
+        "-z" => operand.is_empty(),
+        "-n" => !operand.is_empty(),
+        "-v" => shell.get_value(operand).is_some(),
+        "-t" => match operand.trim().parse::<i32>() {
+            Ok(fd) => unsafe { libc::isatty(fd) == 1 },
+            Err(_) => false,
+        },
+        "-L" | "-h" => fs::symlink_metadata(operand)
+            .map(|metadata| metadata.file_type().is_symlink())
+            .unwrap_or(false),
+        "-r" => is_accessible(operand, libc::R_OK),
+        "-w" => is_accessible(operand, libc::W_OK),
+        "-x" => is_accessible(operand, libc::X_OK),
+        _ => {
+            let metadata = match fs::metadata(operand) {
+                Ok(metadata) => metadata,
+                Err(_) => return false,
+            };
+            let file_type = metadata.file_type();
+            match operator {
+                "-a" | "-e" => true,
+                "-f" => file_type.is_file(),
+                "-d" => file_type.is_dir(),
+                "-b" => file_type.is_block_device(),
+                "-c" => file_type.is_char_device(),
+                "-p" => file_type.is_fifo(),
+                "-S" => file_type.is_socket(),
+                "-s" => metadata.len() > 0,
+                "-u" => metadata.mode() & 0o4000 != 0,
+                "-g" => metadata.mode() & 0o2000 != 0,
+                "-k" => metadata.mode() & 0o1000 != 0,
+                "-O" => metadata.uid() == libc::uid_t::from(geteuid()),
+                "-G" => metadata.gid() == libc::gid_t::from(getegid()),
+                "-N" => metadata.mtime() > metadata.atime(),
+                _ => false,
+            }
+        }
+    }
+}
+
+/* Integer comparisons need integers, which is the only way a binary
+test can fail. */
+pub fn test_binary(left: &str, operator: &str, right: &str) -> Result<bool, String> {
+    if is_integer_operator(operator) {
+        let parse = |operand: &str| {
+            operand
+                .trim()
+                .parse::<i64>()
+                .map_err(|_| format!("{}: integer expression expected", operand))
+        };
+        return Ok(compare_integers(parse(left)?, operator, parse(right)?));
+    }
+    Ok(match operator {
+        "=" | "==" => left == right,
+        "!=" => left != right,
+        "<" => left < right,
+        ">" => left > right,
+        "-nt" => match (modified(left), modified(right)) {
+            (Some(left), Some(right)) => left > right,
+            (left, right) => left.is_some() && right.is_none(),
+        },
+        "-ot" => match (modified(left), modified(right)) {
+            (Some(left), Some(right)) => left < right,
+            (left, right) => left.is_none() && right.is_some(),
+        },
+        "-ef" => match (fs::metadata(left), fs::metadata(right)) {
+            (Ok(left), Ok(right)) => left.dev() == right.dev() && left.ino() == right.ino(),
+            _ => false,
+        },
+        _ => false,
+    })
+}
+
+pub fn compare_integers(left: i64, operator: &str, right: i64) -> bool {
+    match operator {
+        "-eq" => left == right,
+        "-ne" => left != right,
+        "-lt" => left < right,
+        "-le" => left <= right,
+        "-gt" => left > right,
+        "-ge" => left >= right,
+        _ => false,
+    }
+}
+
+/* Evaluates the arguments of `test`. Up to four arguments are read the
+way POSIX decides from their number, which keeps operands that look
+like operators from being mistaken for them. Longer expressions are
+parsed with `!`, `-a`, `-o` and parentheses, where `-a` binds tighter
+than `-o`. */
+pub fn evaluate_test(shell: &Shell, arguments: &[&str]) -> Result<bool, String> {
+    let count = arguments.len();
+    let first = arguments.first().cloned().unwrap_or("");
+    match count {
+        0 => Ok(false),
+        1 => Ok(!first.is_empty()),
+        2 if first == "!" => evaluate_test(shell, &arguments[1..]).map(|result| !result),
+        2 if is_unary_operator(first) => Ok(test_unary(shell, first, arguments[1])),
+        2 => Err(format!("{}: unary operator expected", first)),
+        3 if is_binary_operator(arguments[1]) => test_binary(first, arguments[1], arguments[2]),
+        3 if arguments[1] == "-a" => Ok(!first.is_empty() && !arguments[2].is_empty()),
+        3 if arguments[1] == "-o" => Ok(!first.is_empty() || !arguments[2].is_empty()),
+        3 | 4 if first == "!" => evaluate_test(shell, &arguments[1..]).map(|result| !result),
+        3 | 4 if first == "(" && arguments[count - 1] == ")" => {
+            evaluate_test(shell, &arguments[1..(count - 1)])
+        }
+        _ => {
+            let mut parser = TestParser {
+                shell,
+                arguments,
+                position: 0,
+            };
+            let result = parser.parse_or()?;
+            match arguments.get(parser.position) {
+                Some(_) => Err("too many arguments".to_string()),
+                None => Ok(result),
+            }
+        }
+    }
+}
+
+struct TestParser<'a> {
+    shell: &'a Shell,
+    arguments: &'a [&'a str],
+    position: usize,
+}
+
+impl<'a> TestParser<'a> {
+    fn peek(&self) -> Option<&'a str> {
+        self.arguments.get(self.position).cloned()
+    }
+
+    fn next(&mut self) -> Option<&'a str> {
+        let argument = self.peek();
+        self.position += 1;
+        argument
+    }
+
+    fn parse_or(&mut self) -> Result<bool, String> {
+        let mut result = self.parse_and()?;
+        while self.peek() == Some("-o") {
+            self.position += 1;
+            let right = self.parse_and()?;
+            result = result || right;
+        }
+        Ok(result)
+    }
+
+    fn parse_and(&mut self) -> Result<bool, String> {
+        let mut result = self.parse_not()?;
+        while self.peek() == Some("-a") {
+            self.position += 1;
+            let right = self.parse_not()?;
+            result = result && right;
+        }
+        Ok(result)
+    }
+
+    fn parse_not(&mut self) -> Result<bool, String> {
+        if self.peek() == Some("!") {
+            self.position += 1;
+            return self.parse_not().map(|result| !result);
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<bool, String> {
+        let argument = match self.next() {
+            Some(argument) => argument,
+            None => return Err("argument expected".to_string()),
+        };
+        if argument == "(" {
+            let result = self.parse_or()?;
+            return match self.next() {
+                Some(")") => Ok(result),
+                _ => Err("`)' expected".to_string()),
+            };
+        }
+        let next = self.arguments.get(self.position + 1);
+        match self.peek() {
+            Some(operator) if is_binary_operator(operator) && next.is_some() => {
+                self.position += 2;
+                test_binary(argument, operator, next.unwrap_or(&""))
+            }
+            Some(operand) if is_unary_operator(argument) => {
+                self.position += 1;
+                Ok(test_unary(self.shell, argument, operand))
+            }
+            _ => Ok(!argument.is_empty()),
+        }
+    }
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/* Permissions are checked with access(2), so that they are judged for
+the real user the same way the kernel would. */
+pub fn is_accessible(path: &str, mode: libc::c_int) -> bool {
+    match CString::new(path) {
+        Ok(path) => unsafe { libc::access(path.as_ptr(), mode) == 0 },
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_binary_test() {
+        assert_eq!(test_binary("10", "-gt", " 9"), Ok(true));
+        assert_eq!(test_binary("a", "<", "b"), Ok(true));
+        assert_eq!(test_binary("a", "!=", "a"), Ok(false));
+        assert!(test_binary("x", "-eq", "1").is_err());
+        assert_eq!(test_binary("/", "-ef", "/."), Ok(true));
+        assert_eq!(test_binary("/", "-nt", "/nonexistent"), Ok(true));
+        let shell = Shell::new();
+        assert!(test_unary(&shell, "-d", "/") && !test_unary(&shell, "-f", "/"));
+        assert!(test_unary(&shell, "-z", "") && test_unary(&shell, "-c", "/dev/null"));
+    }
+
+    #[test]
+    fn evaluate_test_test() {
+        let shell = Shell::new();
+        let test = |arguments: &str| {
+            let arguments: Vec<&str> = arguments.split(' ').filter(|a| !a.is_empty()).collect();
+            evaluate_test(&shell, &arguments)
+        };
+        assert_eq!(test(""), Ok(false));
+        assert_eq!(test("-n"), Ok(true));
+        assert_eq!(test("! -z"), Ok(false));
+        assert_eq!(test("-z -n"), Ok(false));
+        assert_eq!(test("-a = -a"), Ok(true));
+        assert_eq!(test("! = !"), Ok(true));
+        assert_eq!(test("( -n )"), Ok(true));
+        assert_eq!(test("! ( x = y )"), Ok(true));
+        assert_eq!(test("a = b -o 1 -lt 2 -a -d /"), Ok(true));
+        assert_eq!(test("a = a -a ! ( -e /nonexistent -o x )"), Ok(false));
+        assert!(test("x y").is_err());
+        assert!(test("a b c d e").is_err());
+        assert!(test("( a = a").is_err());
+        assert!(test("1 -eq x").is_err());
+    }
+}
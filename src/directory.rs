@@ -0,0 +1,451 @@
+extern crate nix;
+
+use builtins::Streams;
+use directory::nix::unistd::{chdir, getcwd};
+use shell::describe_nix_error;
+use shell::Shell;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+/* The shell keeps track of the current directory in PWD as the path it
+was reached by, with symbolic links left unresolved. A PWD inherited
+from the environment is only trusted if it really names the current
+directory. */
+pub fn initial_directory(pwd: Option<&str>) -> Option<String> {
+    match pwd {
+        Some(pwd) if pwd.starts_with('/') && is_same_file(pwd, ".") => Some(pwd.to_string()),
+        _ => physical_directory(),
+    }
+}
+
+fn physical_directory() -> Option<String> {
+    getcwd()
+        .ok()
+        .map(|path| path.to_string_lossy().into_owned())
+}
+
+fn is_same_file(first: &str, second: &str) -> bool {
+    match (fs::metadata(first), fs::metadata(second)) {
+        (Ok(first), Ok(second)) => first.dev() == second.dev() && first.ino() == second.ino(),
+        _ => false,
+    }
+}
+
+/* Resolves `.` and `..` components without looking at the file system,
+so `..` leaves a symbolic link the way it was entered. */
+pub fn normalize_path(path: &str) -> String {
+    let mut components: Vec<&str> = Vec::new();
+    for component in path.split('/') {
+        match component {
+            "" | "." => (),
+            ".." => {
+                components.pop();
+            }
+            _ => components.push(component),
+        }
+    }
+    format!("/{}", components.join("/"))
+}
+
+/* The current directory as `pwd` prints it, falling back to the physical
+one when PWD is no longer right. */
+pub fn current_directory(shell: &Shell) -> String {
+    initial_directory(shell.get_variable("PWD")).unwrap_or_else(|| ".".to_string())
+}
+
+/* Changes to the directory and updates PWD and OLDPWD. Logically, the
+directory is taken relative to PWD and `..` removes its last component.
+Physically, the new PWD is the directory with all links resolved. */
+pub fn change_directory(shell: &mut Shell, directory: &str, physical: bool) -> Result<(), String> {
+    let old_directory = current_directory(shell);
+    let target = if physical {
+        directory.to_string()
+    } else if directory.starts_with('/') {
+        normalize_path(directory)
+    } else {
+        normalize_path(&format!("{}/{}", old_directory, directory))
+    };
+    if let Err(err) = chdir(Path::new(&target)) {
+        return Err(format!("{}: {}", directory, describe_nix_error(&err)));
+    }
+    let new_directory = if physical {
+        physical_directory().unwrap_or(target)
+    } else {
+        target
+    };
+    shell.set_variable("OLDPWD", old_directory);
+    shell.set_variable("PWD", new_directory);
+    Ok(())
+}
+
+/* Parses the `-L` and `-P` options shared by `cd` and `pwd`, where the
+last one wins. Returns whether paths are physical and the position of
+the first operand. */
+fn parse_link_options(arguments: &[String], streams: &mut Streams) -> Option<(bool, usize)> {
+    let mut physical = false;
+    let mut position = 1;
+    while let Some(argument) = arguments.get(position) {
+        if argument == "--" {
+            position += 1;
+            break;
+        }
+        if !argument.starts_with('-') || argument.len() == 1 {
+            break;
+        }
+        for option in argument[1..].chars() {
+            match option {
+                'L' => physical = false,
+                'P' => physical = true,
+                _ => {
+                    streams.error(format!("{}: -{}: invalid option", arguments[0], option));
+                    return None;
+                }
+            }
+        }
+        position += 1;
+    }
+    Some((physical, position))
+}
+
+/* `cd [-L|-P] [directory]` goes to HOME without a directory and to
+OLDPWD with `-`. A relative directory not starting with `.` or `..` is
+looked up in the directories of CDPATH first, and the new directory is
+printed when it was found there or with `-`. */
+pub fn builtin_cd(shell: &mut Shell, arguments: &[String], streams: &mut Streams) -> i32 {
+    let (physical, position) = match parse_link_options(arguments, streams) {
+        Some(options) => options,
+        None => return 2,
+    };
+    let operands = &arguments[position..];
+    if operands.len() > 1 {
+        streams.error("cd: too many arguments");
+        return 1;
+    }
+    let (directory, mut print) = match operands.first().map(|operand| operand.as_str()) {
+        None => match shell.get_variable("HOME") {
+            Some(home) => (home.to_string(), false),
+            None => {
+                streams.error("cd: HOME not set");
+                return 1;
+            }
+        },
+        Some("-") => match shell.get_variable("OLDPWD") {
+            Some(old_directory) => (old_directory.to_string(), true),
+            None => {
+                streams.error("cd: OLDPWD not set");
+                return 1;
+            }
+        },
+        Some(directory) => (directory.to_string(), false),
+    };
+    let mut candidates: Vec<(String, bool)> = Vec::new();
+    let is_relative = !directory.starts_with('/')
+        && directory != "."
+        && directory != ".."
+        && !directory.starts_with("./")
+        && !directory.starts_with("../");
+    if let (true, Some(cdpath)) = (is_relative, shell.get_variable("CDPATH")) {
+        for prefix in cdpath.split(':') {
+            match prefix {
+                "" => candidates.push((directory.clone(), false)),
+                _ => candidates.push((
+                    format!("{}/{}", prefix.trim_end_matches('/'), directory),
+                    true,
+                )),
+            }
+        }
+    }
+    for (candidate, found_in_cdpath) in candidates {
+        if Path::new(&candidate).is_dir() && change_directory(shell, &candidate, physical).is_ok() {
+            print |= found_in_cdpath;
+            return finish_cd(shell, streams, print);
+        }
+    }
+    match change_directory(shell, &directory, physical) {
+        Ok(()) => finish_cd(shell, streams, print),
+        Err(message) => {
+            streams.error(format!("cd: {}", message));
+            1
+        }
+    }
+}
+
+fn finish_cd(shell: &Shell, streams: &mut Streams, print: bool) -> i32 {
+    if print {
+        let _ = writeln!(
+            streams.stdout,
+            "{}",
+            shell.get_variable("PWD").unwrap_or("")
+        );
+    }
+    0
+}
+
+/* `pwd [-L|-P]` prints PWD, or the directory with all links resolved
+with -P. */
+pub fn builtin_pwd(shell: &mut Shell, arguments: &[String], streams: &mut Streams) -> i32 {
+    let physical = match parse_link_options(arguments, streams) {
+        Some((physical, _)) => physical,
+        None => return 2,
+    };
+    let directory = if physical {
+        match getcwd() {
+            Ok(path) => path.to_string_lossy().into_owned(),
+            Err(err) => {
+                streams.error(format!("pwd: {}", describe_nix_error(&err)));
+                return 1;
+            }
+        }
+    } else {
+        current_directory(shell)
+    };
+    match writeln!(streams.stdout, "{}", directory) {
+        Ok(()) => 0,
+        Err(err) => {
+            streams.error(format!("pwd: write error: {}", err));
+            1
+        }
+    }
+}
+
+/* The directory stack as `dirs` shows it, with the current directory on
+top. */
+fn full_stack(shell: &Shell) -> Vec<String> {
+    let mut stack = vec![current_directory(shell)];
+    stack.extend(shell.directory_stack.iter().cloned());
+    stack
+}
+
+/* Parses a stack index of the form `+N`, counting from the top, or `-N`,
+counting from the bottom, into a position in the full stack. Returns
+None if the argument isn't an index at all, and Some(None) if the index
+is out of range. */
+fn parse_stack_index(argument: &str, length: usize) -> Option<Option<usize>> {
+    let (from_bottom, digits) = match argument.chars().next() {
+        Some('+') => (false, &argument[1..]),
+        Some('-') => (true, &argument[1..]),
+        _ => return None,
+    };
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let index = match digits.parse::<usize>() {
+        Ok(index) if index < length => index,
+        _ => return Some(None),
+    };
+    Some(Some(if from_bottom {
+        length - 1 - index
+    } else {
+        index
+    }))
+}
+
+/* The entry `~N`, `~+N` or `~-N` expands to, where N alone counts from
+the top. */
+pub fn directory_stack_entry(shell: &Shell, index: &str) -> Option<String> {
+    let index = match index.chars().next() {
+        Some('+') | Some('-') => index.to_string(),
+        _ => format!("+{}", index),
+    };
+    let stack = full_stack(shell);
+    match parse_stack_index(&index, stack.len()) {
+        Some(Some(index)) => Some(stack[index].clone()),
+        _ => None,
+    }
+}
+
+/* Abbreviates the home directory at the start of a path with `~`. */
+fn abbreviate_home(shell: &Shell, path: &str) -> String {
+    match shell.get_variable("HOME") {
+        Some(home) if !home.is_empty() && path.starts_with(home) => {
+            let rest = &path[home.len()..];
+            if rest.is_empty() || rest.starts_with('/') {
+                return format!("~{}", rest);
+            }
+            path.to_string()
+        }
+        _ => path.to_string(),
+    }
+}
+
+fn print_stack(shell: &Shell, streams: &mut Streams) {
+    let stack: Vec<String> = full_stack(shell)
+        .iter()
+        .map(|directory| abbreviate_home(shell, directory))
+        .collect();
+    let _ = writeln!(streams.stdout, "{}", stack.join(" "));
+}
+
+fn invalid_number(streams: &mut Streams, name: &str, argument: &str, usage: &str) -> i32 {
+    streams.error(format!("{}: {}: invalid number", name, argument));
+    let _ = writeln!(streams.stderr, "{}: usage: {} {}", name, name, usage);
+    2
+}
+
+/* `pushd dir` changes to the directory and pushes the previous one on the
+stack. `pushd +N` and `pushd -N` rotate the stack until entry N is on
+top, and `pushd` alone swaps the two top entries. */
+pub fn builtin_pushd(shell: &mut Shell, arguments: &[String], streams: &mut Streams) -> i32 {
+    let mut stack = full_stack(shell);
+    let argument = match arguments.get(1) {
+        Some(argument) if argument == "--" => arguments.get(2),
+        argument => argument,
+    };
+    match argument.map(|argument| (argument, parse_stack_index(argument, stack.len()))) {
+        None if stack.len() < 2 => {
+            streams.error("pushd: no other directory");
+            return 1;
+        }
+        None => stack.swap(0, 1),
+        Some((_, Some(Some(index)))) => stack.rotate_left(index),
+        Some((argument, Some(None))) => {
+            streams.error(format!(
+                "pushd: {}: directory stack index out of range",
+                argument
+            ));
+            return 1;
+        }
+        Some((argument, None))
+            if argument.starts_with('-') && argument.len() > 1 && argument != "--" =>
+        {
+            return invalid_number(streams, "pushd", argument, "[+N | -N | dir]");
+        }
+        Some((directory, None)) => {
+            if let Err(message) = change_directory(shell, directory, false) {
+                streams.error(format!("pushd: {}", message));
+                return 1;
+            }
+            shell.directory_stack.insert(0, stack.remove(0));
+            print_stack(shell, streams);
+            return 0;
+        }
+    }
+    if let Err(message) = change_directory(shell, &stack[0], false) {
+        streams.error(format!("pushd: {}", message));
+        return 1;
+    }
+    shell.directory_stack = stack[1..].to_vec();
+    print_stack(shell, streams);
+    0
+}
+
+/* `popd` removes the top of the stack and changes to the directory below
+it. `popd +N` and `popd -N` remove entry N instead. */
+pub fn builtin_popd(shell: &mut Shell, arguments: &[String], streams: &mut Streams) -> i32 {
+    let length = shell.directory_stack.len() + 1;
+    let index = match arguments.get(1) {
+        None => 0,
+        Some(argument) => match parse_stack_index(argument, length) {
+            Some(Some(index)) => index,
+            Some(None) => {
+                streams.error(format!(
+                    "popd: {}: directory stack index out of range",
+                    argument
+                ));
+                return 1;
+            }
+            None => return invalid_number(streams, "popd", argument, "[+N | -N]"),
+        },
+    };
+    if shell.directory_stack.is_empty() {
+        streams.error("popd: directory stack empty");
+        return 1;
+    }
+    if index == 0 {
+        let directory = shell.directory_stack[0].clone();
+        if let Err(message) = change_directory(shell, &directory, false) {
+            streams.error(format!("popd: {}", message));
+            return 1;
+        }
+    }
+    shell.directory_stack.remove(index.max(1) - 1);
+    print_stack(shell, streams);
+    0
+}
+
+/* `dirs [-clpv] [+N | -N]` shows the directory stack on one line, one
+entry per line with -p, or numbered with -v. With -l the home directory
+isn't abbreviated to `~`, and -c clears the stack. */
+pub fn builtin_dirs(shell: &mut Shell, arguments: &[String], streams: &mut Streams) -> i32 {
+    let (mut long, mut per_line, mut numbered) = (false, false, false);
+    let stack = full_stack(shell);
+    let mut selected = None;
+    for argument in &arguments[1..] {
+        if let Some(index) = parse_stack_index(argument, stack.len()) {
+            match index {
+                Some(index) => selected = Some(index),
+                None => {
+                    streams.error(format!(
+                        "dirs: {}: directory stack index out of range",
+                        &argument[1..]
+                    ));
+                    return 1;
+                }
+            }
+            continue;
+        }
+        if !argument.starts_with('-') || argument.len() == 1 {
+            return invalid_number(streams, "dirs", argument, "[-clpv] [+N] [-N]");
+        }
+        for option in argument[1..].chars() {
+            match option {
+                'c' => {
+                    shell.directory_stack.clear();
+                    return 0;
+                }
+                'l' => long = true,
+                'p' => per_line = true,
+                'v' => numbered = true,
+                _ => return invalid_number(streams, "dirs", argument, "[-clpv] [+N] [-N]"),
+            }
+        }
+    }
+    let format = |directory: &str| {
+        if long {
+            directory.to_string()
+        } else {
+            abbreviate_home(shell, directory)
+        }
+    };
+    if let Some(index) = selected {
+        let _ = writeln!(streams.stdout, "{}", format(&stack[index]));
+        return 0;
+    }
+    let entries: Vec<String> = stack.iter().map(|directory| format(directory)).collect();
+    if numbered {
+        for (index, entry) in entries.iter().enumerate() {
+            let _ = writeln!(streams.stdout, "{:2}  {}", index, entry);
+        }
+    } else if per_line {
+        for entry in &entries {
+            let _ = writeln!(streams.stdout, "{}", entry);
+        }
+    } else {
+        let _ = writeln!(streams.stdout, "{}", entries.join(" "));
+    }
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_path_test() {
+        assert_eq!(normalize_path("/a/./b//c/"), "/a/b/c");
+        assert_eq!(normalize_path("/a/b/../../c/.."), "/");
+        assert_eq!(normalize_path("/.."), "/");
+        assert_eq!(normalize_path("/link/.."), "/");
+    }
+
+    #[test]
+    fn parse_stack_index_test() {
+        assert_eq!(parse_stack_index("+0", 3), Some(Some(0)));
+        assert_eq!(parse_stack_index("-0", 3), Some(Some(2)));
+        assert_eq!(parse_stack_index("+2", 3), Some(Some(2)));
+        assert_eq!(parse_stack_index("+3", 3), Some(None));
+        assert_eq!(parse_stack_index("dir", 3), None);
+        assert_eq!(parse_stack_index("-", 3), None);
+    }
+}
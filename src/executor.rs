@@ -1,93 +1,1461 @@
 extern crate nix;
 extern crate rustyline;
 
-use executor::nix::sys::wait::waitpid;
+use arithmetic::evaluate_arithmetic;
+use assignment::assign;
+use builtins::Builtin;
+use builtins::Streams;
+use conditional::compare_integers;
+use conditional::is_integer_operator;
+use conditional::test_binary;
+use conditional::test_unary;
+use executor::nix::errno::Errno;
+use executor::nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
 use executor::nix::unistd::*;
-use lexer::get_function_from_string;
-use lexer::ParseError;
-use lexer::Function;
 use executor::rustyline::error::ReadlineError;
 use executor::rustyline::Editor;
+use expansion::expand_arithmetic;
+use expansion::expand_command_words;
+use expansion::expand_here_document;
+use expansion::expand_word_to_pattern;
+use expansion::expand_word_to_regex;
+use expansion::expand_word_to_string;
+use expansion::expand_words;
+use hash::find_command_to_run;
+use hash::search_path;
+use lexer::ParseError;
+use options::quote_word;
+use parser::parse_string_with_aliases;
+use parser::AndOr;
+use parser::AndOrOperator;
+use parser::Assignment;
+use parser::AssignmentValue;
+use parser::CaseItem;
+use parser::CaseTerminator;
+use parser::Command;
+use parser::CompoundCommand;
+use parser::ConditionalExpression;
+use parser::List;
+use parser::ListItem;
+use parser::Pipeline;
+use parser::Redirection;
+use parser::SimpleCommand;
+use pattern::pattern_matches;
+use redirection::apply_redirections;
+use redirection::restore_redirections;
+use regex::Regex;
+use shell::ControlFlow;
+use shell::ProcessSubstitution;
+use shell::Shell;
+use shell::ShellError;
+use shell::Value;
+use signals;
+use std::ffi::CString;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::mem::{self, ManuallyDrop};
+use std::os::unix::io::{FromRawFd, RawFd};
+use std::process;
+use std::slice;
+use trap::reset_traps;
+
+/* The file in the home directory that interactive shells run first. */
+const RC_FILE: &str = ".shellerrc";
 
+/* Used for the function nesting limit unless FUNCNEST says otherwise. */
+const MAX_FUNCTION_DEPTH: usize = 1000;
 
-pub enum ToQuit {
-    Quit,
-    Continue,
+/* Traps that functions don't inherit from their caller. */
+const FUNCTION_TRAPS: [&str; 3] = ["DEBUG", "ERR", "RETURN"];
+
+/* Runs the items of a list one after the other, returning the status of
+the last one. Items ending in `&` run in a forked child that the shell
+doesn't wait for. */
+pub fn execute_list(shell: &mut Shell, list: &[ListItem]) -> nix::Result<i32> {
+    for item in list {
+        run_pending_traps(shell);
+        if shell.control_flow != ControlFlow::Normal || signals::interrupted() {
+            break;
+        }
+        if item.background {
+            match fork_child(shell, true)? {
+                ForkResult::Parent { child } => {
+                    shell.unreaped_children.push(child);
+                    shell.last_background_pid = Some(child);
+                    shell.last_status = 0;
+                }
+                ForkResult::Child => {
+                    let status = execute_and_or(shell, &item.and_or);
+                    exit_child(shell, status);
+                }
+            }
+        } else {
+            shell.last_status = execute_and_or(shell, &item.and_or)?;
+        }
+    }
+    run_pending_traps(shell);
+    Ok(shell.last_status)
 }
 
-fn execute_all_functions(functions: Vec<Function>) -> nix::Result<ToQuit> {
-    let mut quit_after_execution = false;
-    let mut children: Vec<Pid> = Vec::new();
+/* Runs pipelines joined by `&&` and `||`. Only a failure of the last
+one can trigger the ERR trap or make errexit end the shell, since the
+status of the others is tested. */
+fn execute_and_or(shell: &mut Shell, and_or: &AndOr) -> nix::Result<i32> {
+    let mut status = execute_tested_pipeline(shell, &and_or.first, !and_or.rest.is_empty())?;
+    let mut last_pipeline = if and_or.rest.is_empty() {
+        Some(&and_or.first)
+    } else {
+        None
+    };
+    for (index, (operator, pipeline)) in and_or.rest.iter().enumerate() {
+        if shell.control_flow != ControlFlow::Normal || signals::interrupted() {
+            break;
+        }
+        shell.last_status = status;
+        let run_next = match operator {
+            AndOrOperator::And => status == 0,
+            AndOrOperator::Or => status != 0,
+        };
+        if run_next {
+            let is_last = index + 1 == and_or.rest.len();
+            status = execute_tested_pipeline(shell, pipeline, !is_last)?;
+            if is_last {
+                last_pipeline = Some(pipeline);
+            }
+        }
+    }
+    let failed = status != 0 && shell.condition_depth == 0;
+    if failed && last_pipeline.is_some_and(triggers_error_trap) {
+        if let ControlFlow::Normal | ControlFlow::Return = shell.control_flow {
+            shell.last_status = status;
+            run_trap(shell, "ERR");
+            if shell.options.errexit {
+                shell.control_flow = ControlFlow::Exit(status);
+            }
+        }
+    }
+    Ok(status)
+}
+
+/* Runs a pipeline, as a condition if its status is tested or negated. */
+fn execute_tested_pipeline(
+    shell: &mut Shell,
+    pipeline: &Pipeline,
+    tested: bool,
+) -> nix::Result<i32> {
+    if tested || pipeline.negated {
+        shell.condition_depth += 1;
+        let status = execute_pipeline(shell, pipeline);
+        shell.condition_depth -= 1;
+        status
+    } else {
+        execute_pipeline(shell, pipeline)
+    }
+}
+
+/* Runs a list whose status decides what happens next, so that failures
+in it don't trigger the ERR trap or errexit. */
+fn execute_condition(shell: &mut Shell, list: &[ListItem]) -> nix::Result<i32> {
+    shell.condition_depth += 1;
+    let status = execute_list(shell, list);
+    shell.condition_depth -= 1;
+    status
+}
 
-    for function in &functions {
-        match function {
-            Function::Quit => quit_after_execution = true,
-            Function::ShellCommand(command) => {
-                let fork_result = fork()?;
-                match fork_result {
-                    ForkResult::Parent { child } => {
-                        children.push(child);
+/* Whether a pipeline that failed triggers the ERR trap and errexit. A
+compound command run by the shell itself doesn't, as the failing
+command inside it already did. */
+fn triggers_error_trap(pipeline: &Pipeline) -> bool {
+    if pipeline.negated {
+        return false;
+    }
+    match pipeline.commands.as_slice() {
+        [Command::Compound(command, _)] => matches!(
+            command,
+            CompoundCommand::Subshell(_)
+                | CompoundCommand::Arithmetic(_)
+                | CompoundCommand::Conditional(_)
+        ),
+        _ => true,
+    }
+}
+
+/* A single command runs directly in the shell. Longer pipelines fork a
+child for every command, with each stdout connected to the next stdin,
+and the status is the one of the last command, or with pipefail the one
+of the last command that failed. */
+fn execute_pipeline(shell: &mut Shell, pipeline: &Pipeline) -> nix::Result<i32> {
+    let status = if pipeline.commands.len() == 1 {
+        execute_command(shell, &pipeline.commands[0])?
+    } else {
+        let mut children: Vec<Pid> = Vec::new();
+        let mut previous_read_end: Option<RawFd> = None;
+        for (index, command) in pipeline.commands.iter().enumerate() {
+            let is_last = index + 1 == pipeline.commands.len();
+            let (read_end, write_end) = if is_last {
+                (None, None)
+            } else {
+                let (read_end, write_end) = pipe()?;
+                (Some(read_end), Some(write_end))
+            };
+            match fork_child(shell, false)? {
+                ForkResult::Parent { child } => {
+                    children.push(child);
+                    if let Some(fd) = previous_read_end {
+                        close(fd)?;
                     }
-                    ForkResult::Child => {
-                        match command.execute() {
-                            Ok(_) => (),
-                            Err(err) => println!("Exectution Error {:?}", err),
-                        };
+                    if let Some(fd) = write_end {
+                        close(fd)?;
                     }
+                    previous_read_end = read_end;
                 }
+                ForkResult::Child => {
+                    if let Some(fd) = previous_read_end {
+                        dup2(fd, 0)?;
+                        close(fd)?;
+                    }
+                    if let Some(fd) = write_end {
+                        dup2(fd, 1)?;
+                        close(fd)?;
+                    }
+                    if let Some(fd) = read_end {
+                        close(fd)?;
+                    }
+                    let status = execute_command(shell, command);
+                    exit_child(shell, status);
+                }
+            }
+        }
+        let mut status = 0;
+        for child in children {
+            let child_status = wait_for_child(child)?;
+            if child_status != 0 || !shell.options.pipefail {
+                status = child_status;
+            }
+        }
+        status
+    };
+    if pipeline.negated {
+        Ok(if status == 0 { 1 } else { 0 })
+    } else {
+        Ok(status)
+    }
+}
+
+fn execute_command(shell: &mut Shell, command: &Command) -> nix::Result<i32> {
+    match command {
+        Command::Simple(simple_command) => {
+            let pending = shell.process_substitutions.len();
+            let status = execute_simple_command(shell, simple_command);
+            finish_process_substitutions(shell, pending);
+            status
+        }
+        Command::Compound(compound_command, redirections) => {
+            let pending = shell.process_substitutions.len();
+            let status = execute_compound_command(shell, compound_command, redirections);
+            finish_process_substitutions(shell, pending);
+            status
+        }
+        Command::FunctionDefinition(name, body) => {
+            shell.functions.insert(name.clone(), body.clone());
+            Ok(0)
+        }
+    }
+}
+
+/* Redirections on a compound command apply to everything inside it. A
+subshell runs in a forked copy of the shell, so nothing it changes is
+visible afterwards, while a brace group runs in the shell itself. */
+fn execute_compound_command(
+    shell: &mut Shell,
+    compound_command: &CompoundCommand,
+    redirections: &[Redirection],
+) -> nix::Result<i32> {
+    if let CompoundCommand::Subshell(ref list) = compound_command {
+        return match fork_child(shell, false)? {
+            ForkResult::Parent { child } => wait_for_child(child),
+            ForkResult::Child => {
+                if let Err(err) = apply_redirections(shell, redirections, false) {
+                    exit_child(shell, Ok(report_error(err)));
+                }
+                let status = execute_list(shell, list);
+                exit_child(shell, status);
             }
+        };
+    }
+    let saved = match apply_redirections(shell, redirections, true) {
+        Ok(saved) => saved,
+        Err(err) => return Ok(report_error(err)),
+    };
+    let status = match compound_command {
+        CompoundCommand::BraceGroup(list) => execute_list(shell, list),
+        CompoundCommand::If {
+            branches,
+            else_branch,
+        } => execute_if(shell, branches, else_branch),
+        CompoundCommand::While {
+            condition,
+            body,
+            until,
+        } => execute_while(shell, condition, body, *until),
+        CompoundCommand::For { name, words, body } => execute_for(shell, name, words, body),
+        CompoundCommand::ArithmeticFor {
+            initialization,
+            condition,
+            update,
+            body,
+        } => execute_arithmetic_for(shell, initialization, condition, update, body),
+        CompoundCommand::Arithmetic(expression) => match expand_arithmetic(shell, expression) {
+            Ok(0) => Ok(1),
+            Ok(_) => Ok(0),
+            Err(err) => Ok(report_error(err)),
+        },
+        CompoundCommand::Conditional(expression) => match evaluate_conditional(shell, expression) {
+            Ok(true) => Ok(0),
+            Ok(false) => Ok(1),
+            Err(err) => {
+                report_error(err);
+                Ok(2)
+            }
+        },
+        CompoundCommand::Case { word, items } => execute_case(shell, word, items),
+        CompoundCommand::Subshell(_) => Ok(0),
+    };
+    let _ = io::stdout().flush();
+    restore_redirections(saved);
+    status
+}
+
+/* Runs the body of the first branch whose condition succeeds. The
+status is that of the body, or zero if no branch was taken. */
+fn execute_if(
+    shell: &mut Shell,
+    branches: &[(List, List)],
+    else_branch: &Option<List>,
+) -> nix::Result<i32> {
+    for (condition, body) in branches {
+        if execute_condition(shell, condition)? == 0 {
+            return execute_list(shell, body);
         }
     }
-    for child in &children {
-        waitpid(*child, None)?;
+    match else_branch {
+        Some(body) => execute_list(shell, body),
+        None => Ok(0),
+    }
+}
+
+/* Runs the body for as long as the condition succeeds, or for `until`,
+for as long as it fails. The status is that of the last body run. */
+fn execute_while(
+    shell: &mut Shell,
+    condition: &[ListItem],
+    body: &[ListItem],
+    until: bool,
+) -> nix::Result<i32> {
+    let mut status = 0;
+    shell.loop_depth += 1;
+    let result = loop {
+        let condition_status = match execute_condition(shell, condition) {
+            Ok(condition_status) => condition_status,
+            Err(err) => break Err(err),
+        };
+        if !continue_loop(shell) {
+            break Ok(status);
+        }
+        if (condition_status == 0) == until {
+            break Ok(status);
+        }
+        status = match execute_list(shell, body) {
+            Ok(body_status) => body_status,
+            Err(err) => break Err(err),
+        };
+        if !continue_loop(shell) {
+            break Ok(status);
+        }
+    };
+    shell.loop_depth -= 1;
+    result
+}
+
+/* Runs the body of the first item with a pattern matching the word, and
+then goes on according to the terminator of that item. The status is
+that of the last body run, or zero if nothing matched. */
+fn execute_case(shell: &mut Shell, word: &str, items: &[CaseItem]) -> nix::Result<i32> {
+    let word = match expand_word_to_string(shell, word) {
+        Ok(word) => word,
+        Err(err) => return Ok(report_error(err)),
+    };
+    let mut status = 0;
+    let mut falling_through = false;
+    for item in items {
+        if !falling_through {
+            let mut matched = false;
+            for pattern in &item.patterns {
+                match expand_word_to_pattern(shell, pattern) {
+                    Ok(pattern) if pattern_matches(&pattern, &word) => {
+                        matched = true;
+                        break;
+                    }
+                    Ok(_) => (),
+                    Err(err) => return Ok(report_error(err)),
+                }
+            }
+            if !matched {
+                continue;
+            }
+        }
+        status = execute_list(shell, &item.body)?;
+        if item.terminator == CaseTerminator::Break
+            || shell.control_flow != ControlFlow::Normal
+            || signals::interrupted()
+        {
+            break;
+        }
+        falling_through = item.terminator == CaseTerminator::FallThrough;
+    }
+    Ok(status)
+}
+
+/* Evaluates the expression of `[[ ... ]]`. Operands are expanded without
+field splitting or pathname expansion, the right side of `==` and `!=`
+is a pattern, and the operands of integer comparisons are arithmetic
+expressions. */
+fn evaluate_conditional(
+    shell: &mut Shell,
+    expression: &ConditionalExpression,
+) -> Result<bool, ShellError> {
+    Ok(match expression {
+        ConditionalExpression::Word(word) => !expand_word_to_string(shell, word)?.is_empty(),
+        ConditionalExpression::Unary(operator, operand) => {
+            let operand = expand_word_to_string(shell, operand)?;
+            test_unary(shell, operator, &operand)
+        }
+        ConditionalExpression::Binary(left, operator, right) => {
+            let left = expand_word_to_string(shell, left)?;
+            match operator.as_str() {
+                "=" | "==" | "!=" => {
+                    let pattern = expand_word_to_pattern(shell, right)?;
+                    pattern_matches(&pattern, &left) == (operator != "!=")
+                }
+                "=~" => match_regex(shell, &left, right)?,
+                _ if is_integer_operator(operator) => {
+                    let right = expand_word_to_string(shell, right)?;
+                    let left = evaluate_arithmetic(shell, &left)?;
+                    let right = evaluate_arithmetic(shell, &right)?;
+                    compare_integers(left, operator, right)
+                }
+                _ => {
+                    let right = expand_word_to_string(shell, right)?;
+                    test_binary(&left, operator, &right).unwrap_or(false)
+                }
+            }
+        }
+        ConditionalExpression::Not(expression) => !evaluate_conditional(shell, expression)?,
+        ConditionalExpression::And(left, right) => {
+            evaluate_conditional(shell, left)? && evaluate_conditional(shell, right)?
+        }
+        ConditionalExpression::Or(left, right) => {
+            evaluate_conditional(shell, left)? || evaluate_conditional(shell, right)?
+        }
+    })
+}
+
+/* Matches the text against a regular expression, leaving the match and
+what every group matched in the BASH_REMATCH array. */
+fn match_regex(shell: &mut Shell, text: &str, word: &str) -> Result<bool, ShellError> {
+    let pattern = expand_word_to_regex(shell, word)?;
+    let regex = Regex::new(&pattern)
+        .map_err(|message| ShellError::InvalidRegex(pattern.clone(), message))?;
+    let captures = regex.captures(text);
+    let elements = captures
+        .iter()
+        .flatten()
+        .map(|capture| capture.clone().unwrap_or_default())
+        .enumerate()
+        .collect();
+    shell.set_value("BASH_REMATCH", Value::Indexed(elements));
+    Ok(captures.is_some())
+}
+
+/* Runs the body once for every word, or for every positional parameter
+if there is no word list, with the variable set to it. The words are
+expanded once, before the first iteration. */
+fn execute_for(
+    shell: &mut Shell,
+    name: &str,
+    words: &Option<Vec<String>>,
+    body: &[ListItem],
+) -> nix::Result<i32> {
+    let values = match words {
+        Some(words) => match expand_words(shell, words) {
+            Ok(values) => values,
+            Err(err) => return Ok(report_error(err)),
+        },
+        None => shell.positional_parameters.clone(),
+    };
+    let mut status = 0;
+    let mut result = Ok(());
+    shell.loop_depth += 1;
+    for value in values {
+        shell.set_variable(name, value);
+        match execute_list(shell, body) {
+            Ok(body_status) => status = body_status,
+            Err(err) => {
+                result = Err(err);
+                break;
+            }
+        }
+        if !continue_loop(shell) {
+            break;
+        }
+    }
+    shell.loop_depth -= 1;
+    result.map(|_| status)
+}
+
+/* `for ((initialization; condition; update))` evaluates the condition
+before every iteration and the update after it, also when the body
+ends with `continue`. An empty condition is always true. */
+fn execute_arithmetic_for(
+    shell: &mut Shell,
+    initialization: &str,
+    condition: &str,
+    update: &str,
+    body: &[ListItem],
+) -> nix::Result<i32> {
+    if let Err(err) = expand_arithmetic(shell, initialization) {
+        return Ok(report_error(err));
+    }
+    let mut status = 0;
+    shell.loop_depth += 1;
+    let result = loop {
+        if !condition.trim().is_empty() {
+            match expand_arithmetic(shell, condition) {
+                Ok(0) => break Ok(status),
+                Ok(_) => (),
+                Err(err) => break Ok(report_error(err)),
+            }
+        }
+        status = match execute_list(shell, body) {
+            Ok(body_status) => body_status,
+            Err(err) => break Err(err),
+        };
+        if !continue_loop(shell) {
+            break Ok(status);
+        }
+        if let Err(err) = expand_arithmetic(shell, update) {
+            break Ok(report_error(err));
+        }
+    };
+    shell.loop_depth -= 1;
+    result
+}
+
+/* Looks at what `break` and `continue` asked for after a part of a loop
+has run, and consumes the request if it was meant for this loop. An
+interrupt ends every loop. */
+fn continue_loop(shell: &mut Shell) -> bool {
+    if signals::interrupted() {
+        return false;
+    }
+    match shell.control_flow {
+        ControlFlow::Normal => true,
+        ControlFlow::Break(1) => {
+            shell.control_flow = ControlFlow::Normal;
+            false
+        }
+        ControlFlow::Break(count) => {
+            shell.control_flow = ControlFlow::Break(count - 1);
+            false
+        }
+        ControlFlow::Continue(1) => {
+            shell.control_flow = ControlFlow::Normal;
+            true
+        }
+        ControlFlow::Continue(count) => {
+            shell.control_flow = ControlFlow::Continue(count - 1);
+            false
+        }
+        ControlFlow::Return | ControlFlow::Exit(_) => false,
+    }
+}
+
+/* Forks the shell, flushing buffered output first so that it isn't
+written twice. The child starts without the traps of the shell. */
+pub fn fork_child(shell: &mut Shell, background: bool) -> nix::Result<ForkResult> {
+    let _ = io::stdout().flush();
+    let result = fork()?;
+    if let ForkResult::Child = result {
+        signals::reset_pipe_signal();
+        reset_traps(shell);
+        signals::take_pending_signals();
+        signals::reset_child_signals(background);
+    }
+    Ok(result)
+}
+
+/* Ends a forked child of the shell with the given status, or the one
+given to `exit` if it was run, after the EXIT trap the child set. */
+fn exit_child(shell: &mut Shell, status: nix::Result<i32>) -> ! {
+    let status = match status {
+        Ok(status) => exit_status(shell, status),
+        Err(err) => report_error(ShellError::Nix(err)),
+    };
+    let status = run_exit_trap(shell, status);
+    let _ = io::stdout().flush();
+    process::exit(status);
+}
+
+/* Expands and runs a single command, returning its exit status. Errors
+that only affect this command are reported here and turned into a
+failing status, so that the rest of the line still runs. */
+fn execute_simple_command(shell: &mut Shell, command: &SimpleCommand) -> nix::Result<i32> {
+    run_trap(shell, "DEBUG");
+    shell.substitution_status = None;
+    let (arguments, declarations) = match expand_command_words(shell, &command.words) {
+        Ok(expanded) => expanded,
+        Err(err) => return Ok(report_error(err)),
+    };
+    /* Only an `exit` right after the one that warned about stopped jobs
+    goes through with it. */
+    if arguments.first().map(|argument| argument.as_str()) != Some("exit") {
+        shell.exit_warned = false;
+    }
+
+    if arguments.is_empty() {
+        for assignment in &command.assignments {
+            if let Err(err) = assign(shell, assignment) {
+                return Ok(report_error(err));
+            }
+            if shell.options.xtrace && assignment.subscript.is_none() {
+                let value = shell.get_variable(&assignment.name).unwrap_or("");
+                let word = format!("{}={}", assignment.name, value);
+                trace_command(shell, &[word]);
+            }
+        }
+        return match apply_redirections(shell, &command.redirections, true) {
+            Ok(saved) => {
+                restore_redirections(saved);
+                Ok(shell.substitution_status.unwrap_or(0))
+            }
+            Err(err) => Ok(report_error(err)),
+        };
+    }
+
+    /* Only plain variables can go to the environment of a command, so
+    assignments to arrays and their elements stay in the shell. */
+    let mut assignments: Vec<(String, String)> = Vec::new();
+    for assignment in &command.assignments {
+        let result = match assignment {
+            Assignment {
+                subscript: None,
+                value: AssignmentValue::Scalar(value),
+                ..
+            } => expand_word_to_string(shell, value).map(|value| {
+                let value = if assignment.append {
+                    format!(
+                        "{}{}",
+                        shell.get_variable(&assignment.name).unwrap_or(""),
+                        value
+                    )
+                } else {
+                    value
+                };
+                assignments.push((assignment.name.clone(), value));
+            }),
+            _ => assign(shell, assignment),
+        };
+        if let Err(err) = result {
+            return Ok(report_error(err));
+        }
+    }
+    if shell.options.xtrace {
+        for (name, value) in &assignments {
+            trace_command(shell, &[format!("{}={}", name, value)]);
+        }
+        trace_command(shell, &arguments);
+    }
+
+    if let Some(body) = shell.functions.get(&arguments[0]).cloned() {
+        let saved = match apply_redirections(shell, &command.redirections, true) {
+            Ok(saved) => saved,
+            Err(err) => return Ok(report_error(err)),
+        };
+        let status = call_function(shell, &body, &arguments, assignments);
+        let _ = io::stdout().flush();
+        restore_redirections(saved);
+        return status;
+    }
+
+    if let Some(builtin) = shell.builtins.get(&arguments[0]) {
+        /* `exec` without a command changes the descriptors of the shell
+        for good. */
+        let permanent = arguments[0] == "exec" && arguments.len() == 1;
+        let saved = match apply_redirections(shell, &command.redirections, !permanent) {
+            Ok(saved) => saved,
+            Err(err) => return Ok(report_error(err)),
+        };
+        let saved_variables = assign_temporarily(shell, assignments);
+        let mut status = run_builtin(shell, &*builtin, &arguments);
+        restore_variables(shell, saved_variables);
+        if status == 0 {
+            for assignment in &declarations {
+                if let Err(err) = assign(shell, assignment) {
+                    status = report_error(err);
+                }
+            }
+        }
+        let _ = io::stdout().flush();
+        restore_redirections(saved);
+        return Ok(status);
     }
-    if quit_after_execution {
-        Ok(ToQuit::Quit)
+
+    /* A PATH given to the command itself is searched without the hash
+    table, which only remembers what the shell's own PATH finds. */
+    let path_variable = assignments
+        .iter()
+        .rev()
+        .find(|(name, _)| name == "PATH")
+        .map(|(_, value)| value.clone());
+    let redirections = &command.redirections;
+    run_external(
+        shell,
+        &arguments,
+        assignments,
+        redirections,
+        path_variable.as_deref(),
+    )
+}
+
+/* Prints a command that is about to run for xtrace, after the expanded
+value of PS4. */
+fn trace_command(shell: &mut Shell, words: &[String]) {
+    let prompt = shell.get_variable("PS4").unwrap_or("+ ").to_string();
+    let prompt = expand_here_document(shell, &prompt).unwrap_or(prompt);
+    let words: Vec<String> = words.iter().map(|word| quote_word(word)).collect();
+    eprintln!("{}{}", prompt, words.join(" "));
+}
+
+/* Looks up and runs a program in a child process, applying the
+redirections there. The program is searched in `path_variable` if it is
+given, and through the hash table otherwise. A program that can't be
+found is reported without forking. */
+pub fn run_external(
+    shell: &mut Shell,
+    arguments: &[String],
+    assignments: Vec<(String, String)>,
+    redirections: &[Redirection],
+    path_variable: Option<&str>,
+) -> nix::Result<i32> {
+    let path = match path_variable {
+        Some(path_variable) if !arguments[0].contains('/') => {
+            search_path(path_variable, &arguments[0])
+        }
+        _ => find_command_to_run(shell, &arguments[0]),
+    };
+    /* The error goes wherever the command's own stderr would have. */
+    let path = match path {
+        Some(path) => path,
+        None => {
+            return Ok(match apply_redirections(shell, redirections, true) {
+                Ok(saved) => {
+                    report_error(ShellError::CommandNotFound(arguments[0].clone()));
+                    restore_redirections(saved);
+                    127
+                }
+                Err(err) => report_error(err),
+            });
+        }
+    };
+
+    match fork_child(shell, false)? {
+        ForkResult::Parent { child } => wait_for_child(child),
+        ForkResult::Child => {
+            if let Err(err) = apply_redirections(shell, redirections, false) {
+                process::exit(report_error(err));
+            }
+            let err = execute_external(shell, path, arguments, assignments);
+            let status = exec_failure_status(&err);
+            report_error(err);
+            process::exit(status);
+        }
+    }
+}
+
+/* Runs a builtin with the shell's own standard streams. Standard input
+is read without buffering, so that a builtin never takes input meant
+for the commands after it. Standard output gets a buffer of its own,
+which goes away with whatever failed to be written before the
+redirections of the builtin are undone. */
+fn run_builtin(shell: &mut Shell, builtin: &dyn Builtin, arguments: &[String]) -> i32 {
+    let mut stdin = ManuallyDrop::new(unsafe { File::from_raw_fd(0) });
+    let stdout = ManuallyDrop::new(unsafe { File::from_raw_fd(1) });
+    let stderr = io::stderr();
+    let mut streams = Streams {
+        stdin: &mut *stdin,
+        stdout: &mut BufWriter::new(&*stdout),
+        stderr: &mut stderr.lock(),
+    };
+    let status = builtin.run(shell, arguments, &mut streams);
+    let _ = streams.stdout.flush();
+    status
+}
+
+/* Gives the prefix assignments of a builtin to the shell while it runs,
+exported like they would be for any other command. Returns what the
+variables were before, for `restore_variables`. */
+fn assign_temporarily(
+    shell: &mut Shell,
+    assignments: Vec<(String, String)>,
+) -> Vec<(String, Option<Value>, bool)> {
+    let mut saved = Vec::new();
+    for (name, value) in assignments {
+        saved.push((
+            name.clone(),
+            shell.get_value(&name).cloned(),
+            shell.is_exported(&name),
+        ));
+        shell.set_value(&name, Value::Scalar(value));
+        shell.set_exported(&name, true);
+    }
+    saved
+}
+
+fn restore_variables(shell: &mut Shell, saved: Vec<(String, Option<Value>, bool)>) {
+    for (name, value, exported) in saved.into_iter().rev() {
+        match value {
+            Some(value) => shell.set_value(&name, value),
+            None => shell.unset_variable(&name),
+        }
+        shell.set_exported(&name, exported);
+    }
+}
+
+/* Runs a function in the shell itself, with the arguments as its
+positional parameters. Prefix assignments only last for the call, just
+like the variables it declares `local`. Loops around the call can't be
+left with `break` or `continue` from inside the function. The DEBUG,
+ERR and RETURN traps of the caller are put aside for the call, while
+the ones the function sets stay in effect after it. */
+fn call_function(
+    shell: &mut Shell,
+    body: &Command,
+    arguments: &[String],
+    assignments: Vec<(String, String)>,
+) -> nix::Result<i32> {
+    let limit = shell
+        .get_variable("FUNCNEST")
+        .and_then(|value| value.parse::<usize>().ok())
+        .filter(|limit| *limit > 0)
+        .unwrap_or(MAX_FUNCTION_DEPTH);
+    if shell.function_depth >= limit {
+        return Ok(report_error(ShellError::FunctionNesting(
+            arguments[0].clone(),
+            limit,
+        )));
+    }
+    let saved_parameters = mem::replace(&mut shell.positional_parameters, arguments[1..].to_vec());
+    let saved_loop_depth = mem::replace(&mut shell.loop_depth, 0);
+    let saved_traps: Vec<(String, String)> = FUNCTION_TRAPS
+        .iter()
+        .filter_map(|name| shell.traps.remove_entry(*name))
+        .collect();
+    shell.function_depth += 1;
+    shell.push_scope();
+    for (name, value) in assignments {
+        shell.make_local(&name);
+        shell.set_variable(&name, value);
+    }
+    let status = execute_command(shell, body);
+    if shell.control_flow == ControlFlow::Return {
+        shell.control_flow = ControlFlow::Normal;
+    }
+    run_trap(shell, "RETURN");
+    shell.pop_scope();
+    shell.function_depth -= 1;
+    shell.loop_depth = saved_loop_depth;
+    shell.positional_parameters = saved_parameters;
+    for (name, command) in saved_traps {
+        shell.traps.entry(name).or_insert(command);
+    }
+    status
+}
+
+/* Replaces the current process with the program at `path`, started with
+the exported variables and the prefix assignments of the command as
+its environment. Only returns if something went wrong. */
+fn execute_external(
+    shell: &Shell,
+    path: String,
+    arguments: &[String],
+    assignments: Vec<(String, String)>,
+) -> ShellError {
+    let mut environment = shell.environment();
+    for (name, value) in assignments {
+        environment.retain(|(existing, _)| *existing != name);
+        environment.push((name, value));
+    }
+    replace_process(path, arguments, environment)
+}
+
+/* Replaces the current process with the program at `path`, started with
+the given environment. Only returns if something went wrong. */
+pub fn replace_process(
+    path: String,
+    arguments: &[String],
+    environment: Vec<(String, String)>,
+) -> ShellError {
+    let environment: Vec<String> = environment
+        .into_iter()
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect();
+    let (program, full_args, environment) = match (
+        to_cstrings(slice::from_ref(&path)),
+        to_cstrings(arguments),
+        to_cstrings(&environment),
+    ) {
+        (Ok(program), Ok(full_args), Ok(environment)) => (program, full_args, environment),
+        (Err(err), _, _) | (_, Err(err), _) | (_, _, Err(err)) => return err,
+    };
+    match execve(&program[0], &full_args, &environment) {
+        Err(err) => ShellError::Exec(path, err),
+        Ok(void) => match void {},
+    }
+}
+
+/* A program that has vanished since it was found counts as not found,
+anything else that stops it from running as not executable. */
+pub fn exec_failure_status(err: &ShellError) -> i32 {
+    match err {
+        ShellError::Exec(_, nix::Error::Sys(Errno::ENOENT)) => 127,
+        _ => 126,
+    }
+}
+
+fn to_cstrings(strings: &[String]) -> Result<Vec<CString>, ShellError> {
+    strings
+        .iter()
+        .map(|string| {
+            CString::new(string.as_str()).map_err(|_| ShellError::NullByte(string.clone()))
+        })
+        .collect()
+}
+
+fn wait_for_child(child: Pid) -> nix::Result<i32> {
+    loop {
+        match waitpid(child, None) {
+            Ok(WaitStatus::Exited(_, status)) => return Ok(status),
+            Ok(WaitStatus::Signaled(_, signal, _)) => return Ok(128 + signal as i32),
+            Ok(_) => (),
+            Err(nix::Error::Sys(Errno::EINTR)) => (),
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/* Starts `command` with its stdout (for `<(...)`) or stdin (for
+`>(...)`) connected to a pipe, and returns a path through which the
+other end of the pipe can be opened. The shell keeps its end open until
+the command using the path has finished. */
+pub fn start_process_substitution(
+    shell: &mut Shell,
+    command: &str,
+    reads_output: bool,
+) -> Result<String, ShellError> {
+    let (read_end, write_end) = pipe()?;
+    match fork_child(shell, false)? {
+        ForkResult::Parent { child } => {
+            let (kept_end, other_end) = if reads_output {
+                (read_end, write_end)
+            } else {
+                (write_end, read_end)
+            };
+            close(other_end)?;
+            shell.process_substitutions.push(ProcessSubstitution {
+                fd: kept_end,
+                pid: child,
+            });
+            Ok(format!("/dev/fd/{}", kept_end))
+        }
+        ForkResult::Child => {
+            for substitution in shell.process_substitutions.drain(..) {
+                let _ = close(substitution.fd);
+            }
+            let connected = if reads_output {
+                dup2(write_end, 1)
+            } else {
+                dup2(read_end, 0)
+            };
+            let _ = close(read_end);
+            let _ = close(write_end);
+            if let Err(err) = connected {
+                process::exit(report_error(ShellError::Nix(err)));
+            }
+            let status = execute_string(shell, command);
+            let _ = io::stdout().flush();
+            process::exit(status);
+        }
+    }
+}
+
+/* Runs `command` in a forked copy of the shell and returns what it
+wrote to stdout, without trailing newlines. Its status is remembered so
+that a command consisting only of assignments can return it. */
+pub fn capture_command_output(shell: &mut Shell, command: &str) -> Result<String, ShellError> {
+    let (read_end, write_end) = pipe()?;
+    match fork_child(shell, false)? {
+        ForkResult::Parent { child } => {
+            close(write_end)?;
+            let mut output: Vec<u8> = Vec::new();
+            let mut pipe_file = unsafe { fs::File::from_raw_fd(read_end) };
+            let _ = pipe_file.read_to_end(&mut output);
+            drop(pipe_file);
+            shell.substitution_status = Some(wait_for_child(child)?);
+            let output = String::from_utf8_lossy(&output);
+            Ok(output.trim_end_matches('\n').to_string())
+        }
+        ForkResult::Child => {
+            for substitution in shell.process_substitutions.drain(..) {
+                let _ = close(substitution.fd);
+            }
+            /* Command substitutions don't inherit errexit. */
+            shell.options.errexit = false;
+            let _ = close(read_end);
+            let connected = dup2(write_end, 1);
+            let _ = close(write_end);
+            if let Err(err) = connected {
+                process::exit(report_error(ShellError::Nix(err)));
+            }
+            let status = execute_string(shell, command);
+            exit_child(shell, Ok(status));
+        }
+    }
+}
+
+/* Closes the shell's ends of the process substitution pipes once the
+command that used them is done. Only the ones made for this command,
+after the first `pending`, are closed: those of a function call or a
+loop have to stay open for every command inside it. The inner processes
+are reaped as soon as they exit, without making the shell wait for
+them. */
+fn finish_process_substitutions(shell: &mut Shell, pending: usize) {
+    let pending = pending.min(shell.process_substitutions.len());
+    for substitution in shell.process_substitutions.drain(pending..) {
+        let _ = close(substitution.fd);
+        shell.unreaped_children.push(substitution.pid);
+    }
+    reap_children(shell);
+}
+
+/* The status the shell exits with once `exit` has unwound everything,
+or the status of the last command otherwise. */
+fn exit_status(shell: &Shell, status: i32) -> i32 {
+    match shell.control_flow {
+        ControlFlow::Exit(status) => status,
+        _ => status,
+    }
+}
+
+/* Whether a background child has been stopped, which `exit` warns about
+before leaving it behind. A stop is only reported once, so the stopped
+children are remembered until they continue or finish. Children that
+have finished are reaped on the way. */
+pub fn has_stopped_children(shell: &mut Shell) -> bool {
+    let flags = WaitPidFlag::WNOHANG | WaitPidFlag::WUNTRACED | WaitPidFlag::WCONTINUED;
+    let mut stopped = mem::take(&mut shell.stopped_children);
+    shell
+        .unreaped_children
+        .retain(|child| match waitpid(*child, Some(flags)) {
+            Ok(WaitStatus::Stopped(..)) => {
+                if !stopped.contains(child) {
+                    stopped.push(*child);
+                }
+                true
+            }
+            Ok(WaitStatus::Continued(..)) => {
+                stopped.retain(|pid| pid != child);
+                true
+            }
+            Ok(WaitStatus::Exited(..)) | Ok(WaitStatus::Signaled(..)) | Err(_) => false,
+            Ok(_) => true,
+        });
+    let children = &shell.unreaped_children;
+    stopped.retain(|pid| children.contains(pid));
+    shell.stopped_children = stopped;
+    !shell.stopped_children.is_empty()
+}
+
+fn reap_children(shell: &mut Shell) {
+    shell
+        .unreaped_children
+        .retain(|child| match waitpid(*child, Some(WaitPidFlag::WNOHANG)) {
+            Ok(WaitStatus::StillAlive) => true,
+            Ok(WaitStatus::Exited(..)) | Ok(WaitStatus::Signaled(..)) => false,
+            Ok(_) => true,
+            Err(_) => false,
+        });
+}
+
+/* Runs the command of a trap, unless the shell is already running one.
+$? is left as it was, unless the trap used `exit`. */
+fn run_trap(shell: &mut Shell, name: &str) {
+    if shell.in_trap {
+        return;
+    }
+    let command = match shell.traps.get(name) {
+        Some(command) if !command.is_empty() => command.clone(),
+        _ => return,
+    };
+    let status = shell.last_status;
+    shell.in_trap = true;
+    execute_string(shell, &command);
+    shell.in_trap = false;
+    shell.last_status = status;
+}
+
+/* Runs the traps of the signals that arrived since the last command. A
+signal that is only caught for the sake of the EXIT trap runs that trap
+and then ends the shell the way the signal would have. */
+pub fn run_pending_traps(shell: &mut Shell) {
+    if shell.in_trap {
+        return;
+    }
+    for signal in signals::take_pending_signals() {
+        let name = signals::signal_name(signal);
+        if shell.traps.contains_key(name) {
+            run_trap(shell, name);
+        } else {
+            run_exit_trap(shell, 128 + signal as i32);
+            let _ = io::stdout().flush();
+            signals::reraise(signal);
+        }
+    }
+}
+
+/* Runs the EXIT trap as the shell is about to exit with `status`, which
+`exit` in the trap can change. The trap only ever runs once. */
+pub fn run_exit_trap(shell: &mut Shell, status: i32) -> i32 {
+    let command = match shell.traps.remove("EXIT") {
+        Some(command) => command,
+        None => return status,
+    };
+    shell.last_status = status;
+    shell.control_flow = ControlFlow::Normal;
+    shell.in_trap = true;
+    execute_string(shell, &command);
+    shell.in_trap = false;
+    let _ = io::stdout().flush();
+    exit_status(shell, status)
+}
+
+fn report_error(err: ShellError) -> i32 {
+    eprintln!("sheller: {}", err);
+    1
+}
+
+/* Parses and runs a string in the given shell, returning the status of
+the last command. Used wherever the shell has to run code that doesn't
+come from the prompt. */
+pub fn execute_string(shell: &mut Shell, string: &str) -> i32 {
+    let list = match parse_string_with_aliases(string, &shell.aliases) {
+        Ok(list) => list,
+        Err(parse_error) => {
+            eprintln!("sheller: ParseError {:?}", parse_error);
+            return 2;
+        }
+    };
+    match execute_list(shell, &list) {
+        Ok(status) => exit_status(shell, status),
+        Err(err) => report_error(ShellError::Nix(err)),
+    }
+}
+
+/* Runs a script one complete command at a time, so that a command
+spanning several lines is only parsed once all of its lines are there.
+Stops at the first syntax error, like other shells do, and at `exit` or
+a `return` from a sourced script. */
+pub fn execute_script(shell: &mut Shell, content: &str) -> i32 {
+    let mut pending_input = String::new();
+    for line in content.lines() {
+        pending_input.push_str(line);
+        pending_input.push('\n');
+        let list = match parse_string_with_aliases(&pending_input, &shell.aliases) {
+            Ok(list) => list,
+            Err(ref parse_error) if parse_error.is_incomplete() => continue,
+            Err(parse_error) => {
+                eprintln!("sheller: ParseError {:?}", parse_error);
+                return 2;
+            }
+        };
+        pending_input.clear();
+        if let Err(err) = execute_list(shell, &list) {
+            report_error(ShellError::Nix(err));
+        }
+        reap_children(shell);
+        match shell.control_flow {
+            ControlFlow::Exit(status) => return status,
+            ControlFlow::Return => return shell.last_status,
+            _ => (),
+        }
+    }
+    if !pending_input.is_empty() {
+        eprintln!("sheller: ParseError {:?}", ParseError::UnexpectedEof);
+        return 2;
+    }
+    shell.last_status
+}
+
+/* Runs the contents of a file in the shell itself, for `source`. The
+arguments, if there are any, are the positional parameters while it
+runs, and `return` leaves it early. The RETURN trap runs once it is
+done. */
+pub fn source_script(shell: &mut Shell, content: &str, arguments: &[String]) -> i32 {
+    let saved_parameters = if arguments.is_empty() {
+        None
     } else {
-        Ok(ToQuit::Continue)
+        Some(mem::replace(
+            &mut shell.positional_parameters,
+            arguments.to_vec(),
+        ))
+    };
+    shell.source_depth += 1;
+    let status = execute_script(shell, content);
+    shell.source_depth -= 1;
+    if shell.control_flow == ControlFlow::Return {
+        shell.control_flow = ControlFlow::Normal;
+    }
+    if let Some(parameters) = saved_parameters {
+        shell.positional_parameters = parameters;
     }
+    shell.last_status = status;
+    run_trap(shell, "RETURN");
+    status
 }
 
-fn run_string_input(string_input: &str) -> Result<nix::Result<ToQuit>, ParseError> {
-    let functions = get_function_from_string(string_input)?;
-    Ok(execute_all_functions(functions))
+/* Runs a script file, with the remaining command line arguments as its
+positional parameters. */
+pub fn run_script_mode(script_file: &str, arguments: &[String]) -> i32 {
+    run_script(&mut Shell::new(), script_file, arguments)
 }
 
-pub fn run_shell_mode(history_file: &str) {
+/* Runs a script file in a shell set up by the caller, for example with
+builtins of its own. */
+pub fn run_script(shell: &mut Shell, script_file: &str, arguments: &[String]) -> i32 {
+    shell.script_name = script_file.to_string();
+    shell.positional_parameters = arguments.to_vec();
+    match fs::read_to_string(script_file) {
+        Ok(content) => {
+            let status = execute_script(shell, &content);
+            run_exit_trap(shell, status)
+        }
+        Err(err) => {
+            eprintln!("sheller: {}: {}", script_file, err);
+            127
+        }
+    }
+}
+
+fn run_string_input(shell: &mut Shell, string_input: &str) -> Result<nix::Result<i32>, ParseError> {
+    let list = parse_string_with_aliases(string_input, &shell.aliases)?;
+    Ok(execute_list(shell, &list))
+}
+
+pub fn run_shell_mode(history_file: &str) -> i32 {
+    let mut shell = Shell::new();
+    shell.interactive = true;
+    load_rc_file(&mut shell);
+    run_prompt(&mut shell, history_file)
+}
+
+/* Runs ~/.shellerrc, where interactive shells get their aliases and
+other settings from. */
+pub fn load_rc_file(shell: &mut Shell) {
+    let rc_file = match shell.get_variable("HOME") {
+        Some(home) => format!("{}/{}", home.trim_end_matches('/'), RC_FILE),
+        None => return,
+    };
+    if let Ok(content) = fs::read_to_string(&rc_file) {
+        execute_script(shell, &content);
+    }
+}
+
+/* Reads and runs commands from the prompt in a shell set up by the
+caller, until `exit` or the end of input. Returns the status the shell
+exits with. */
+pub fn run_prompt(shell: &mut Shell, history_file: &str) -> i32 {
+    shell.interactive = true;
+    signals::catch_interrupts();
     let mut prompt = Editor::<()>::new();
     if prompt.load_history(history_file).is_err() {
         println!("No history file");
     }
+    let mut pending_input = String::new();
     loop {
-        let line = prompt.readline("sheller>> ");
+        let line = if pending_input.is_empty() {
+            prompt.readline("sheller>> ")
+        } else {
+            prompt.readline("> ")
+        };
         match line {
             Ok(input_string) => {
                 prompt.add_history_entry(input_string.as_ref());
-                match run_string_input(&input_string) {
+                pending_input.push_str(input_string.trim_end_matches('\n'));
+                pending_input.push('\n');
+                signals::clear_interrupt();
+                let result = run_string_input(shell, &pending_input);
+                match result {
+                    Err(ref parse_error) if parse_error.is_incomplete() => continue,
+                    _ => pending_input.clear(),
+                }
+                reap_children(shell);
+                if let ControlFlow::Exit(status) = shell.control_flow {
+                    prompt.save_history(history_file).unwrap();
+                    return run_exit_trap(shell, status);
+                }
+                shell.control_flow = ControlFlow::Normal;
+                match result {
                     Err(parse_error) => println!("ParseError {:?}", parse_error),
-                    Ok(inner_result) => match inner_result {
-                        Err(nix_error) => println!("NixError {:?}", nix_error),
-                        Ok(result) => match result {
-                            ToQuit::Quit => {
-                                prompt.save_history(history_file).unwrap();
-                                break
-                            },
-                            ToQuit::Continue => (),
-                        },
-                    },
+                    Ok(Err(nix_error)) => println!("NixError {:?}", nix_error),
+                    Ok(Ok(_)) => (),
                 }
             }
             Err(ReadlineError::Interrupted) => println!("KeyboardInterrupt"),
             Err(ReadlineError::Eof) => {
                 println!("Ctrl-D received. Exiting.");
                 prompt.save_history(history_file).unwrap();
-                break;
+                let status = shell.last_status;
+                return run_exit_trap(shell, status);
             }
             Err(err) => {
                 println!("Readline Error: {:?}", err);
                 prompt.save_history(history_file).unwrap();
-                break;
+                return 1;
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /* Runs a script in a forked copy of the shell and returns what it
+    wrote to standard output. */
+    fn output_of(shell: &mut Shell, script: &str) -> String {
+        capture_command_output(shell, script).unwrap()
+    }
+
+    #[test]
+    fn process_substitution_test() {
+        let mut shell = Shell::new();
+        assert_eq!(
+            output_of(&mut shell, "f() { echo start; cat \"$1\"; }; f <(echo hi)"),
+            "start\nhi"
+        );
+        assert_eq!(
+            output_of(
+                &mut shell,
+                "for f in <(echo x); do echo pre; cat $f; done; cat <(echo y)"
+            ),
+            "pre\nx\ny"
+        );
+    }
+
+    #[test]
+    fn command_not_found_test() {
+        let mut shell = Shell::new();
+        assert_eq!(
+            output_of(&mut shell, "nosuchcmd 2>/dev/null; echo $?"),
+            "127"
+        );
+        assert_eq!(
+            output_of(&mut shell, "nosuchcmd >/nonexistent/file; echo $?"),
+            "1"
+        );
+        assert_eq!(
+            output_of(
+                &mut shell,
+                "{ nosuchcmd 2>/dev/null || echo fallback; } 2>&1"
+            ),
+            "fallback"
+        );
+    }
+
+    #[test]
+    fn broken_pipe_test() {
+        let mut shell = Shell::new();
+        assert_eq!(
+            output_of(
+                &mut shell,
+                "{ { yes; echo $? >&3; } | head -1 >/dev/null; } 3>&1"
+            ),
+            "141"
+        );
+        assert_eq!(
+            output_of(
+                &mut shell,
+                "{ while true; do echo y; done | head -2; } 2>&1"
+            ),
+            "y\ny"
+        );
+    }
+
+    #[test]
+    fn here_string_test() {
+        let mut shell = Shell::new();
+        assert_eq!(
+            output_of(
+                &mut shell,
+                "x='a b'; read first rest <<< \"$x c\"; echo $rest"
+            ),
+            "b c"
+        );
+        assert_eq!(output_of(&mut shell, "cat <<< 'one two'"), "one two");
+        assert_eq!(output_of(&mut shell, "wc -l <<< ''"), "1");
+    }
+
+    #[test]
+    fn loop_control_test() {
+        let mut shell = Shell::new();
+        let script = "for i in 1 2 3; do for j in a b c; do
+            [ $j = b ] && break 2; echo $i$j; done; echo never; done; echo after";
+        assert_eq!(output_of(&mut shell, script), "1a\nafter");
+        let script = "for i in 1 2 3; do for j in a b c; do
+            [ $j = b ] && continue 2; echo $i$j; done; echo never; done";
+        assert_eq!(output_of(&mut shell, script), "1a\n2a\n3a");
+        let script = "for i in 1 2; do for j in a b; do for k in x y; do
+            [ $k = y ] && continue 3; echo $i$j$k; done; done; done";
+        assert_eq!(output_of(&mut shell, script), "1ax\n2ax");
+        let script = "i=0; until [ $i -ge 3 ]; do i=$((i + 1)); echo $i; done";
+        assert_eq!(output_of(&mut shell, script), "1\n2\n3");
+        let script = "i=0; until false; do i=$((i + 1));
+            [ $i = 2 ] && continue; [ $i -gt 3 ] && break; echo $i; done";
+        assert_eq!(output_of(&mut shell, script), "1\n3");
+        let script = "for i in 1 2; do break 5; done; echo $i";
+        assert_eq!(output_of(&mut shell, script), "1");
+        let script = "f() { for x in 1 2; do echo $x; break; done; }; for i in 1 2; do f; done";
+        assert_eq!(output_of(&mut shell, script), "1\n1");
+    }
+
+    #[test]
+    fn continue_loop_test() {
+        let mut shell = Shell::new();
+        assert!(continue_loop(&mut shell));
+        shell.control_flow = ControlFlow::Break(2);
+        assert!(!continue_loop(&mut shell));
+        assert!(shell.control_flow == ControlFlow::Break(1));
+        assert!(!continue_loop(&mut shell));
+        assert!(shell.control_flow == ControlFlow::Normal);
+        shell.control_flow = ControlFlow::Continue(2);
+        assert!(!continue_loop(&mut shell));
+        assert!(shell.control_flow == ControlFlow::Continue(1));
+        assert!(continue_loop(&mut shell));
+        assert!(shell.control_flow == ControlFlow::Normal);
+        shell.control_flow = ControlFlow::Return;
+        assert!(!continue_loop(&mut shell));
+        assert!(shell.control_flow == ControlFlow::Return);
+        shell.control_flow = ControlFlow::Exit(3);
+        assert!(!continue_loop(&mut shell));
+        assert!(shell.control_flow == ControlFlow::Exit(3));
+    }
+}
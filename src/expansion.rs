@@ -0,0 +1,1380 @@
+use arithmetic::evaluate_arithmetic;
+use assignment::assign;
+use assignment::resolve_index;
+use directory::directory_stack_entry;
+use executor::capture_command_output;
+use executor::start_process_substitution;
+use options::Options;
+use parser::is_valid_name;
+use parser::parse_assignment;
+use parser::Assignment;
+use parser::AssignmentValue;
+use pattern::glob;
+use pattern::is_pattern_special;
+use regex::is_regex_special;
+use shell::ControlFlow;
+use shell::Shell;
+use shell::ShellError;
+use shell::Value;
+use std::iter;
+use std::mem;
+
+/* The language of the patterns built next to the fields, which decides
+the characters that are escaped when they are quoted. */
+#[derive(Clone, Copy)]
+enum PatternSyntax {
+    Glob,
+    Regex,
+}
+
+impl PatternSyntax {
+    fn is_special(self, character: char) -> bool {
+        match self {
+            PatternSyntax::Glob => is_pattern_special(character),
+            PatternSyntax::Regex => is_regex_special(character),
+        }
+    }
+
+    fn is_wildcard(self, character: char) -> bool {
+        match self {
+            PatternSyntax::Glob => "*?[".contains(character),
+            PatternSyntax::Regex => is_regex_special(character),
+        }
+    }
+
+    fn escape(self, text: &str) -> String {
+        let mut pattern = String::new();
+        for character in text.chars() {
+            if self.is_special(character) {
+                pattern.push('\\');
+            }
+            pattern.push(character);
+        }
+        pattern
+    }
+}
+
+/* Collects the fields produced while expanding a word. Text coming from
+quoted parts is always kept together, while the results of unquoted
+expansions are split on the characters of IFS. Next to every field the
+same text is kept as a pattern, where only unquoted characters can act
+as wildcards. The unquoted text of the word in `${name:-word}` is split
+as well, which `splits_literals` is set for. */
+struct Fields {
+    fields: Vec<String>,
+    patterns: Vec<Option<String>>,
+    current: String,
+    current_pattern: String,
+    has_wildcards: bool,
+    has_current: bool,
+    after_whitespace_delimiter: bool,
+    only_empty_list: bool,
+    splits_literals: bool,
+    syntax: PatternSyntax,
+}
+
+impl Fields {
+    fn new() -> Fields {
+        Fields::with_syntax(PatternSyntax::Glob)
+    }
+
+    fn with_syntax(syntax: PatternSyntax) -> Fields {
+        Fields {
+            fields: Vec::new(),
+            patterns: Vec::new(),
+            current: String::new(),
+            current_pattern: String::new(),
+            has_wildcards: false,
+            has_current: false,
+            after_whitespace_delimiter: false,
+            only_empty_list: false,
+            splits_literals: false,
+            syntax,
+        }
+    }
+
+    fn push_literal(&mut self, string: &str) {
+        string
+            .chars()
+            .for_each(|character| self.push_char(character));
+        self.has_current = true;
+        self.after_whitespace_delimiter = false;
+    }
+
+    fn push_char(&mut self, character: char) {
+        self.current.push(character);
+        if self.syntax.is_special(character) {
+            self.current_pattern.push('\\');
+        }
+        self.current_pattern.push(character);
+        self.has_current = true;
+        self.after_whitespace_delimiter = false;
+    }
+
+    /* An unquoted character, which keeps its special meaning in
+    patterns. */
+    fn push_unquoted(&mut self, character: char) {
+        if character == '\\' {
+            return self.push_char(character);
+        }
+        self.current.push(character);
+        self.current_pattern.push(character);
+        self.has_wildcards |= self.syntax.is_wildcard(character);
+        self.has_current = true;
+        self.after_whitespace_delimiter = false;
+    }
+
+    fn end_field(&mut self) {
+        let field = self.current.split_off(0);
+        let pattern = self.current_pattern.split_off(0);
+        self.fields.push(field);
+        self.patterns.push(if self.has_wildcards {
+            Some(pattern)
+        } else {
+            None
+        });
+        self.has_wildcards = false;
+        self.has_current = false;
+    }
+
+    /* Whitespace characters in IFS are collapsed and never produce empty
+    fields, while every other IFS character ends exactly one field. */
+    fn push_split(&mut self, string: &str, ifs: &str) {
+        for character in string.chars() {
+            if !ifs.contains(character) {
+                self.push_unquoted(character);
+            } else if character == ' ' || character == '\t' || character == '\n' {
+                if self.has_current {
+                    self.end_field();
+                    self.after_whitespace_delimiter = true;
+                }
+            } else {
+                if self.has_current || !self.after_whitespace_delimiter {
+                    self.end_field();
+                }
+                self.after_whitespace_delimiter = false;
+            }
+        }
+    }
+
+    /* Quoted `"$@"` and `"${array[@]}"` produce no field at all when
+    they are empty and nothing else was added to the field. */
+    fn push_empty_list(&mut self) {
+        self.only_empty_list = self.current.is_empty();
+    }
+
+    fn end_word(&mut self) {
+        if self.has_current && !(self.only_empty_list && self.current.is_empty()) {
+            self.end_field();
+        }
+    }
+
+    fn finish(mut self) -> Vec<String> {
+        self.end_word();
+        self.fields
+    }
+
+    fn finish_patterns(mut self) -> Vec<String> {
+        self.end_word();
+        let syntax = self.syntax;
+        self.fields
+            .iter()
+            .zip(self.patterns)
+            .map(|(field, pattern)| pattern.unwrap_or_else(|| syntax.escape(field)))
+            .collect()
+    }
+
+    /* Replaces every field containing wildcards by the paths matching
+    it. A pattern that matches nothing is kept as it was written, or
+    left out with nullglob. With noglob nothing is replaced. */
+    fn finish_globbed(mut self, options: &Options) -> Vec<String> {
+        self.end_word();
+        if options.noglob {
+            return self.fields;
+        }
+        let mut result: Vec<String> = Vec::new();
+        for (field, pattern) in self.fields.into_iter().zip(self.patterns) {
+            let mut paths = match pattern {
+                Some(pattern) => glob(&pattern, options.dotglob),
+                None => {
+                    result.push(field);
+                    continue;
+                }
+            };
+            if paths.is_empty() && !options.nullglob {
+                result.push(field);
+            } else {
+                result.append(&mut paths);
+            }
+        }
+        result
+    }
+}
+
+/* Expands every word of a command into the final list of arguments. */
+pub fn expand_words(shell: &mut Shell, words: &[String]) -> Result<Vec<String>, ShellError> {
+    let mut arguments: Vec<String> = Vec::new();
+    for word in words.iter().flat_map(|word| expand_braces(word)) {
+        let mut fields = Fields::new();
+        expand_word_into(shell, &word, &mut fields, true)?;
+        arguments.append(&mut fields.finish_globbed(&shell.options));
+    }
+    Ok(arguments)
+}
+
+/* Builtins that take assignments as arguments. Their assignment
+arguments are expanded like real assignments, without field splitting
+or pathname expansion. */
+const DECLARATION_COMMANDS: [&str; 4] = ["declare", "typeset", "local", "export"];
+
+/* Expands the words of a simple command into its arguments. Array
+assignments given to declaration commands can't be passed as a single
+argument, so only their name is, and the assignments are returned to be
+performed once the builtin has declared the variables. */
+pub fn expand_command_words(
+    shell: &mut Shell,
+    words: &[String],
+) -> Result<(Vec<String>, Vec<Assignment>), ShellError> {
+    let mut arguments: Vec<String> = Vec::new();
+    let mut assignments: Vec<Assignment> = Vec::new();
+    for (index, word) in words.iter().enumerate() {
+        let is_declaration = arguments
+            .first()
+            .is_some_and(|name| DECLARATION_COMMANDS.contains(&name.as_str()));
+        match parse_assignment(word) {
+            Some(assignment) if index > 0 && is_declaration => match assignment {
+                Assignment {
+                    subscript: None,
+                    append: false,
+                    value: AssignmentValue::Scalar(ref value),
+                    ..
+                } => {
+                    let value = expand_word_to_string(shell, value)?;
+                    arguments.push(format!("{}={}", assignment.name, value));
+                }
+                _ => {
+                    arguments.push(assignment.name.clone());
+                    assignments.push(assignment);
+                }
+            },
+            _ => arguments.append(&mut expand_words(shell, &words[index..(index + 1)])?),
+        }
+    }
+    Ok((arguments, assignments))
+}
+
+/* Expands a word where the result has to stay a single string, like the
+value of an assignment or the target of a redirection. */
+pub fn expand_word_to_string(shell: &mut Shell, word: &str) -> Result<String, ShellError> {
+    let mut fields = Fields::new();
+    expand_word_into(shell, word, &mut fields, false)?;
+    Ok(fields.finish().join(" "))
+}
+
+/* Expands the pattern of a `case` item. Quoted characters only match
+themselves, while wildcards in unquoted text and unquoted expansions
+keep their meaning. */
+pub fn expand_word_to_pattern(shell: &mut Shell, word: &str) -> Result<String, ShellError> {
+    let mut fields = Fields::new();
+    expand_word_into(shell, word, &mut fields, false)?;
+    Ok(fields.finish_patterns().join(" "))
+}
+
+/* Expands the right side of `=~` the same way, with quoted characters
+escaped for a regular expression instead. */
+pub fn expand_word_to_regex(shell: &mut Shell, word: &str) -> Result<String, ShellError> {
+    let mut fields = Fields::with_syntax(PatternSyntax::Regex);
+    expand_word_into(shell, word, &mut fields, false)?;
+    Ok(fields.finish_patterns().join(" "))
+}
+
+fn expand_word_into(
+    shell: &mut Shell,
+    word: &str,
+    fields: &mut Fields,
+    split: bool,
+) -> Result<(), ShellError> {
+    let characters: Vec<char> = word.chars().collect();
+    let mut position = 0;
+    let mut in_double_quotes = false;
+    if characters.first() == Some(&'~') {
+        position = expand_tilde(shell, &characters, fields);
+    }
+    while position < characters.len() {
+        let character = characters[position];
+        position += 1;
+        match character {
+            '\\' => {
+                if let Some(&escaped) = characters.get(position) {
+                    position += 1;
+                    if in_double_quotes && !"$`\"\\\n".contains(escaped) {
+                        fields.push_char('\\');
+                    }
+                    fields.push_char(escaped);
+                }
+            }
+            '\'' if !in_double_quotes => {
+                let mut quoted = String::new();
+                while position < characters.len() && characters[position] != '\'' {
+                    quoted.push(characters[position]);
+                    position += 1;
+                }
+                position += 1;
+                fields.push_literal(&quoted);
+            }
+            '\"' => {
+                in_double_quotes = !in_double_quotes;
+                fields.push_literal("");
+            }
+            '$' | '`' => {
+                let (value, consumed) = if character == '`' {
+                    let (output, consumed) = expand_backquoted(shell, &characters[position..])?;
+                    (Some(Expanded::Single(output)), consumed)
+                } else {
+                    expand_parameter(shell, &characters[position..], in_double_quotes)?
+                };
+                position += consumed;
+                match value {
+                    Some(Expanded::Word(word)) => {
+                        let splits_literals = mem::replace(&mut fields.splits_literals, split);
+                        expand_word_into(shell, &word, fields, split)?;
+                        fields.splits_literals = splits_literals;
+                    }
+                    Some(value) => push_expanded(shell, fields, value, in_double_quotes, split),
+                    None if in_double_quotes => fields.push_char('$'),
+                    None => fields.push_unquoted('$'),
+                }
+            }
+            '<' | '>' if !in_double_quotes && characters.get(position) == Some(&'(') => {
+                let end = match find_closing_paren(&characters, position) {
+                    Some(end) => end,
+                    None => return Err(ShellError::BadSubstitution(word.to_string())),
+                };
+                let command: String = characters[(position + 1)..end].iter().collect();
+                position = end + 1;
+                let path = start_process_substitution(shell, &command, character == '<')?;
+                fields.push_literal(&path);
+            }
+            _ if in_double_quotes => fields.push_char(character),
+            _ if fields.splits_literals => {
+                let ifs = shell.get_variable("IFS").unwrap_or(" \t\n").to_string();
+                fields.push_split(&character.to_string(), &ifs);
+            }
+            _ => fields.push_unquoted(character),
+        }
+    }
+    Ok(())
+}
+
+/* Adds the result of an expansion to the fields. Inside double quotes
+the values of a list stay separate fields, while unquoted values are
+split on IFS unless the word has to stay a single string. */
+fn push_expanded(
+    shell: &Shell,
+    fields: &mut Fields,
+    value: Expanded,
+    in_double_quotes: bool,
+    split: bool,
+) {
+    let values = match value {
+        Expanded::Multiple(ref values) if in_double_quotes && split => {
+            if values.is_empty() {
+                fields.push_empty_list();
+            }
+            for (index, value) in values.iter().enumerate() {
+                if index > 0 {
+                    fields.end_field();
+                }
+                fields.push_literal(value);
+            }
+            return;
+        }
+        Expanded::Single(value) | Expanded::Word(value) => vec![value],
+        Expanded::Multiple(_) | Expanded::Joined(_) if in_double_quotes || !split => {
+            vec![value.join(shell)]
+        }
+        Expanded::Multiple(values) | Expanded::Joined(values) => values,
+    };
+    let ifs = shell.get_variable("IFS").unwrap_or(" \t\n").to_string();
+    for (index, value) in values.iter().enumerate() {
+        if in_double_quotes {
+            fields.push_literal(value);
+        } else if !split {
+            value.chars().for_each(|c| fields.push_unquoted(c));
+        } else {
+            if index > 0 && fields.has_current {
+                fields.end_field();
+            }
+            fields.push_split(value, &ifs);
+        }
+    }
+}
+
+/* Brace expansion turns `a{b,c}d` into `abd acd` and `{1..3}` into
+`1 2 3`. It works on the raw word before any other expansion, and
+braces that are quoted or part of `${...}` are left alone. */
+pub fn expand_braces(word: &str) -> Vec<String> {
+    let characters: Vec<char> = word.chars().collect();
+    let mut position = 0;
+    while position < characters.len() {
+        match characters[position] {
+            '{' => {
+                if let Some((end, alternatives)) = parse_brace(&characters, position) {
+                    let prefix: String = characters[..position].iter().collect();
+                    let suffix: String = characters[(end + 1)..].iter().collect();
+                    return alternatives
+                        .iter()
+                        .flat_map(|alternative| {
+                            expand_braces(&format!("{}{}{}", prefix, alternative, suffix))
+                        })
+                        .collect();
+                }
+                position += 1;
+            }
+            _ => position = skip_quoted(&characters, position),
+        }
+    }
+    vec![word.to_string()]
+}
+
+/* Returns the position right after the character at `position`, or
+after the whole quoted section or substitution starting there. */
+fn skip_quoted(characters: &[char], position: usize) -> usize {
+    let closing = match (characters[position], characters.get(position + 1)) {
+        ('\\', _) => return position + 2,
+        ('\'', _) => '\'',
+        ('\"', _) => '\"',
+        ('`', _) => '`',
+        ('$', Some('(')) => {
+            return match find_closing_paren(characters, position + 1) {
+                Some(end) => end + 1,
+                None => characters.len(),
+            }
+        }
+        ('$', Some('{')) => '}',
+        _ => return position + 1,
+    };
+    let mut end = position + 1;
+    while end < characters.len() && characters[end] != closing {
+        if characters[end] == '\\' && closing != '\'' {
+            end += 1;
+        }
+        end += 1;
+    }
+    end + 1
+}
+
+/* Parses the brace expression opening at `open`. Returns the position
+of the closing brace and the alternatives, or None if the braces don't
+form a list or a sequence and are kept literally. */
+fn parse_brace(characters: &[char], open: usize) -> Option<(usize, Vec<String>)> {
+    let mut alternatives: Vec<String> = Vec::new();
+    let mut start = open + 1;
+    let mut depth = 0;
+    let mut position = open + 1;
+    while position < characters.len() {
+        match characters[position] {
+            '{' => depth += 1,
+            '}' if depth > 0 => depth -= 1,
+            ',' if depth == 0 => {
+                alternatives.push(characters[start..position].iter().collect());
+                start = position + 1;
+            }
+            '}' => {
+                let last: String = characters[start..position].iter().collect();
+                if !alternatives.is_empty() {
+                    alternatives.push(last);
+                    return Some((position, alternatives));
+                }
+                return parse_sequence(&last).map(|sequence| (position, sequence));
+            }
+            _ => {
+                position = skip_quoted(characters, position);
+                continue;
+            }
+        }
+        position += 1;
+    }
+    None
+}
+
+/* Parses the inside of `{start..end}` or `{start..end..step}`, where
+start and end are both integers or both single letters. */
+fn parse_sequence(text: &str) -> Option<Vec<String>> {
+    let parts: Vec<&str> = text.split("..").collect();
+    if parts.len() != 2 && parts.len() != 3 {
+        return None;
+    }
+    let step = match parts.get(2) {
+        Some(step) => step.parse::<i64>().ok()?.abs().max(1),
+        None => 1,
+    };
+    let (start, end, is_letter) = match (parts[0].parse::<i64>(), parts[1].parse::<i64>()) {
+        (Ok(start), Ok(end)) => (start, end, false),
+        _ => {
+            let mut start = parts[0].chars();
+            let mut end = parts[1].chars();
+            match (start.next(), start.next(), end.next(), end.next()) {
+                (Some(start), None, Some(end), None)
+                    if start.is_ascii_alphabetic() && end.is_ascii_alphabetic() =>
+                {
+                    (start as i64, end as i64, true)
+                }
+                _ => return None,
+            }
+        }
+    };
+    let is_padded = |part: &str| part.trim_start_matches('-').starts_with('0') && part.len() > 1;
+    let width = if !is_letter && (is_padded(parts[0]) || is_padded(parts[1])) {
+        parts[0].len().max(parts[1].len())
+    } else {
+        0
+    };
+    let mut sequence: Vec<String> = Vec::new();
+    let mut value = start;
+    while (start <= end && value <= end) || (start > end && value >= end) {
+        if is_letter {
+            sequence.push((value as u8 as char).to_string());
+        } else if value < 0 {
+            sequence.push(format!(
+                "-{:0width$}",
+                -value,
+                width = width.saturating_sub(1)
+            ));
+        } else {
+            sequence.push(format!("{:0width$}", value, width = width));
+        }
+        value += if start <= end { step } else { -step };
+    }
+    Some(sequence)
+}
+
+/* Runs the command between backquotes, where a backslash only escapes
+`$`, another backquote or a backslash. Returns the output along with
+the number of characters consumed, including the closing backquote. */
+fn expand_backquoted(
+    shell: &mut Shell,
+    characters: &[char],
+) -> Result<(String, usize), ShellError> {
+    let mut command = String::new();
+    let mut position = 0;
+    while position < characters.len() {
+        match (characters[position], characters.get(position + 1)) {
+            ('`', _) => {
+                let output = capture_command_output(shell, &command)?;
+                return Ok((output, position + 1));
+            }
+            ('\\', Some(&escaped)) if "$`\\".contains(escaped) => {
+                command.push(escaped);
+                position += 2;
+            }
+            (character, _) => {
+                command.push(character);
+                position += 1;
+            }
+        }
+    }
+    Err(ShellError::BadSubstitution(format!("`{}", command)))
+}
+
+/* Finds the parenthesis that closes the one at `open`, skipping over
+nested parentheses and quoted text. */
+fn find_closing_paren(characters: &[char], open: usize) -> Option<usize> {
+    let mut depth = 0;
+    let mut position = open;
+    let mut in_double_quotes = false;
+    while position < characters.len() {
+        match characters[position] {
+            '\\' => position += 1,
+            '\'' if !in_double_quotes => {
+                position += 1;
+                while position < characters.len() && characters[position] != '\'' {
+                    position += 1;
+                }
+            }
+            '\"' => in_double_quotes = !in_double_quotes,
+            '(' if !in_double_quotes => depth += 1,
+            ')' if !in_double_quotes => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(position);
+                }
+            }
+            _ => (),
+        }
+        position += 1;
+    }
+    None
+}
+
+/* A leading `~` is replaced by the home directory, `~+` by the current
+directory, `~-` by the previous one and `~N`, `~+N` or `~-N` by an entry
+of the directory stack. Returns how many characters were consumed. */
+fn expand_tilde(shell: &Shell, characters: &[char], fields: &mut Fields) -> usize {
+    let length = characters
+        .iter()
+        .position(|&c| c == '/')
+        .unwrap_or(characters.len());
+    let prefix: String = characters[1..length].iter().collect();
+    let directory = match prefix.as_str() {
+        "" => shell.get_variable("HOME").map(|home| home.to_string()),
+        "+" => shell.get_variable("PWD").map(|pwd| pwd.to_string()),
+        "-" => shell
+            .get_variable("OLDPWD")
+            .map(|old_pwd| old_pwd.to_string()),
+        _ => directory_stack_entry(shell, &prefix),
+    };
+    match directory {
+        Some(directory) => {
+            fields.push_literal(&directory);
+            length
+        }
+        None => 0,
+    }
+}
+
+/* What a `$` expansion produces. `$@` and arrays subscripted with `@`
+expand to several values, which `$*` and `[*]` join into a single one
+inside double quotes. An unquoted `${name:-word}` gives back its word,
+which is expanded in place so that quotes in it still prevent field
+splitting. */
+enum Expanded {
+    Single(String),
+    Multiple(Vec<String>),
+    Joined(Vec<String>),
+    Word(String),
+}
+
+impl Expanded {
+    fn join(self, shell: &Shell) -> String {
+        match self {
+            Expanded::Single(value) | Expanded::Word(value) => value,
+            Expanded::Multiple(values) => values.join(" "),
+            Expanded::Joined(values) => {
+                let separator: String = shell
+                    .get_variable("IFS")
+                    .unwrap_or(" ")
+                    .chars()
+                    .take(1)
+                    .collect();
+                values.join(&separator)
+            }
+        }
+    }
+}
+
+/* Expands the parameter, command substitution or arithmetic expansion
+following a `$`. Returns the value, or None if the `$` doesn't start an
+expansion and should be kept literally, along with the number of
+characters that were consumed. */
+fn expand_parameter(
+    shell: &mut Shell,
+    characters: &[char],
+    quoted: bool,
+) -> Result<(Option<Expanded>, usize), ShellError> {
+    match characters.first() {
+        Some('(') => {
+            let end = match find_closing_paren(characters, 0) {
+                Some(end) => end,
+                None => {
+                    let word: String = characters.iter().collect();
+                    return Err(ShellError::BadSubstitution(format!("${}", word)));
+                }
+            };
+            let value = if characters.get(1) == Some(&'(')
+                && find_closing_paren(characters, 1) == Some(end - 1)
+            {
+                let expression: String = characters[2..(end - 1)].iter().collect();
+                expand_arithmetic(shell, &expression)?.to_string()
+            } else {
+                let command: String = characters[1..end].iter().collect();
+                capture_command_output(shell, &command)?
+            };
+            Ok((Some(Expanded::Single(value)), end + 1))
+        }
+        Some('{') => {
+            let end = match find_closing_brace(characters) {
+                Some(end) => end,
+                None => {
+                    let word: String = characters.iter().collect();
+                    return Err(ShellError::BadSubstitution(format!("${}", word)));
+                }
+            };
+            let expression: String = characters[1..end].iter().collect();
+            Ok((Some(expand_braced(shell, &expression, quoted)?), end + 1))
+        }
+        Some(character) if *character == '_' || character.is_ascii_alphabetic() => {
+            let length = characters
+                .iter()
+                .take_while(|c| **c == '_' || c.is_ascii_alphanumeric())
+                .count();
+            let name: String = characters[..length].iter().collect();
+            let value = lookup_parameter(shell, &name);
+            Ok((
+                Some(Expanded::Single(require_set(shell, &name, value)?)),
+                length,
+            ))
+        }
+        Some('@') => Ok((
+            Some(Expanded::Multiple(shell.positional_parameters.clone())),
+            1,
+        )),
+        Some('*') => Ok((
+            Some(Expanded::Joined(shell.positional_parameters.clone())),
+            1,
+        )),
+        Some(character) if character.is_ascii_digit() || "?$!#-".contains(*character) => {
+            let name = characters[0].to_string();
+            let value = lookup_parameter(shell, &name);
+            Ok((Some(Expanded::Single(require_set(shell, &name, value)?)), 1))
+        }
+        _ => Ok((None, 0)),
+    }
+}
+
+/* Finds the brace closing the `${` at the start of `characters`, where
+the subscript may itself contain expansions. */
+fn find_closing_brace(characters: &[char]) -> Option<usize> {
+    let mut depth = 0;
+    let mut position = 0;
+    while position < characters.len() {
+        match characters[position] {
+            '\\' => position += 1,
+            '\'' => {
+                position += 1;
+                while position < characters.len() && characters[position] != '\'' {
+                    position += 1;
+                }
+            }
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(position);
+                }
+            }
+            _ => (),
+        }
+        position += 1;
+    }
+    None
+}
+
+/* Expands the inside of `${...}`: a parameter with an optional array
+subscript, preceded by `#` for its length or by `!` for the keys of an
+array, and followed by an optional `:offset:length` slice. */
+fn expand_braced(
+    shell: &mut Shell,
+    expression: &str,
+    quoted: bool,
+) -> Result<Expanded, ShellError> {
+    let bad_substitution = || ShellError::BadSubstitution(format!("${{{}}}", expression));
+    let (operator, rest) = match expression.chars().next() {
+        Some(operator) if (operator == '#' || operator == '!') && expression.len() > 1 => {
+            (Some(operator), &expression[1..])
+        }
+        _ => (None, expression),
+    };
+    let name_length = parameter_name_length(rest);
+    if name_length == 0 {
+        return Err(bad_substitution());
+    }
+    let name = &rest[..name_length];
+    let mut rest = &rest[name_length..];
+    let mut subscript = None;
+    if rest.starts_with('[') {
+        let end = find_closing_bracket(rest).ok_or_else(bad_substitution)?;
+        subscript = Some(&rest[1..end]);
+        rest = &rest[(end + 1)..];
+    }
+    if let Some((colon, kind, word)) = split_operation(rest) {
+        if operator.is_some() || (subscript.is_some() && !is_valid_name(name)) {
+            return Err(bad_substitution());
+        }
+        let base = &expression[..(expression.len() - rest.len())];
+        let operation = Operation {
+            colon,
+            operator: kind,
+            word,
+            quoted,
+        };
+        return expand_operation(shell, base, name, subscript, operation);
+    }
+    let slice = match rest.strip_prefix(':') {
+        Some(slice) if operator.is_none() && !slice.starts_with(&['-', '=', '+', '?'][..]) => {
+            Some(slice)
+        }
+        _ if rest.is_empty() => None,
+        _ => return Err(bad_substitution()),
+    };
+    if subscript.is_some() && !is_valid_name(name) {
+        return Err(bad_substitution());
+    }
+    let is_list = match subscript {
+        Some(subscript) => subscript == "@" || subscript == "*",
+        None => name == "@" || name == "*",
+    };
+    if !is_list {
+        if operator == Some('!') {
+            return Err(bad_substitution());
+        }
+        let value = lookup_element(shell, name, subscript)?;
+        let value = match subscript {
+            Some(subscript) => require_set(shell, &format!("{}[{}]", name, subscript), value)?,
+            None => require_set(shell, name, value)?,
+        };
+        return match (operator, slice) {
+            (Some(_), _) => Ok(Expanded::Single(value.chars().count().to_string())),
+            (None, Some(slice)) => Ok(Expanded::Single(slice_string(shell, &value, slice)?)),
+            (None, None) => Ok(Expanded::Single(value)),
+        };
+    }
+    let mut elements: Vec<(usize, String, String)> = match (subscript, shell.get_value(name)) {
+        (None, _) => iter::once(&shell.script_name)
+            .chain(shell.positional_parameters.iter())
+            .enumerate()
+            .map(|(index, value)| (index, index.to_string(), value.clone()))
+            .collect(),
+        (Some(_), Some(Value::Indexed(elements))) => elements
+            .iter()
+            .map(|(index, value)| (*index, index.to_string(), value.clone()))
+            .collect(),
+        (Some(_), Some(value)) => value
+            .keys()
+            .into_iter()
+            .zip(value.elements())
+            .enumerate()
+            .map(|(index, (key, value))| (index, key, value))
+            .collect(),
+        (Some(_), None) => Vec::new(),
+    };
+    match slice {
+        Some(slice) => elements = slice_elements(shell, elements, slice)?,
+        None if subscript.is_none() => {
+            elements.remove(0);
+        }
+        None => (),
+    }
+    let values: Vec<String> = elements
+        .into_iter()
+        .map(|(_, key, value)| if operator == Some('!') { key } else { value })
+        .collect();
+    let is_joined = subscript.map_or(name == "*", |subscript| subscript == "*");
+    Ok(match operator {
+        Some('#') => Expanded::Single(values.len().to_string()),
+        _ if is_joined => Expanded::Joined(values),
+        _ => Expanded::Multiple(values),
+    })
+}
+
+/* The `:-word` and the like that can follow a parameter, with whether
+the expansion was inside double quotes, where the word is too. */
+struct Operation<'a> {
+    colon: bool,
+    operator: char,
+    word: &'a str,
+    quoted: bool,
+}
+
+/* Splits such an operation into whether there was a colon, the operator
+and the word. */
+fn split_operation(text: &str) -> Option<(bool, char, &str)> {
+    let (colon, text) = match text.strip_prefix(':') {
+        Some(text) => (true, text),
+        None => (false, text),
+    };
+    match text.chars().next() {
+        Some(operator) if "-=+?".contains(operator) => Some((colon, operator, &text[1..])),
+        _ => None,
+    }
+}
+
+/* `${name-word}` and `${name=word}` use, and assign, the word when the
+parameter is unset, `${name+word}` when it is set, and `${name?word}`
+fails with the word as the message when it is unset. With a colon a
+parameter that is set but empty counts as unset too. None of them are
+affected by nounset. */
+fn expand_operation(
+    shell: &mut Shell,
+    base: &str,
+    name: &str,
+    subscript: Option<&str>,
+    operation: Operation,
+) -> Result<Expanded, ShellError> {
+    let is_list = match subscript {
+        Some(subscript) => subscript == "@" || subscript == "*",
+        None => name == "@" || name == "*",
+    };
+    let value = if is_list {
+        match expand_braced(shell, base, operation.quoted)? {
+            Expanded::Multiple(ref values) | Expanded::Joined(ref values) if values.is_empty() => {
+                None
+            }
+            expanded => Some(expanded),
+        }
+    } else {
+        lookup_element(shell, name, subscript)?.map(Expanded::Single)
+    };
+    let is_unset = match value {
+        Some(Expanded::Single(ref value)) | Some(Expanded::Word(ref value)) => {
+            operation.colon && value.is_empty()
+        }
+        Some(Expanded::Multiple(ref values)) | Some(Expanded::Joined(ref values)) => {
+            operation.colon && values.iter().all(|value| value.is_empty())
+        }
+        None => true,
+    };
+    let word = if operation.quoted {
+        format!("\"{}\"", operation.word)
+    } else {
+        operation.word.to_string()
+    };
+    let alternative = |shell: &mut Shell, word: String| {
+        if operation.quoted {
+            Ok(Expanded::Single(expand_word_to_string(shell, &word)?))
+        } else {
+            Ok(Expanded::Word(word))
+        }
+    };
+    match (operation.operator, value) {
+        ('-', Some(value)) if !is_unset => Ok(value),
+        ('-', _) => alternative(shell, word),
+        ('+', _) if is_unset => Ok(Expanded::Single(String::new())),
+        ('+', _) => alternative(shell, word),
+        ('=', Some(value)) if !is_unset => Ok(value),
+        ('=', _) => {
+            if is_list || !is_valid_name(name) {
+                return Err(ShellError::CannotAssign(format!("${}", base)));
+            }
+            let assignment = Assignment {
+                name: name.to_string(),
+                subscript: subscript.map(|subscript| subscript.to_string()),
+                append: false,
+                value: AssignmentValue::Scalar(word),
+            };
+            assign(shell, &assignment)?;
+            let value = lookup_element(shell, name, subscript)?;
+            Ok(Expanded::Single(value.unwrap_or_default()))
+        }
+        (_, Some(value)) if !is_unset => Ok(value),
+        _ => {
+            let message = match expand_word_to_string(shell, &word)?.as_str() {
+                "" if operation.colon => "parameter null or not set".to_string(),
+                "" => "parameter not set".to_string(),
+                message => message.to_string(),
+            };
+            if !shell.interactive {
+                shell.control_flow = ControlFlow::Exit(1);
+            }
+            Err(ShellError::ParameterNotSet(base.to_string(), message))
+        }
+    }
+}
+
+/* The length of the parameter name at the start of `text`: a variable
+name, a positional parameter or a special parameter. */
+fn parameter_name_length(text: &str) -> usize {
+    match text.chars().next() {
+        Some(character) if character == '_' || character.is_ascii_alphabetic() => text
+            .chars()
+            .take_while(|c| *c == '_' || c.is_ascii_alphanumeric())
+            .count(),
+        Some(character) if character.is_ascii_digit() => {
+            text.chars().take_while(|c| c.is_ascii_digit()).count()
+        }
+        Some(character) if "?$!#@*-".contains(character) => 1,
+        _ => 0,
+    }
+}
+
+/* Finds the bracket closing the subscript that `text` starts with. */
+pub fn find_closing_bracket(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (position, character) in text.char_indices() {
+        match character {
+            '[' => depth += 1,
+            ']' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(position);
+                }
+            }
+            _ => (),
+        }
+    }
+    None
+}
+
+/* Looks up a parameter or a single array element. Associative arrays
+take any string as a key, other variables an arithmetic index, with a
+scalar being the array of its single value. */
+pub fn lookup_element(
+    shell: &mut Shell,
+    name: &str,
+    subscript: Option<&str>,
+) -> Result<Option<String>, ShellError> {
+    let subscript = match subscript {
+        Some(subscript) => subscript,
+        None => return Ok(lookup_parameter(shell, name)),
+    };
+    if let Some(Value::Associative(_)) = shell.get_value(name) {
+        let key = expand_word_to_string(shell, subscript)?;
+        return Ok(match shell.get_value(name) {
+            Some(Value::Associative(elements)) => elements.get(&key).cloned(),
+            _ => None,
+        });
+    }
+    let index = expand_arithmetic(shell, subscript)?;
+    match shell.get_value(name) {
+        Some(Value::Indexed(elements)) => {
+            let index = resolve_index(name, subscript, index, elements)?;
+            Ok(elements.get(&index).cloned())
+        }
+        Some(Value::Scalar(value)) if index == 0 || index == -1 => Ok(Some(value.clone())),
+        Some(Value::Scalar(_)) | None if index >= 0 => Ok(None),
+        _ => Err(ShellError::BadArraySubscript(format!(
+            "{}[{}]",
+            name, subscript
+        ))),
+    }
+}
+
+/* Splits the `offset:length` of a slice and evaluates both parts. */
+fn parse_slice(shell: &mut Shell, slice: &str) -> Result<(i64, Option<i64>), ShellError> {
+    let (offset, length) = match slice.find(':') {
+        Some(position) => (&slice[..position], Some(&slice[(position + 1)..])),
+        None => (slice, None),
+    };
+    let offset = expand_arithmetic(shell, offset)?;
+    let length = match length {
+        Some(length) => Some(expand_arithmetic(shell, length)?),
+        None => None,
+    };
+    Ok((offset, length))
+}
+
+/* `${name:offset:length}` counts characters. A negative offset counts
+back from the end, and a negative length leaves that many characters
+off the end. */
+fn slice_string(shell: &mut Shell, value: &str, slice: &str) -> Result<String, ShellError> {
+    let (offset, length) = parse_slice(shell, slice)?;
+    let characters: Vec<char> = value.chars().collect();
+    let count = characters.len() as i64;
+    let start = if offset < 0 { count + offset } else { offset };
+    if start < 0 || start > count {
+        return Ok(String::new());
+    }
+    let end = match length {
+        Some(length) if length < 0 => count + length,
+        Some(length) => (start + length).min(count),
+        None => count,
+    };
+    if end < start {
+        return Err(ShellError::Arithmetic(
+            slice.to_string(),
+            "substring expression < 0".to_string(),
+        ));
+    }
+    Ok(characters[(start as usize)..(end as usize)]
+        .iter()
+        .collect())
+}
+
+/* Slices of arrays and of `$@` count indexes rather than positions, so
+the slice of a sparse array starts at the first element whose index is
+at least the offset. A negative offset counts back from the end. */
+fn slice_elements(
+    shell: &mut Shell,
+    elements: Vec<(usize, String, String)>,
+    slice: &str,
+) -> Result<Vec<(usize, String, String)>, ShellError> {
+    let (offset, length) = parse_slice(shell, slice)?;
+    let end = elements.last().map_or(0, |element| element.0 as i64 + 1);
+    let start = if offset < 0 { end + offset } else { offset };
+    let length = match length {
+        Some(length) if length < 0 => {
+            return Err(ShellError::Arithmetic(
+                slice.to_string(),
+                "substring expression < 0".to_string(),
+            ))
+        }
+        Some(length) => length as usize,
+        None => elements.len(),
+    };
+    if start < 0 {
+        return Ok(Vec::new());
+    }
+    Ok(elements
+        .into_iter()
+        .filter(|element| element.0 as i64 >= start)
+        .take(length)
+        .collect())
+}
+
+/* The value of a parameter that is about to be expanded. With nounset
+an unset one is an error, which also ends a shell that isn't
+interactive. */
+fn require_set(shell: &mut Shell, name: &str, value: Option<String>) -> Result<String, ShellError> {
+    match value {
+        Some(value) => Ok(value),
+        None if shell.options.nounset => {
+            if !shell.interactive {
+                shell.control_flow = ControlFlow::Exit(1);
+            }
+            let name = if name.chars().all(|c| c.is_ascii_digit()) {
+                format!("${}", name)
+            } else {
+                name.to_string()
+            };
+            Err(ShellError::UnboundVariable(name))
+        }
+        None => Ok(String::new()),
+    }
+}
+
+fn lookup_parameter(shell: &Shell, name: &str) -> Option<String> {
+    match name {
+        "?" => Some(shell.last_status.to_string()),
+        "-" if shell.interactive => Some(shell.options.flags() + "i"),
+        "-" => Some(shell.options.flags()),
+        "$" => Some(shell.shell_pid.to_string()),
+        "!" => shell.last_background_pid.map(|pid| pid.to_string()),
+        "#" => Some(shell.positional_parameters.len().to_string()),
+        "0" => Some(shell.script_name.clone()),
+        _ if !name.is_empty() && name.chars().all(|c| c.is_ascii_digit()) => {
+            let index = name.parse::<usize>().unwrap_or(0);
+            let parameter = index
+                .checked_sub(1)
+                .and_then(|index| shell.positional_parameters.get(index));
+            parameter.cloned()
+        }
+        _ => shell.get_variable(name).map(|value| value.to_string()),
+    }
+}
+
+/* The body of an unquoted here-document is expanded as if it were in
+double quotes, except that double quotes themselves are not special. */
+pub fn expand_here_document(shell: &mut Shell, body: &str) -> Result<String, ShellError> {
+    let characters: Vec<char> = body.chars().collect();
+    let mut result = String::new();
+    let mut position = 0;
+    while position < characters.len() {
+        let character = characters[position];
+        position += 1;
+        match character {
+            '\\' => match characters.get(position) {
+                Some('\n') => position += 1,
+                Some(&escaped) if "$`\\".contains(escaped) => {
+                    result.push(escaped);
+                    position += 1;
+                }
+                _ => result.push('\\'),
+            },
+            '$' => {
+                let (value, consumed) = expand_parameter(shell, &characters[position..], true)?;
+                position += consumed;
+                match value {
+                    Some(value) => result.push_str(&value.join(shell)),
+                    None => result.push('$'),
+                }
+            }
+            '`' => {
+                let (output, consumed) = expand_backquoted(shell, &characters[position..])?;
+                position += consumed;
+                result.push_str(&output);
+            }
+            _ => result.push(character),
+        }
+    }
+    Ok(result)
+}
+
+/* Arithmetic expressions have their parameters and substitutions
+expanded the same way as the body of a here-document before they are
+evaluated. */
+pub fn expand_arithmetic(shell: &mut Shell, expression: &str) -> Result<i64, ShellError> {
+    let expression = expand_here_document(shell, expression)?;
+    evaluate_arithmetic(shell, &expression)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand(shell: &mut Shell, words: Vec<&str>) -> Vec<String> {
+        let words: Vec<String> = words.iter().map(|word| word.to_string()).collect();
+        expand_words(shell, &words).unwrap()
+    }
+
+    #[test]
+    fn expand_words_test() {
+        let mut shell = Shell::new();
+        shell.set_variable("x", "a  b".to_string());
+        shell.set_variable("empty", String::new());
+
+        assert_eq!(expand(&mut shell, vec!["echo", "'$x'"]), vec!["echo", "$x"]);
+        assert_eq!(expand(&mut shell, vec!["$x"]), vec!["a", "b"]);
+        assert_eq!(expand(&mut shell, vec!["\"$x\""]), vec!["a  b"]);
+        assert_eq!(expand(&mut shell, vec!["${x}c"]), vec!["a", "bc"]);
+        assert_eq!(expand(&mut shell, vec!["$empty"]), Vec::<String>::new());
+        assert_eq!(expand(&mut shell, vec!["\"$empty\""]), vec![""]);
+        assert_eq!(
+            expand(&mut shell, vec!["a\\ b", "\"\\$\\a\""]),
+            vec!["a b", "$\\a"]
+        );
+        assert_eq!(expand(&mut shell, vec!["$", "a$"]), vec!["$", "a$"]);
+
+        shell.last_status = 3;
+        assert_eq!(expand(&mut shell, vec!["$?"]), vec!["3"]);
+
+        shell.set_variable("HOME", "/home/me".to_string());
+        assert_eq!(
+            expand(&mut shell, vec!["~/a", "a~"]),
+            vec!["/home/me/a", "a~"]
+        );
+
+        assert!(expand_words(&mut shell, &["${x".to_string()]).is_err());
+        assert!(expand_words(&mut shell, &["${=}".to_string()]).is_err());
+    }
+
+    #[test]
+    fn positional_parameters_test() {
+        let mut shell = Shell::new();
+        assert_eq!(expand(&mut shell, vec!["\"$@\"", "$#"]), vec!["0"]);
+
+        shell.positional_parameters = vec!["a b".to_string(), "c".to_string()];
+        assert_eq!(
+            expand(&mut shell, vec!["$1", "${2}", "$3"]),
+            vec!["a", "b", "c"]
+        );
+        assert_eq!(expand(&mut shell, vec!["x\"$@\"y"]), vec!["xa b", "cy"]);
+        assert_eq!(expand(&mut shell, vec!["\"$*\"", "$#"]), vec!["a b c", "2"]);
+        assert_eq!(expand(&mut shell, vec!["$((1 + $# * 2))"]), vec!["5"]);
+    }
+
+    #[test]
+    fn array_expansion_test() {
+        let mut shell = Shell::new();
+        let mut elements = ::std::collections::BTreeMap::new();
+        elements.insert(0, "a b".to_string());
+        elements.insert(3, "c".to_string());
+        elements.insert(4, "d".to_string());
+        shell.set_value("a", Value::Indexed(elements));
+
+        assert_eq!(
+            expand(&mut shell, vec!["\"${a[@]}\""]),
+            vec!["a b", "c", "d"]
+        );
+        assert_eq!(
+            expand(&mut shell, vec!["${a[@]}"]),
+            vec!["a", "b", "c", "d"]
+        );
+        assert_eq!(expand(&mut shell, vec!["\"${a[*]}\""]), vec!["a b c d"]);
+        assert_eq!(
+            expand(&mut shell, vec!["${#a[@]}", "${!a[@]}"]),
+            vec!["3", "0", "3", "4"]
+        );
+        assert_eq!(
+            expand(&mut shell, vec!["\"${a[@]:1:1}\"", "${a[-1]}"]),
+            vec!["c", "d"]
+        );
+        assert_eq!(
+            expand(&mut shell, vec!["$a", "${#a}", "${a[1]}x"]),
+            vec!["a", "b", "3", "x"]
+        );
+        assert_eq!(
+            expand(&mut shell, vec!["\"${e[@]}\"", "x\"${e[@]}\""]),
+            vec!["x"]
+        );
+        assert_eq!(
+            expand_word_to_string(&mut shell, "${a[@]}").unwrap(),
+            "a b c d"
+        );
+
+        shell.set_variable("s", "hello".to_string());
+        assert_eq!(
+            expand(&mut shell, vec!["${s:1:3}", "${s: -2}", "${s:1:-1}"]),
+            vec!["ell", "lo", "ell"]
+        );
+        assert!(expand_words(&mut shell, &["${a[-9]}".to_string()]).is_err());
+    }
+
+    #[test]
+    fn parameter_operation_test() {
+        let mut shell = Shell::new();
+        shell.options.nounset = true;
+        shell.set_variable("e", String::new());
+        assert_eq!(
+            expand(&mut shell, vec!["${u:-a b}", "\"${e:-c d}\"", "[${e-x}]"]),
+            vec!["a", "b", "c d", "[]"]
+        );
+        assert_eq!(
+            expand(&mut shell, vec!["${u:-\"p  q\" r}", "${e:+x}", "${e+y}"]),
+            vec!["p  q", "r", "y"]
+        );
+        assert_eq!(expand(&mut shell, vec!["${n:=v}", "$n"]), vec!["v", "v"]);
+        assert!(expand_words(&mut shell, &["${1:=v}".to_string()]).is_err());
+        assert!(expand_words(&mut shell, &["${u:?}".to_string()]).is_err());
+        assert!(expand_words(&mut shell, &["$u".to_string()]).is_err());
+    }
+
+    #[test]
+    fn expand_braces_test() {
+        assert_eq!(expand_braces("a{b,c}d"), vec!["abd", "acd"]);
+        assert_eq!(expand_braces("{a,b{1..3}}"), vec!["a", "b1", "b2", "b3"]);
+        assert_eq!(expand_braces("{5..1..2}"), vec!["5", "3", "1"]);
+        assert_eq!(expand_braces("{08..10}"), vec!["08", "09", "10"]);
+        assert_eq!(expand_braces("{c..a}"), vec!["c", "b", "a"]);
+        assert_eq!(expand_braces("{a}"), vec!["{a}"]);
+        assert_eq!(expand_braces("{x{a,b}}"), vec!["{xa}", "{xb}"]);
+        assert_eq!(expand_braces("'{a,b}'"), vec!["'{a,b}'"]);
+        assert_eq!(expand_braces("${a}{\"x,\",y}"), vec!["${a}\"x,\"", "${a}y"]);
+    }
+
+    #[test]
+    fn find_closing_paren_test() {
+        let characters: Vec<char> = "<(a (b) ')' \")\" \\)) c".chars().collect();
+        assert_eq!(find_closing_paren(&characters, 1), Some(18));
+        let characters: Vec<char> = "<(a".chars().collect();
+        assert_eq!(find_closing_paren(&characters, 1), None);
+    }
+
+    #[test]
+    fn field_splitting_test() {
+        let mut shell = Shell::new();
+        shell.set_variable("IFS", " :".to_string());
+        shell.set_variable("x", " a : b::c: ".to_string());
+        assert_eq!(expand(&mut shell, vec!["$x"]), vec!["a", "b", "", "c"]);
+        assert_eq!(
+            expand(&mut shell, vec!["p$x"]),
+            vec!["p", "a", "b", "", "c"]
+        );
+    }
+
+    #[test]
+    fn expand_word_to_string_test() {
+        let mut shell = Shell::new();
+        shell.set_variable("x", "a  b".to_string());
+        assert_eq!(expand_word_to_string(&mut shell, "$x").unwrap(), "a  b");
+        assert_eq!(expand_word_to_string(&mut shell, "'it''s'").unwrap(), "its");
+    }
+
+    #[test]
+    fn expand_word_to_pattern_test() {
+        let mut shell = Shell::new();
+        shell.set_variable("x", "a* b".to_string());
+        let pattern = expand_word_to_pattern(&mut shell, "$x\"$x\"'?'[ab]").unwrap();
+        assert_eq!(pattern, "a* ba\\* b\\?[ab]");
+    }
+
+    #[test]
+    fn expand_here_document_test() {
+        let mut shell = Shell::new();
+        shell.set_variable("x", "1".to_string());
+        let body = "\"$x\" \\$x \\a\n";
+        assert_eq!(
+            expand_here_document(&mut shell, body).unwrap(),
+            "\"1\" $x \\a\n"
+        );
+    }
+}
@@ -0,0 +1,217 @@
+extern crate nix;
+
+use builtins::Streams;
+use conditional::is_accessible;
+use hash::nix::libc;
+use shell::Shell;
+use std::fs;
+
+/* Where commands are looked up when PATH isn't set. */
+pub const DEFAULT_PATH: &str = "/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin";
+
+/* A command found in PATH, remembered so that PATH doesn't have to be
+searched again, along with the number of times it was run. */
+#[derive(PartialEq, Debug, Clone)]
+pub struct HashedCommand {
+    pub path: String,
+    pub hits: usize,
+}
+
+/* Looks for an executable file called `name` in the directories of a
+PATH value. An empty directory stands for the current one. */
+pub fn search_path(path_variable: &str, name: &str) -> Option<String> {
+    search_path_all(path_variable, name).next()
+}
+
+/* Every executable file called `name` in the directories of a PATH
+value, in the order they are searched. */
+pub fn search_path_all<'a>(
+    path_variable: &'a str,
+    name: &'a str,
+) -> impl Iterator<Item = String> + 'a {
+    path_variable.split(':').filter_map(move |directory| {
+        let path = match directory {
+            "" => name.to_string(),
+            _ => format!("{}/{}", directory.trim_end_matches('/'), name),
+        };
+        if is_executable_file(&path) {
+            Some(path)
+        } else {
+            None
+        }
+    })
+}
+
+pub fn is_executable_file(path: &str) -> bool {
+    let is_file = fs::metadata(path)
+        .map(|metadata| metadata.is_file())
+        .unwrap_or(false);
+    is_file && is_accessible(path, libc::X_OK)
+}
+
+/* Finds the program to run for a command name, using the hash table
+when the name is in it and still names an executable. Anything found by
+searching PATH is added to the table. */
+pub fn find_command(shell: &mut Shell, name: &str) -> Option<String> {
+    if name.contains('/') {
+        return Some(name.to_string());
+    }
+    let is_valid = shell
+        .command_hash
+        .get(name)
+        .is_some_and(|hashed| is_executable_file(&hashed.path));
+    if !is_valid {
+        let path = search_path(shell.get_variable("PATH").unwrap_or(DEFAULT_PATH), name)?;
+        shell
+            .command_hash
+            .insert(name.to_string(), HashedCommand { path, hits: 0 });
+    }
+    shell
+        .command_hash
+        .get(name)
+        .map(|hashed| hashed.path.clone())
+}
+
+/* Finds the program for a command that is about to run, counting the
+run as a hit. */
+pub fn find_command_to_run(shell: &mut Shell, name: &str) -> Option<String> {
+    let path = find_command(shell, name)?;
+    if let Some(hashed) = shell.command_hash.get_mut(name) {
+        hashed.hits += 1;
+    }
+    Some(path)
+}
+
+fn usage(streams: &mut Streams) -> i32 {
+    let _ = writeln!(
+        streams.stderr,
+        "hash: usage: hash [-lr] [-p pathname] [-dt] [name ...]"
+    );
+    2
+}
+
+/* `hash [-lr] [-p pathname] [-dt] [name ...]` adds the names to the hash
+table, or lists it without names. -r empties it, -d removes the names,
+-t prints their paths and -p hashes a name to the given path. With -l
+the table is listed in a form that can be run again. */
+pub fn builtin_hash(shell: &mut Shell, arguments: &[String], streams: &mut Streams) -> i32 {
+    let (mut reusable, mut delete, mut print, mut reset) = (false, false, false, false);
+    let mut pathname = None;
+    let mut position = 1;
+    while let Some(argument) = arguments.get(position) {
+        if !argument.starts_with('-') || argument == "-" {
+            break;
+        }
+        position += 1;
+        if argument == "--" {
+            break;
+        }
+        for option in argument[1..].chars() {
+            match option {
+                'l' => reusable = true,
+                'r' => reset = true,
+                'd' => delete = true,
+                't' => print = true,
+                'p' => match arguments.get(position) {
+                    Some(path) => {
+                        pathname = Some(path.clone());
+                        position += 1;
+                    }
+                    None => {
+                        streams.error("hash: -p: option requires an argument");
+                        return usage(streams);
+                    }
+                },
+                _ => {
+                    streams.error(format!("hash: -{}: invalid option", option));
+                    return usage(streams);
+                }
+            }
+        }
+    }
+    if reset {
+        shell.command_hash.clear();
+    }
+    let names = &arguments[position..];
+    if names.is_empty() && reset {
+        return 0;
+    }
+    if names.is_empty() {
+        if shell.command_hash.is_empty() {
+            let _ = writeln!(streams.stdout, "hash: hash table empty");
+        } else if reusable {
+            for (name, hashed) in &shell.command_hash {
+                let _ = writeln!(streams.stdout, "builtin hash -p {} {}", hashed.path, name);
+            }
+        } else {
+            let _ = writeln!(streams.stdout, "hits\tcommand");
+            for hashed in shell.command_hash.values() {
+                let _ = writeln!(streams.stdout, "{:4}\t{}", hashed.hits, hashed.path);
+            }
+        }
+        return 0;
+    }
+    let mut status = 0;
+    for name in names {
+        let found = if let Some(ref path) = pathname {
+            let hashed = HashedCommand {
+                path: path.clone(),
+                hits: 0,
+            };
+            shell.command_hash.insert(name.clone(), hashed);
+            true
+        } else if delete {
+            shell.command_hash.remove(name).is_some()
+        } else if print {
+            match shell.command_hash.get(name) {
+                Some(hashed) if names.len() > 1 => {
+                    let _ = writeln!(streams.stdout, "{}\t{}", name, hashed.path);
+                    true
+                }
+                Some(hashed) => {
+                    let _ = writeln!(streams.stdout, "{}", hashed.path);
+                    true
+                }
+                None => false,
+            }
+        } else if shell.builtins.get(name).is_some() || shell.functions.contains_key(name) {
+            true
+        } else {
+            shell.command_hash.remove(name);
+            find_command(shell, name).is_some()
+        };
+        if !found {
+            streams.error(format!("hash: {}: not found", name));
+            status = 1;
+        }
+    }
+    status
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_command_test() {
+        let mut shell = Shell::new();
+        shell.set_variable("PATH", "/nonexistent::/bin".to_string());
+        assert_eq!(
+            find_command_to_run(&mut shell, "sh"),
+            Some("/bin/sh".to_string())
+        );
+        assert_eq!(
+            find_command_to_run(&mut shell, "sh"),
+            Some("/bin/sh".to_string())
+        );
+        assert_eq!(
+            shell.command_hash.get("sh").map(|hashed| hashed.hits),
+            Some(2)
+        );
+        assert_eq!(find_command(&mut shell, "./sh"), Some("./sh".to_string()));
+        assert_eq!(find_command(&mut shell, "no-such-command"), None);
+
+        shell.set_variable("PATH", "/usr/bin".to_string());
+        assert!(shell.command_hash.is_empty());
+    }
+}
@@ -1,559 +1,862 @@
-extern crate nix;
-extern crate void;
-
-use lexer::nix::unistd::*;
-use lexer::void::Void;
-use std::ffi::CString;
-use std::iter::FromIterator;
+use parser::is_reserved_word;
 
 #[derive(PartialEq, Debug, Clone)]
-enum Token<'a> {
-    Progname(&'a str),
-    Argument(&'a str),
+pub enum Token {
+    Word(String),
+    IoNumber(i32),
+    IoVariable(String),
+    HereDocument(HereDocument),
+    Operator(Operator),
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Operator {
     Separator,
-    Quit,
+    DoubleSemicolon,
+    SemicolonAnd,
+    DoubleSemicolonAnd,
+    Newline,
+    Ampersand,
+    AndIf,
+    Pipe,
+    OrIf,
+    LeftParen,
+    RightParen,
+    Less,
+    Great,
+    DoubleGreat,
+    LessGreat,
+    LessAnd,
+    GreatAnd,
+    Clobber,
+    TripleLess,
+}
+
+/* A here-document is lexed as a single token. The body is only known
+once the lexer reaches the end of the line the `<<` appeared on, so it
+is filled in after the fact. */
+#[derive(PartialEq, Debug, Clone)]
+pub struct HereDocument {
+    pub delimiter: String,
+    pub strip_tabs: bool,
+    pub body: String,
 }
 
 #[derive(PartialEq, Debug)]
-pub enum ParseError<'a> {
-    UnbalancedQuote(&'a str),
-    QuoteInAtomicString(&'a str),
+pub enum ParseError {
+    UnbalancedQuote(String),
+    UnterminatedHereDocument(String),
+    UnexpectedToken(String),
+    UnexpectedEof,
     TokenOutOfPlace,
-    NullByteError,
 }
 
-#[derive(PartialEq, Debug)]
-pub struct Command {
-    progname: CString,
-    arguments: Vec<CString>,
+impl ParseError {
+    /* Whether the input ended in the middle of a construct, so that
+    reading more lines could still make it valid. */
+    pub fn is_incomplete(&self) -> bool {
+        matches!(
+            self,
+            ParseError::UnbalancedQuote(_)
+                | ParseError::UnterminatedHereDocument(_)
+                | ParseError::UnexpectedEof
+        )
+    }
 }
 
-impl Command {
-    fn new(tokens: Vec<Token>) -> Result<Command, ParseError> {
-        let mut cstrings: Vec<CString> = Vec::new();
-        for (index, token) in tokens.iter().enumerate() {
-            if index == 0 {
-                match token {
-                    Token::Progname(string) => match CString::new(*string) {
-                        Ok(cstring) => cstrings.push(cstring),
-                        Err(_) => return Err(ParseError::NullByteError),
-                    },
-                    _ => return Err(ParseError::TokenOutOfPlace),
-                }
-            } else {
-                match token {
-                    Token::Argument(string) => match CString::new(*string) {
-                        Ok(cstring) => cstrings.push(cstring),
-                        Err(_) => return Err(ParseError::NullByteError),
-                    },
-                    _ => return Err(ParseError::TokenOutOfPlace),
-                }
-            }
-        }
-        let progname = cstrings[0].clone();
-        let arguments = Vec::from_iter(cstrings[1..].iter().cloned());
-        Ok(Command {
-            progname,
-            arguments,
-        })
+impl Operator {
+    fn is_redirection(self) -> bool {
+        matches!(
+            self,
+            Operator::Less
+                | Operator::Great
+                | Operator::DoubleGreat
+                | Operator::LessGreat
+                | Operator::LessAnd
+                | Operator::GreatAnd
+                | Operator::Clobber
+                | Operator::TripleLess
+        )
+    }
+}
+
+impl HereDocument {
+    /* The body is expanded like a double quoted string unless some part
+    of the delimiter was quoted. */
+    pub fn is_quoted(&self) -> bool {
+        self.delimiter.contains(&['\'', '\"', '\\'][..])
     }
 
-    pub fn execute(&self) -> nix::Result<Void> {
-        let mut full_args: Vec<CString> = Vec::new();
-        full_args.push(self.progname.clone());
-        full_args.extend(self.arguments.clone());
-        execvp(&self.progname, &full_args)
+    pub fn unquoted_delimiter(&self) -> String {
+        remove_quotes(&self.delimiter)
     }
 }
 
-#[derive(PartialEq, Debug)]
-pub enum Function {
-    ShellCommand(Command),
-    Quit,
+struct Lexer {
+    chars: Vec<char>,
+    position: usize,
+    tokens: Vec<Token>,
+    pending_here_documents: Vec<usize>,
+    in_conditional: bool,
 }
 
-pub fn get_function_from_string(string: &str) -> Result<Vec<Function>, ParseError> {
-    let tokens = tokenize_string(string)?;
-    convert_tokens(tokens)
+/* This function takes a string slice and splits it into words and
+operators. Words are kept exactly as written, quotes and all, because
+the expansion step needs to know which parts were quoted. */
+pub fn tokenize_string(string: &str) -> Result<Vec<Token>, ParseError> {
+    let mut lexer = Lexer {
+        chars: string.chars().collect(),
+        position: 0,
+        tokens: Vec::new(),
+        pending_here_documents: Vec::new(),
+        in_conditional: false,
+    };
+    lexer.tokenize()?;
+    Ok(lexer.tokens)
 }
 
-fn convert_tokens(tokens: Vec<Token>) -> Result<Vec<Function>, ParseError> {
-    let mut functions: Vec<Function> = Vec::new();
-    let mut token_buffer: Vec<Token> = Vec::new();
-    let mut has_quit = false;
-    for token in &tokens {
-        match token {
-            Token::Separator => {
-                if !token_buffer.is_empty() {
-                    let current_tokens = token_buffer.clone();
-                    functions.push(Function::ShellCommand(Command::new(current_tokens)?));
-                    token_buffer.clear();
+impl Lexer {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).cloned()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.position + offset).cloned()
+    }
+
+    fn rest(&self) -> String {
+        self.chars[self.position..].iter().collect()
+    }
+
+    fn tokenize(&mut self) -> Result<(), ParseError> {
+        loop {
+            self.skip_blanks();
+            let character = match self.peek() {
+                Some(character) => character,
+                None => break,
+            };
+            match character {
+                '#' => self.skip_comment(),
+                '\n' => {
+                    self.position += 1;
+                    self.tokens.push(Token::Operator(Operator::Newline));
+                    self.read_here_document_bodies()?;
                 }
-            }
-            Token::Quit => {
-                if !token_buffer.is_empty() {
-                    return Err(ParseError::TokenOutOfPlace);
-                } else if !has_quit {
-                    functions.push(Function::Quit);
-                    has_quit = true;
+                _ if self.in_conditional
+                    && matches!(self.tokens.last(), Some(Token::Word(word)) if word == "=~") =>
+                {
+                    let word = self.lex_regex()?;
+                    self.tokens.push(Token::Word(word));
+                }
+                '<' | '>' if self.peek_at(1) == Some('(') => {
+                    let word = self.lex_word()?;
+                    self.tokens.push(Token::Word(word));
+                }
+                '(' if self.peek_at(1) == Some('(') => {
+                    let word = self.lex_arithmetic_command()?;
+                    self.tokens.push(Token::Word(word));
+                }
+                _ if is_operator_start(character) => self.lex_operator()?,
+                _ => {
+                    let word = self.lex_word()?;
+                    let before_redirection = self.peek() == Some('<') || self.peek() == Some('>');
+                    let is_io_number = !word.is_empty() && word.chars().all(|c| c.is_ascii_digit());
+                    match word.parse::<i32>() {
+                        Ok(number) if is_io_number && before_redirection => {
+                            self.tokens.push(Token::IoNumber(number))
+                        }
+                        _ => match io_variable_name(&word) {
+                            Some(name) if before_redirection => {
+                                self.tokens.push(Token::IoVariable(name.to_string()))
+                            }
+                            _ => self.push_word(word),
+                        },
+                    }
                 }
             }
-            _ => {
-                token_buffer.push(token.clone());
-            }
-        };
+        }
+        match self.pending_here_documents.first() {
+            Some(index) => match self.tokens[*index] {
+                Token::HereDocument(ref here_document) => Err(
+                    ParseError::UnterminatedHereDocument(here_document.unquoted_delimiter()),
+                ),
+                _ => Err(ParseError::TokenOutOfPlace),
+            },
+            None => Ok(()),
+        }
     }
-    if !token_buffer.is_empty() {
-        functions.push(Function::ShellCommand(Command::new(token_buffer)?));
+
+    /* Keeps track of whether the lexer is inside `[[ ... ]]`, the only
+    place where `=~` is followed by a regular expression. */
+    fn push_word(&mut self, word: String) {
+        if word == "[[" && self.at_command_start() {
+            self.in_conditional = true;
+        } else if word == "]]" {
+            self.in_conditional = false;
+        }
+        self.tokens.push(Token::Word(word));
     }
-    Ok(functions)
-}
 
-/* This function takes a string slice, and tries to lex it according
-to a very basic grammar.
-It first splits up the string into the atomic commands, i.e. the commands
-separated by ';'. It then calls `tokenize_atomic_string on each of the
-atomic strings separately.` */
-fn tokenize_string(string: &str) -> Result<Vec<Token>, ParseError> {
-    let mut tokens: Vec<Token> = Vec::new();
-    let atomic_strings =
-        exclusive_separate_at_positions(string, find_all_separator_positions(string));
-    for (index, atomic_string) in atomic_strings.iter().enumerate() {
-        if index > 0 {
-            tokens.push(Token::Separator);
+    /* Whether the next word is where a command could start, so that it
+    can be a reserved word. */
+    fn at_command_start(&self) -> bool {
+        match self.tokens.last() {
+            None => true,
+            Some(Token::Operator(operator)) => !operator.is_redirection(),
+            Some(Token::Word(word)) => word != "in" && is_reserved_word(word),
+            Some(_) => false,
         }
-        let mut atomic_tokens = tokenize_atomic_string(atomic_string)?;
-        tokens.append(&mut atomic_tokens);
     }
-    Ok(tokens)
-}
 
-/* This function does most of the heavy lifting. It first checks for
-any quoted portion in the atomic string. If there is any, it checks
-it for balanced quotes, and finally, splits up the line into tokens. */
-fn tokenize_atomic_string(string: &str) -> Result<Vec<Token>, ParseError> {
-    let mut tokens: Vec<Token> = Vec::new();
-    let quoted_block_position = find_quoted_block_position(string)?;
-    match quoted_block_position {
-        Some((fb, lb)) => {
-            let left_words = split_by_whitespace(&string[..fb])?;
-            let right_words = split_by_whitespace(&string[(lb + 1)..])?;
-
-            for (index, word) in left_words.iter().enumerate() {
-                if index == 0 {
-                    match word.to_owned() {
-                        "quit" => tokens.push(Token::Quit),
-                        _ => tokens.push(Token::Progname(word)),
-                    };
-                } else {
-                    tokens.push(Token::Argument(word));
-                }
+    /* Blanks separate words. A backslash followed by a newline is a line
+    continuation and is skipped just like a blank. */
+    fn skip_blanks(&mut self) {
+        loop {
+            match (self.peek(), self.peek_at(1)) {
+                (Some(' '), _) | (Some('\t'), _) => self.position += 1,
+                (Some('\\'), Some('\n')) => self.position += 2,
+                _ => break,
             }
+        }
+    }
 
-            let quoted_part = Token::Argument(&string[fb..(lb + 1)]);
-            tokens.push(quoted_part);
+    fn skip_comment(&mut self) {
+        while let Some(character) = self.peek() {
+            if character == '\n' {
+                break;
+            }
+            self.position += 1;
+        }
+    }
 
-            for word in &right_words {
-                tokens.push(Token::Argument(word));
+    fn lex_operator(&mut self) -> Result<(), ParseError> {
+        let candidates = [
+            (";;&", Some(Operator::DoubleSemicolonAnd)),
+            ("<<<", Some(Operator::TripleLess)),
+            ("<<-", None),
+            ("<<", None),
+            (";;", Some(Operator::DoubleSemicolon)),
+            (";&", Some(Operator::SemicolonAnd)),
+            ("&&", Some(Operator::AndIf)),
+            ("||", Some(Operator::OrIf)),
+            (">>", Some(Operator::DoubleGreat)),
+            ("<>", Some(Operator::LessGreat)),
+            ("<&", Some(Operator::LessAnd)),
+            (">&", Some(Operator::GreatAnd)),
+            (">|", Some(Operator::Clobber)),
+            (";", Some(Operator::Separator)),
+            ("&", Some(Operator::Ampersand)),
+            ("|", Some(Operator::Pipe)),
+            ("(", Some(Operator::LeftParen)),
+            (")", Some(Operator::RightParen)),
+            ("<", Some(Operator::Less)),
+            (">", Some(Operator::Great)),
+        ];
+        for (text, operator) in candidates.iter() {
+            if !self.starts_with(text) {
+                continue;
+            }
+            self.position += text.chars().count();
+            match operator {
+                Some(operator) => self.tokens.push(Token::Operator(*operator)),
+                None => self.lex_here_document_delimiter(*text == "<<-")?,
             }
+            return Ok(());
         }
-        None => {
-            let words = split_by_whitespace(string)?;
-            for (index, word) in words.iter().enumerate() {
-                if index == 0 {
-                    match word.to_owned() {
-                        "quit" => tokens.push(Token::Quit),
-                        _ => tokens.push(Token::Progname(word)),
-                    };
+        Err(ParseError::UnexpectedToken(self.rest()))
+    }
+
+    fn starts_with(&self, text: &str) -> bool {
+        text.chars()
+            .enumerate()
+            .all(|(offset, character)| self.peek_at(offset) == Some(character))
+    }
+
+    fn lex_here_document_delimiter(&mut self, strip_tabs: bool) -> Result<(), ParseError> {
+        self.skip_blanks();
+        match self.peek() {
+            Some(character) if character != '\n' && !is_operator_start(character) => (),
+            Some(_) => return Err(ParseError::UnexpectedToken(self.rest())),
+            None => return Err(ParseError::UnexpectedToken("newline".to_string())),
+        }
+        let delimiter = self.lex_word()?;
+        self.pending_here_documents.push(self.tokens.len());
+        self.tokens.push(Token::HereDocument(HereDocument {
+            delimiter,
+            strip_tabs,
+            body: String::new(),
+        }));
+        Ok(())
+    }
+
+    /* Called right after a newline. Every here-document started on the
+    previous line takes its body from the lines that follow, in order. */
+    fn read_here_document_bodies(&mut self) -> Result<(), ParseError> {
+        let pending: Vec<usize> = self.pending_here_documents.drain(..).collect();
+        for index in pending {
+            let (delimiter, strip_tabs) = match self.tokens[index] {
+                Token::HereDocument(ref here_document) => {
+                    (here_document.unquoted_delimiter(), here_document.strip_tabs)
+                }
+                _ => continue,
+            };
+            let mut body = String::new();
+            let mut terminated = false;
+            while self.position < self.chars.len() {
+                let mut line = String::new();
+                while let Some(character) = self.peek() {
+                    self.position += 1;
+                    if character == '\n' {
+                        break;
+                    }
+                    line.push(character);
+                }
+                let line = if strip_tabs {
+                    line.trim_start_matches('\t').to_string()
                 } else {
-                    tokens.push(Token::Argument(word));
+                    line
+                };
+                if line == delimiter {
+                    terminated = true;
+                    break;
                 }
+                body.push_str(&line);
+                body.push('\n');
+            }
+            if !terminated {
+                return Err(ParseError::UnterminatedHereDocument(delimiter));
+            }
+            if let Token::HereDocument(ref mut here_document) = self.tokens[index] {
+                here_document.body = body;
             }
         }
+        Ok(())
     }
-    Ok(tokens)
-}
 
-/* This function splits up a string and ignores the whitespace.
-If it comes across a quote, it throws a ParseError. */
-fn split_by_whitespace(string: &str) -> Result<Vec<&str>, ParseError> {
-    let mut individual_words: Vec<&str> = Vec::new();
-    let quote_position = string.find('\"');
-    match quote_position {
-        Some(pos) => Err(ParseError::QuoteInAtomicString(&string[pos..])),
-        None => {
-            for string_slice in string.split_whitespace() {
-                individual_words.push(string_slice);
+    /* `((` at the start of a token opens an arithmetic command, which runs
+    until the matching `))`. What's inside is an expression and not shell
+    syntax, so the whole thing is kept as a single word. */
+    fn lex_arithmetic_command(&mut self) -> Result<String, ParseError> {
+        let start = self.position;
+        self.position += 2;
+        let mut depth = 0;
+        loop {
+            match (self.peek(), self.peek_at(1)) {
+                (Some(')'), Some(')')) if depth == 0 => {
+                    self.position += 2;
+                    return Ok(self.chars[start..self.position].iter().collect());
+                }
+                (Some('('), _) => depth += 1,
+                (Some(')'), _) => depth -= 1,
+                (Some(_), _) => (),
+                (None, _) => {
+                    let quoted = self.chars[start..].iter().collect();
+                    return Err(ParseError::UnbalancedQuote(quoted));
+                }
             }
-            Ok(individual_words)
+            self.position += 1;
         }
     }
-}
 
-/* This function actually finds the quotes position and checks for balanced
-quotes, throwing an error otherwise. */
-fn find_quoted_block_position(string: &str) -> Result<Option<(usize, usize)>, ParseError> {
-    let first_occurrence = string.find('\"');
-    let last_occurrence = string.rfind('\"');
-    match (first_occurrence, last_occurrence) {
-        (None, _) => Ok(None),
-        (_, None) => Ok(None),
-        (Some(fb), Some(lb)) => {
-            if has_balanced_apostrophes(&string[fb..(lb + 1)], '\"') {
-                Ok(Some((fb, lb)))
-            } else {
-                Err(ParseError::UnbalancedQuote(&string[fb..lb + 1]))
+    /* Reads a single word, stopping at the first unquoted blank or
+    operator character. Quoted sections and substitutions are copied
+    verbatim. */
+    fn lex_word(&mut self) -> Result<String, ParseError> {
+        self.lex_word_with(false)
+    }
+
+    /* The right side of `=~` in `[[ ... ]]` is a regular expression, where
+    parentheses and `|` are part of the word, and blanks too as long as
+    they are inside parentheses. */
+    fn lex_regex(&mut self) -> Result<String, ParseError> {
+        self.lex_word_with(true)
+    }
+
+    fn lex_word_with(&mut self, is_regex: bool) -> Result<String, ParseError> {
+        let mut word = String::new();
+        let mut depth = 0;
+        while let Some(character) = self.peek() {
+            match character {
+                ' ' | '\t' if depth > 0 => {
+                    word.push(character);
+                    self.position += 1;
+                }
+                ' ' | '\t' | '\n' => break,
+                '(' | ')' | '|' if is_regex => {
+                    match character {
+                        '(' => depth += 1,
+                        ')' if depth > 0 => depth -= 1,
+                        _ => (),
+                    }
+                    word.push(character);
+                    self.position += 1;
+                }
+                '<' | '>' if self.peek_at(1) == Some('(') => self.lex_substitution(&mut word)?,
+                _ if is_operator_start(character) => break,
+                '\\' => match self.peek_at(1) {
+                    Some('\n') => self.position += 2,
+                    Some(escaped) => {
+                        word.push('\\');
+                        word.push(escaped);
+                        self.position += 2;
+                    }
+                    None => {
+                        word.push('\\');
+                        self.position += 1;
+                    }
+                },
+                '\'' => self.lex_single_quoted(&mut word)?,
+                '\"' => self.lex_double_quoted(&mut word)?,
+                '$' | '`' => self.lex_substitution(&mut word)?,
+                _ => {
+                    word.push(character);
+                    self.position += 1;
+                }
             }
         }
+        Ok(word)
     }
-}
 
-/* This function does the actual checking for balanced apostrophes */
-fn has_balanced_apostrophes(string: &str, apostrophe_char: char) -> bool {
-    let first_occurrence = string.find(apostrophe_char);
-    let last_occurrence = string.rfind(apostrophe_char);
-    match (first_occurrence, last_occurrence) {
-        (None, _) => true,
-        (_, None) => true,
-        (Some(fb), Some(lb)) => {
-            if fb == lb {
-                false
-            } else {
-                has_balanced_apostrophes(&string[(fb + 1)..(lb)], apostrophe_char)
+    fn lex_single_quoted(&mut self, word: &mut String) -> Result<(), ParseError> {
+        let start = self.position;
+        word.push('\'');
+        self.position += 1;
+        loop {
+            match self.peek() {
+                Some('\'') => {
+                    word.push('\'');
+                    self.position += 1;
+                    return Ok(());
+                }
+                Some(character) => {
+                    word.push(character);
+                    self.position += 1;
+                }
+                None => {
+                    let quoted = self.chars[start..].iter().collect();
+                    return Err(ParseError::UnbalancedQuote(quoted));
+                }
             }
         }
     }
-}
 
-/* The next two functions find the positions of the separator, and
-separate the string according to them. I didn't use the string.split(';')
-method because that wouldn't distinguish ';' from an escaped '\;'. */
-fn find_all_separator_positions(string: &str) -> Vec<usize> {
-    let mut separator_positions = Vec::new();
-    for (index, character) in string.chars().enumerate() {
-        if character == ';' {
-            if index > 0 && string.chars().nth(index - 1).unwrap() == '\\' {
-                if index > 1 && string.chars().nth(index - 2).unwrap() == '\\' {
-                    separator_positions.push(index);
+    fn lex_double_quoted(&mut self, word: &mut String) -> Result<(), ParseError> {
+        let start = self.position;
+        word.push('\"');
+        self.position += 1;
+        loop {
+            match self.peek() {
+                Some('\"') => {
+                    word.push('\"');
+                    self.position += 1;
+                    return Ok(());
+                }
+                Some('\\') => {
+                    word.push('\\');
+                    self.position += 1;
+                    if let Some(escaped) = self.peek() {
+                        word.push(escaped);
+                        self.position += 1;
+                    }
+                }
+                Some('$') | Some('`') => self.lex_substitution(word)?,
+                Some(character) => {
+                    word.push(character);
+                    self.position += 1;
+                }
+                None => {
+                    let quoted = self.chars[start..].iter().collect();
+                    return Err(ParseError::UnbalancedQuote(quoted));
                 }
-            } else {
-                separator_positions.push(index);
             }
         }
     }
-    separator_positions
-}
 
-fn exclusive_separate_at_positions(string: &str, positions: Vec<usize>) -> Vec<&str> {
-    let mut current_start: usize = 0;
-    let mut separated_slices: Vec<&str> = Vec::new();
+    /* Copies `$(...)`, `$((...))`, `${...}`, backquoted commands and the
+    process substitutions `<(...)` and `>(...)` into the word, making sure
+    that blanks and operators inside them don't end the word early. */
+    fn lex_substitution(&mut self, word: &mut String) -> Result<(), ParseError> {
+        let start = self.position;
+        let closing = match (self.peek(), self.peek_at(1)) {
+            (Some('`'), _) => '`',
+            (Some('$'), Some('(')) | (Some('<'), Some('(')) | (Some('>'), Some('(')) => ')',
+            (Some('$'), Some('{')) => '}',
+            _ => {
+                word.push('$');
+                self.position += 1;
+                return Ok(());
+            }
+        };
+        if closing == '`' {
+            self.position += 1;
+            loop {
+                match self.peek() {
+                    Some('`') => break,
+                    Some('\\') => self.position += 2,
+                    Some(_) => self.position += 1,
+                    None => {
+                        let quoted = self.chars[start..].iter().collect();
+                        return Err(ParseError::UnbalancedQuote(quoted));
+                    }
+                }
+            }
+        } else {
+            let opening = self.chars[self.position + 1];
+            self.position += 2;
+            let mut depth = 1;
+            while depth > 0 {
+                match self.peek() {
+                    Some(character) if character == opening => {
+                        depth += 1;
+                        self.position += 1;
+                    }
+                    Some(character) if character == closing => {
+                        depth -= 1;
+                        if depth > 0 {
+                            self.position += 1;
+                        }
+                    }
+                    Some('\\') => self.position += 2,
+                    Some('\'') => {
+                        let mut ignored = String::new();
+                        self.lex_single_quoted(&mut ignored)?;
+                    }
+                    Some('\"') => {
+                        let mut ignored = String::new();
+                        self.lex_double_quoted(&mut ignored)?;
+                    }
+                    Some(_) => self.position += 1,
+                    None => {
+                        let quoted = self.chars[start..].iter().collect();
+                        return Err(ParseError::UnbalancedQuote(quoted));
+                    }
+                }
+            }
+        }
+        self.position += 1;
+        let end = self.position.min(self.chars.len());
+        word.extend(self.chars[start..end].iter());
+        Ok(())
+    }
+}
 
-    for position in &positions {
-        separated_slices.push(&string[current_start..*position]);
-        current_start = position + 1;
+/* The name in a `{name}` written right before a redirection operator,
+which then opens a new descriptor and stores its number in the variable. */
+fn io_variable_name(word: &str) -> Option<&str> {
+    let name = word.strip_prefix('{')?.strip_suffix('}')?;
+    let mut characters = name.chars();
+    let starts_name = characters
+        .next()
+        .is_some_and(|c| c == '_' || c.is_ascii_alphabetic());
+    if starts_name && characters.all(|c| c == '_' || c.is_ascii_alphanumeric()) {
+        Some(name)
+    } else {
+        None
     }
-    separated_slices.push(&string[current_start..]);
-    separated_slices
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+fn is_operator_start(character: char) -> bool {
+    ";&|<>()".contains(character)
+}
 
-    #[test]
-    fn get_function_from_string_test() {
-        let string = "ls";
-        let functions = get_function_from_string(&string);
-        let progname = CString::new("ls").unwrap();
-        let arguments: Vec<CString> = Vec::new();
-        let expected_result = Ok(
-            vec![
-                Function::ShellCommand(
-                    Command {
-                        progname,
-                        arguments,
-                    }
-                )
-            ]
-        );
-        assert_eq!(functions, expected_result);
-
-        let string = "ls; quit";
-        let functions = get_function_from_string(&string);
-        let progname = CString::new("ls").unwrap();
-        let arguments: Vec<CString> = Vec::new();
-        let expected_result = Ok(
-            vec![
-                Function::ShellCommand(
-                    Command {
-                        progname,
-                        arguments,
+/* Strips quotes and backslashes from a word without performing any
+expansion. Used where the shell needs the literal text of a word, like
+here-document delimiters. */
+pub fn remove_quotes(word: &str) -> String {
+    let mut result = String::new();
+    let mut characters = word.chars();
+    let mut in_double_quotes = false;
+    while let Some(character) = characters.next() {
+        match character {
+            '\'' if !in_double_quotes => {
+                for quoted in characters.by_ref() {
+                    if quoted == '\'' {
+                        break;
                     }
-                ),
-                Function::Quit,
-            ]
-        );
-        assert_eq!(functions, expected_result);
-
-        let string = "quit; ls; quit";
-        let functions = get_function_from_string(&string);
-        let progname = CString::new("ls").unwrap();
-        let arguments: Vec<CString> = Vec::new();
-        let expected_result = Ok(
-            vec![
-                Function::Quit,
-                Function::ShellCommand(
-                    Command {
-                        progname,
-                        arguments,
-                    }
-                ),
-            ]
-        );
-        assert_eq!(functions, expected_result);
-        
-        let string = "quit; ls; quit; ls";
-        let functions = get_function_from_string(&string);
-        let progname1 = CString::new("ls").unwrap();
-        let progname2 = CString::new("ls").unwrap();
-        let arguments1: Vec<CString> = Vec::new();
-        let arguments2: Vec<CString> = Vec::new();
-        let expected_result = Ok(
-            vec![
-                Function::Quit,
-                Function::ShellCommand(
-                    Command {
-                        progname: progname1,
-                        arguments: arguments1,
-                    }
-                ),
-                Function::ShellCommand(
-                    Command {
-                        progname: progname2,
-                        arguments: arguments2,
+                    result.push(quoted);
+                }
+            }
+            '\"' => in_double_quotes = !in_double_quotes,
+            '\\' => {
+                if let Some(escaped) = characters.next() {
+                    if in_double_quotes && !"$`\"\\\n".contains(escaped) {
+                        result.push('\\');
                     }
-                ),
-            ]
-        );
-        assert_eq!(functions, expected_result);
+                    result.push(escaped);
+                }
+            }
+            _ => result.push(character),
+        }
     }
-    
-    #[test]
-    fn command_new_test() {
-        let string = "ls";
-        let tokens = tokenize_string(&string).unwrap();
-        let progname = CString::new("ls").unwrap();
-        let arguments: Vec<CString> = Vec::new();
-        let expected_result = Ok(Command {
-            progname,
-            arguments,
-        });
-        assert_eq!(Command::new(tokens), expected_result);
-
-        let string = "ls -l";
-        let tokens = tokenize_string(&string).unwrap();
-        let progname = CString::new("ls").unwrap();
-        let arguments = vec![CString::new("-l").unwrap()];
-        let expected_result = Ok(Command {
-            progname,
-            arguments,
-        });
-        assert_eq!(Command::new(tokens), expected_result);
-
-        let string = "echo 3 \"more\"";
-        let tokens = tokenize_string(&string).unwrap();
-        let progname = CString::new("echo").unwrap();
-        let arguments = vec![
-            CString::new("3").unwrap(),
-            CString::new("\"more\"").unwrap(),
-        ];
-        let expected_result = Ok(Command {
-            progname,
-            arguments,
-        });
-        assert_eq!(Command::new(tokens), expected_result);
+    result
+}
 
-        let string = "quit";
-        let tokens = tokenize_string(&string).unwrap();
-        let expected_result = Err(ParseError::TokenOutOfPlace);
-        assert_eq!(Command::new(tokens), expected_result);
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        let string = "str\0ing";
-        let tokens = tokenize_string(&string).unwrap();
-        let expected_result = Err(ParseError::NullByteError);
-        assert_eq!(Command::new(tokens), expected_result);
+    fn word(string: &str) -> Token {
+        Token::Word(string.to_string())
     }
 
     #[test]
     fn tokenize_string_test() {
         let string = ";";
-        let expected_result = Ok(vec![Token::Separator]);
-        assert_eq!(tokenize_string(&string), expected_result);
-
-        let string = ";;";
-        let expected_result = Ok(vec![Token::Separator, Token::Separator]);
-        assert_eq!(tokenize_string(&string), expected_result);
+        let expected_result = Ok(vec![Token::Operator(Operator::Separator)]);
+        assert_eq!(tokenize_string(string), expected_result);
 
         let string = "echo 3 ; quit";
         let expected_result = Ok(vec![
-            Token::Progname(&string[0..4]),
-            Token::Argument(&string[5..6]),
-            Token::Separator,
-            Token::Quit,
+            word("echo"),
+            word("3"),
+            Token::Operator(Operator::Separator),
+            word("quit"),
         ]);
-        assert_eq!(tokenize_string(&string), expected_result);
-    }
-
-    #[test]
-    fn tokenize_atomic_string_test() {
-        let string = "echo";
-        let expected_result = Ok(vec![Token::Progname(&string[..])]);
-        assert_eq!(tokenize_atomic_string(&string), expected_result);
-
-        let string = "quit";
-        let expected_result = Ok(vec![Token::Quit]);
-        assert_eq!(tokenize_atomic_string(&string), expected_result);
+        assert_eq!(tokenize_string(string), expected_result);
 
-        let string = "echo quit";
+        let string = "echo 3 \"blah blah\" 'a;b'c";
         let expected_result = Ok(vec![
-            Token::Progname(&string[0..4]),
-            Token::Argument(&string[5..]),
+            word("echo"),
+            word("3"),
+            word("\"blah blah\""),
+            word("'a;b'c"),
         ]);
-        assert_eq!(tokenize_atomic_string(&string), expected_result);
+        assert_eq!(tokenize_string(string), expected_result);
 
-        let string = "quit echo";
-        let expected_result = Ok(vec![Token::Quit, Token::Argument(&string[5..])]);
-        assert_eq!(tokenize_atomic_string(&string), expected_result);
+        let string = "echo \"bl\"h\"";
+        let expected_result = Err(ParseError::UnbalancedQuote("\"".to_string()));
+        assert_eq!(tokenize_string(string), expected_result);
 
-        let string = "echo \"blah\"";
+        let string = "ls \\; cat";
+        let expected_result = Ok(vec![word("ls"), word("\\;"), word("cat")]);
+        assert_eq!(tokenize_string(string), expected_result);
+
+        let string = "  ";
+        let expected_result = Ok(Vec::new());
+        assert_eq!(tokenize_string(string), expected_result);
+
+        let string = "echo a # a comment";
+        let expected_result = Ok(vec![word("echo"), word("a")]);
+        assert_eq!(tokenize_string(string), expected_result);
+
+        let string = "a;; b;& c;;&";
         let expected_result = Ok(vec![
-            Token::Progname(&string[0..4]),
-            Token::Argument(&string[5..]),
+            word("a"),
+            Token::Operator(Operator::DoubleSemicolon),
+            word("b"),
+            Token::Operator(Operator::SemicolonAnd),
+            word("c"),
+            Token::Operator(Operator::DoubleSemicolonAnd),
         ]);
-        assert_eq!(tokenize_atomic_string(&string), expected_result);
+        assert_eq!(tokenize_string(string), expected_result);
+    }
 
-        let string = "echo \"bl\"h\"";
-        let expected_result = Err(ParseError::UnbalancedQuote(&string[5..11]));
-        assert_eq!(tokenize_atomic_string(&string), expected_result);
+    /* The cases the first lexer was tested with, which split lines on
+    `;` and whitespace and only knew about double quotes. */
+    #[test]
+    fn tokenize_words_test() {
+        let separator = Token::Operator(Operator::Separator);
+
+        let string = "echo   3";
+        let expected_result = Ok(vec![word("echo"), word("3")]);
+        assert_eq!(tokenize_string(string), expected_result);
 
         let string = "echo 3 \"blah\" 4";
+        let expected_result = Ok(vec![word("echo"), word("3"), word("\"blah\""), word("4")]);
+        assert_eq!(tokenize_string(string), expected_result);
+
+        let string = "echo \"fl\"o\"\"";
+        let expected_result = Ok(vec![word("echo"), word("\"fl\"o\"\"")]);
+        assert_eq!(tokenize_string(string), expected_result);
+
+        let string = "test 'string \"even more\"'";
+        let expected_result = Ok(vec![word("test"), word("'string \"even more\"'")]);
+        assert_eq!(tokenize_string(string), expected_result);
+
+        let string = "echo \"floof\"\"";
+        let expected_result = Err(ParseError::UnbalancedQuote("\"".to_string()));
+        assert_eq!(tokenize_string(string), expected_result);
+
+        let string = "test string '";
+        let expected_result = Err(ParseError::UnbalancedQuote("'".to_string()));
+        assert_eq!(tokenize_string(string), expected_result);
+
+        let string = "ls ; cat file \\; echo 3";
         let expected_result = Ok(vec![
-            Token::Progname(&string[0..4]),
-            Token::Argument(&string[5..6]),
-            Token::Argument(&string[7..13]),
-            Token::Argument(&string[14..15]),
+            word("ls"),
+            separator.clone(),
+            word("cat"),
+            word("file"),
+            word("\\;"),
+            word("echo"),
+            word("3"),
         ]);
-        assert_eq!(tokenize_atomic_string(&string), expected_result);
+        assert_eq!(tokenize_string(string), expected_result);
 
-        let string = "  ";
-        let expected_result = Ok(Vec::new());
-        assert_eq!(tokenize_atomic_string(&string), expected_result);
+        let string = "ls \\\\; cat; quit";
+        let expected_result = Ok(vec![
+            word("ls"),
+            word("\\\\"),
+            separator.clone(),
+            word("cat"),
+            separator,
+            word("quit"),
+        ]);
+        assert_eq!(tokenize_string(string), expected_result);
     }
 
     #[test]
-    fn split_by_whitespace_test() {
-        let string = "echo";
-        let expected_result = Ok(vec![&string[..]]);
-        assert_eq!(split_by_whitespace(&string), expected_result);
+    fn tokenize_substitution_test() {
+        let string = "echo $(ls -l; pwd) ${a b}x `c d`";
+        let expected_result = Ok(vec![
+            word("echo"),
+            word("$(ls -l; pwd)"),
+            word("${a b}x"),
+            word("`c d`"),
+        ]);
+        assert_eq!(tokenize_string(string), expected_result);
 
-        let string = "echo 3";
-        let expected_result = Ok(vec![&string[0..4], &string[5..6]]);
-        assert_eq!(split_by_whitespace(&string), expected_result);
+        let string = "echo \"$(echo \")\")\"";
+        let expected_result = Ok(vec![word("echo"), word("\"$(echo \")\")\"")]);
+        assert_eq!(tokenize_string(string), expected_result);
 
-        let string = "echo   3";
-        let expected_result = Ok(vec![&string[0..4], &string[7..8]]);
-        assert_eq!(split_by_whitespace(&string), expected_result);
+        let string = "echo $(ls";
+        let expected_result = Err(ParseError::UnbalancedQuote("$(ls".to_string()));
+        assert_eq!(tokenize_string(string), expected_result);
 
-        let string = "echo \"3\"";
-        let expected_result = Err(ParseError::QuoteInAtomicString(&string[5..]));
-        assert_eq!(split_by_whitespace(&string), expected_result);
-    }
+        let string = "for ((i = (1); i < 3; i++))";
+        let expected_result = Ok(vec![word("for"), word("((i = (1); i < 3; i++))")]);
+        assert_eq!(tokenize_string(string), expected_result);
 
-    #[test]
-    fn find_quoted_block_position_test() {
-        let string = "echo 3";
-        let expected_result = Ok(None);
-        assert_eq!(find_quoted_block_position(&string), expected_result);
+        let string = "((x)";
+        let expected_result = Err(ParseError::UnbalancedQuote("((x)".to_string()));
+        assert_eq!(tokenize_string(string), expected_result);
 
-        let string = "echo \"";
-        let expected_result = Err(ParseError::UnbalancedQuote(&string[5..6]));
-        assert_eq!(find_quoted_block_position(&string), expected_result);
-
-        let string = "echo \"floof\"";
-        let expected_result = Ok(Some((5, 11)));
-        assert_eq!(find_quoted_block_position(&string), expected_result);
+        let string = "[[ $x =~ ^(a|b c)+$ && y ]]";
+        let expected_result = Ok(vec![
+            word("[["),
+            word("$x"),
+            word("=~"),
+            word("^(a|b c)+$"),
+            Token::Operator(Operator::AndIf),
+            word("y"),
+            word("]]"),
+        ]);
+        assert_eq!(tokenize_string(string), expected_result);
 
-        let string = "echo \"floof\"\"";
-        let expected_result = Err(ParseError::UnbalancedQuote(&string[5..13]));
-        assert_eq!(find_quoted_block_position(&string), expected_result);
+        let string = "echo x =~ a|b";
+        let expected_result = Ok(vec![
+            word("echo"),
+            word("x"),
+            word("=~"),
+            word("a"),
+            Token::Operator(Operator::Pipe),
+            word("b"),
+        ]);
+        assert_eq!(tokenize_string(string), expected_result);
 
-        let string = "echo \"fl\"o\"\"";
-        let expected_result = Ok(Some((5, 11)));
-        assert_eq!(find_quoted_block_position(&string), expected_result);
+        let string = "diff <(sort a) x>(tee b) < c";
+        let expected_result = Ok(vec![
+            word("diff"),
+            word("<(sort a)"),
+            word("x>(tee b)"),
+            Token::Operator(Operator::Less),
+            word("c"),
+        ]);
+        assert_eq!(tokenize_string(string), expected_result);
     }
 
     #[test]
-    fn has_balanced_apostrophes_test() {
-        let string = "test string without apostrophe";
-        assert_eq!(has_balanced_apostrophes(&string, '\''), true);
-        assert_eq!(has_balanced_apostrophes(&string, '\"'), true);
+    fn tokenize_redirection_test() {
+        let string = "cat <in >>out 2>&1";
+        let expected_result = Ok(vec![
+            word("cat"),
+            Token::Operator(Operator::Less),
+            word("in"),
+            Token::Operator(Operator::DoubleGreat),
+            word("out"),
+            Token::IoNumber(2),
+            Token::Operator(Operator::GreatAnd),
+            word("1"),
+        ]);
+        assert_eq!(tokenize_string(string), expected_result);
 
-        let string = "test string '";
-        assert_eq!(has_balanced_apostrophes(&string, '\''), false);
-        assert_eq!(has_balanced_apostrophes(&string, '\"'), true);
+        let string = "exec {fd}>log {x} >y";
+        let expected_result = Ok(vec![
+            word("exec"),
+            Token::IoVariable("fd".to_string()),
+            Token::Operator(Operator::Great),
+            word("log"),
+            word("{x}"),
+            Token::Operator(Operator::Great),
+            word("y"),
+        ]);
+        assert_eq!(tokenize_string(string), expected_result);
 
-        let string = "test 'string \"even more\"'";
-        assert_eq!(has_balanced_apostrophes(&string, '\''), true);
-        assert_eq!(has_balanced_apostrophes(&string, '\"'), true);
+        let string = "cat < \"$x\" 2 > f";
+        let expected_result = Ok(vec![
+            word("cat"),
+            Token::Operator(Operator::Less),
+            word("\"$x\""),
+            word("2"),
+            Token::Operator(Operator::Great),
+            word("f"),
+        ]);
+        assert_eq!(tokenize_string(string), expected_result);
+
+        let string = "tr a b <<<abc 0<<< \"$x\"";
+        let expected_result = Ok(vec![
+            word("tr"),
+            word("a"),
+            word("b"),
+            Token::Operator(Operator::TripleLess),
+            word("abc"),
+            Token::IoNumber(0),
+            Token::Operator(Operator::TripleLess),
+            word("\"$x\""),
+        ]);
+        assert_eq!(tokenize_string(string), expected_result);
     }
 
     #[test]
-    fn find_all_separator_positions_test() {
-        let string = ";";
-        let expected_result: Vec<usize> = vec![0];
-        assert_eq!(find_all_separator_positions(&string), expected_result);
-
-        let string = "ls ; cat file";
-        let expected_result: Vec<usize> = vec![3];
-        assert_eq!(find_all_separator_positions(&string), expected_result);
-
-        let string = "ls ; cat file ; echo 3";
-        let expected_result: Vec<usize> = vec![3, 14];
-        assert_eq!(find_all_separator_positions(&string), expected_result);
+    fn tokenize_here_document_test() {
+        let string = "cat <<EOF; echo\nhello\n  $x\nEOF\nls";
+        let expected_result = Ok(vec![
+            word("cat"),
+            Token::HereDocument(HereDocument {
+                delimiter: "EOF".to_string(),
+                strip_tabs: false,
+                body: "hello\n  $x\n".to_string(),
+            }),
+            Token::Operator(Operator::Separator),
+            word("echo"),
+            Token::Operator(Operator::Newline),
+            word("ls"),
+        ]);
+        assert_eq!(tokenize_string(string), expected_result);
 
-        let string = "ls ; cat file \\; echo 3";
-        let expected_result: Vec<usize> = vec![3];
-        assert_eq!(find_all_separator_positions(&string), expected_result);
+        let string = "cat <<-'EOF'\n\thello\n\tEOF\n";
+        let expected_result = Ok(vec![
+            word("cat"),
+            Token::HereDocument(HereDocument {
+                delimiter: "'EOF'".to_string(),
+                strip_tabs: true,
+                body: "hello\n".to_string(),
+            }),
+            Token::Operator(Operator::Newline),
+        ]);
+        assert_eq!(tokenize_string(string), expected_result);
 
-        let string = "ls \\\\; cat file \\; echo 3";
-        let expected_result: Vec<usize> = vec![5];
-        assert_eq!(find_all_separator_positions(&string), expected_result);
+        let string = "cat <<EOF\nhello\n";
+        let expected_result = Err(ParseError::UnterminatedHereDocument("EOF".to_string()));
+        assert_eq!(tokenize_string(string), expected_result);
     }
 
     #[test]
-    fn exclusive_separate_at_positions_test() {
-        let string = "aaaaa";
-        let positions: Vec<usize> = Vec::new();
-        let expected_result = vec![&string[..]];
-        assert_eq!(
-            exclusive_separate_at_positions(&string, positions),
-            expected_result
-        );
-
-        let string = "aaasaa";
-        let positions: Vec<usize> = vec![3];
-        let expected_result = vec![&string[0..3], &string[4..]];
-        assert_eq!(
-            exclusive_separate_at_positions(&string, positions),
-            expected_result
-        );
-
-        let string = "s";
-        let positions: Vec<usize> = vec![0];
-        let expected_result = vec![&string[0..0], &string[1..]];
-        assert_eq!(
-            exclusive_separate_at_positions(&string, positions),
-            expected_result
-        );
+    fn remove_quotes_test() {
+        assert_eq!(remove_quotes("EOF"), "EOF");
+        assert_eq!(remove_quotes("'E\"OF'"), "E\"OF");
+        assert_eq!(remove_quotes("\"a\\$b\\c\""), "a$b\\c");
+        assert_eq!(remove_quotes("\\E\\\\OF"), "E\\OF");
     }
 }
@@ -1,2 +1,20 @@
 pub mod lexer;
+pub mod parser;
+pub mod shell;
+pub mod pattern;
+pub mod regex;
+pub mod arithmetic;
+pub mod conditional;
+pub mod expansion;
+pub mod assignment;
+pub mod redirection;
+pub mod builtins;
+pub mod options;
+pub mod read;
+pub mod output;
+pub mod directory;
+pub mod hash;
+pub mod lookup;
+pub mod signals;
+pub mod trap;
 pub mod executor;
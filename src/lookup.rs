@@ -0,0 +1,347 @@
+use builtins::single_quote;
+use builtins::Streams;
+use executor::{exec_failure_status, replace_process, run_external};
+use hash::{find_command, is_executable_file, search_path_all, DEFAULT_PATH};
+use parser::is_reserved_word;
+use shell::describe_nix_error;
+use shell::ControlFlow;
+use shell::Shell;
+
+/* What a command name can stand for, in the order the shell looks for
+it. Files remember whether they were found in the hash table. */
+#[derive(PartialEq, Debug)]
+pub enum Resolution {
+    Alias(String),
+    Keyword,
+    Function,
+    Builtin,
+    File(String, bool),
+}
+
+/* Finds everything a name stands for, the one the shell would run
+first. Without `all` only the first file found is included, from the
+hash table if it is there. */
+pub fn resolve(shell: &Shell, name: &str, all: bool) -> Vec<Resolution> {
+    let mut resolutions = Vec::new();
+    if let Some(value) = shell.aliases.get(name) {
+        resolutions.push(Resolution::Alias(value.clone()));
+    }
+    if is_reserved_word(name) {
+        resolutions.push(Resolution::Keyword);
+    }
+    if shell.functions.contains_key(name) {
+        resolutions.push(Resolution::Function);
+    }
+    if shell.builtins.get(name).is_some() {
+        resolutions.push(Resolution::Builtin);
+    }
+    resolutions.extend(resolve_files(shell, name, all));
+    resolutions
+}
+
+fn resolve_files(shell: &Shell, name: &str, all: bool) -> Vec<Resolution> {
+    if name.contains('/') {
+        if is_executable_file(name) {
+            return vec![Resolution::File(name.to_string(), false)];
+        }
+        return Vec::new();
+    }
+    if let (false, Some(hashed)) = (all, shell.command_hash.get(name)) {
+        return vec![Resolution::File(hashed.path.clone(), true)];
+    }
+    let path_variable = shell.get_variable("PATH").unwrap_or(DEFAULT_PATH);
+    let paths = search_path_all(path_variable, name).map(|path| Resolution::File(path, false));
+    if all {
+        paths.collect()
+    } else {
+        paths.take(1).collect()
+    }
+}
+
+fn describe(name: &str, resolution: &Resolution) -> String {
+    match resolution {
+        Resolution::Alias(value) => format!("{} is aliased to `{}'", name, value),
+        Resolution::Keyword => format!("{} is a shell keyword", name),
+        Resolution::Function => format!("{} is a function", name),
+        Resolution::Builtin => format!("{} is a shell builtin", name),
+        Resolution::File(path, true) => format!("{} is hashed ({})", name, path),
+        Resolution::File(path, false) => format!("{} is {}", name, path),
+    }
+}
+
+/* Parses single letter options from the given set. Returns them along
+with the position of the first operand, or None after reporting an
+invalid one. */
+fn parse_options(
+    arguments: &[String],
+    valid: &str,
+    streams: &mut Streams,
+) -> Option<(String, usize)> {
+    let mut options = String::new();
+    let mut position = 1;
+    while let Some(argument) = arguments.get(position) {
+        if !argument.starts_with('-') || argument == "-" {
+            break;
+        }
+        position += 1;
+        if argument == "--" {
+            break;
+        }
+        for option in argument[1..].chars() {
+            if !valid.contains(option) {
+                streams.error(format!("{}: -{}: invalid option", arguments[0], option));
+                return None;
+            }
+            options.push(option);
+        }
+    }
+    Some((options, position))
+}
+
+/* `type [-afptP] name...` tells what each name stands for as a command.
+-a shows every match instead of the one that would run, -f leaves out
+functions, -t prints a single word for the kind of match and -p only
+the path of a file. -P looks for a file even if the name stands for
+something else. */
+pub fn builtin_type(shell: &mut Shell, arguments: &[String], streams: &mut Streams) -> i32 {
+    let (options, position) = match parse_options(arguments, "afptP", streams) {
+        Some(parsed) => parsed,
+        None => return 2,
+    };
+    let all = options.contains('a');
+    let mut status = 0;
+    for name in &arguments[position..] {
+        let mut resolutions = if options.contains('P') {
+            resolve_files(shell, name, all)
+        } else {
+            resolve(shell, name, all)
+        };
+        if options.contains('f') {
+            resolutions.retain(|resolution| *resolution != Resolution::Function);
+        }
+        if !all {
+            resolutions.truncate(1);
+        }
+        if resolutions.is_empty() {
+            if !options.contains('t') && !options.contains('p') && !options.contains('P') {
+                streams.error(format!("type: {}: not found", name));
+            }
+            status = 1;
+        }
+        for resolution in &resolutions {
+            let line = match resolution {
+                _ if options.contains('t') => match resolution {
+                    Resolution::Alias(_) => "alias".to_string(),
+                    Resolution::Keyword => "keyword".to_string(),
+                    Resolution::Function => "function".to_string(),
+                    Resolution::Builtin => "builtin".to_string(),
+                    Resolution::File(_, _) => "file".to_string(),
+                },
+                Resolution::File(path, _) if options.contains('p') || options.contains('P') => {
+                    path.clone()
+                }
+                _ if options.contains('p') => continue,
+                _ => describe(name, resolution),
+            };
+            let _ = writeln!(streams.stdout, "{}", line);
+        }
+    }
+    status
+}
+
+/* `command [-pvV] name [argument...]` runs a builtin or program, leaving
+out functions. -p searches the default PATH instead of the one set. -v
+prints what would run and -V describes it like `type`. */
+pub fn builtin_command(shell: &mut Shell, arguments: &[String], streams: &mut Streams) -> i32 {
+    let (options, position) = match parse_options(arguments, "pvV", streams) {
+        Some(parsed) => parsed,
+        None => return 2,
+    };
+    let operands = &arguments[position..];
+    if operands.is_empty() {
+        return 0;
+    }
+    if options.contains('v') || options.contains('V') {
+        let mut status = 0;
+        for name in operands {
+            match resolve(shell, name, false).first() {
+                Some(resolution) if options.contains('V') => {
+                    let _ = writeln!(streams.stdout, "{}", describe(name, resolution));
+                }
+                Some(Resolution::File(path, _)) => {
+                    let _ = writeln!(streams.stdout, "{}", path);
+                }
+                Some(Resolution::Alias(value)) => {
+                    let _ = writeln!(streams.stdout, "alias {}={}", name, single_quote(value));
+                }
+                Some(_) => {
+                    let _ = writeln!(streams.stdout, "{}", name);
+                }
+                None => {
+                    if options.contains('V') {
+                        streams.error(format!("command: {}: not found", name));
+                    }
+                    status = 1;
+                }
+            }
+        }
+        return status;
+    }
+    if let Some(builtin) = shell.builtins.get(&operands[0]) {
+        return builtin.run(shell, operands, streams);
+    }
+    let _ = streams.stdout.flush();
+    let path_variable = if options.contains('p') {
+        Some(DEFAULT_PATH)
+    } else {
+        None
+    };
+    match run_external(shell, operands, Vec::new(), &[], path_variable) {
+        Ok(status) => status,
+        Err(err) => {
+            streams.error(format!("command: {}", describe_nix_error(&err)));
+            1
+        }
+    }
+}
+
+/* `builtin name [argument...]` runs a builtin even when a function has
+its name. */
+pub fn builtin_builtin(shell: &mut Shell, arguments: &[String], streams: &mut Streams) -> i32 {
+    let name = match arguments.get(1) {
+        Some(name) => name,
+        None => return 0,
+    };
+    match shell.builtins.get(name) {
+        Some(builtin) => builtin.run(shell, &arguments[1..], streams),
+        None => {
+            streams.error(format!("builtin: {}: not a shell builtin", name));
+            1
+        }
+    }
+}
+
+fn exec_usage(streams: &mut Streams) -> i32 {
+    let _ = writeln!(
+        streams.stderr,
+        "exec: usage: exec [-cl] [-a name] [command [argument ...]] [redirection ...]"
+    );
+    2
+}
+
+/* `exec [-cl] [-a name] [command [argument...]]` replaces the shell by
+the command, without forking. -c starts it with an empty environment,
+-l puts a dash in front of its name like for a login shell, and -a
+gives it another name. Without a command, the redirections of `exec`
+stay in effect in the shell, which the executor takes care of. A shell
+that isn't interactive exits when the command can't be run. */
+pub fn builtin_exec(shell: &mut Shell, arguments: &[String], streams: &mut Streams) -> i32 {
+    let (mut clear_environment, mut login) = (false, false);
+    let mut name = None;
+    let mut position = 1;
+    while let Some(argument) = arguments.get(position) {
+        if !argument.starts_with('-') || argument == "-" {
+            break;
+        }
+        position += 1;
+        if argument == "--" {
+            break;
+        }
+        for option in argument[1..].chars() {
+            match option {
+                'c' => clear_environment = true,
+                'l' => login = true,
+                'a' => match arguments.get(position) {
+                    Some(value) => {
+                        name = Some(value.clone());
+                        position += 1;
+                    }
+                    None => {
+                        streams.error("exec: -a: option requires an argument");
+                        return exec_usage(streams);
+                    }
+                },
+                _ => {
+                    streams.error(format!("exec: -{}: invalid option", option));
+                    return exec_usage(streams);
+                }
+            }
+        }
+    }
+    let operands = &arguments[position..];
+    if operands.is_empty() {
+        return 0;
+    }
+    let mut program_arguments = operands.to_vec();
+    if let Some(name) = name {
+        program_arguments[0] = name;
+    }
+    if login {
+        program_arguments[0] = format!("-{}", program_arguments[0]);
+    }
+    let (err, status) = match find_command(shell, &operands[0]) {
+        Some(path) => {
+            let environment = if clear_environment {
+                Vec::new()
+            } else {
+                shell.environment()
+            };
+            let _ = streams.stdout.flush();
+            let err = replace_process(path, &program_arguments, environment);
+            let status = exec_failure_status(&err);
+            (err.to_string(), status)
+        }
+        None => (format!("exec: {}: not found", operands[0]), 127),
+    };
+    streams.error(err);
+    if !shell.interactive {
+        shell.control_flow = ControlFlow::Exit(status);
+    }
+    status
+}
+
+/* `which [-a] name...` prints the file each name runs, or every file
+called that in PATH with -a. */
+pub fn builtin_which(shell: &mut Shell, arguments: &[String], streams: &mut Streams) -> i32 {
+    let (options, position) = match parse_options(arguments, "a", streams) {
+        Some(parsed) => parsed,
+        None => return 2,
+    };
+    let mut status = 0;
+    for name in &arguments[position..] {
+        let files = resolve_files(shell, name, options.contains('a'));
+        if files.is_empty() {
+            status = 1;
+        }
+        for file in files {
+            if let Resolution::File(path, _) = file {
+                let _ = writeln!(streams.stdout, "{}", path);
+            }
+        }
+    }
+    status
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_test() {
+        let mut shell = Shell::new();
+        shell.set_variable("PATH", "/nonexistent:/bin".to_string());
+        assert_eq!(resolve(&shell, "if", false), vec![Resolution::Keyword]);
+        assert_eq!(resolve(&shell, "cd", false), vec![Resolution::Builtin]);
+        assert_eq!(
+            resolve(&shell, "sh", false),
+            vec![Resolution::File("/bin/sh".to_string(), false)]
+        );
+        assert_eq!(resolve(&shell, "no-such-command", true), Vec::new());
+        let resolutions = resolve(&shell, "true", true);
+        assert_eq!(resolutions[0], Resolution::Builtin);
+        assert_eq!(
+            resolutions[1],
+            Resolution::File("/bin/true".to_string(), false)
+        );
+    }
+}
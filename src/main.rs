@@ -1,7 +1,29 @@
 extern crate sheller;
 
 use sheller::executor::*;
+use sheller::signals::block_signals_in_thread;
+use std::env;
+use std::process;
+use std::thread;
+
+/* The executor recurses for every nested construct and function call,
+so the shell runs on a thread whose stack is large enough for the
+function nesting limit. */
+const SHELL_STACK_SIZE: usize = 512 * 1024 * 1024;
+
+fn run() -> i32 {
+    let arguments: Vec<String> = env::args().collect();
+    match arguments.get(1) {
+        Some(script_file) => run_script_mode(script_file, &arguments[2..]),
+        None => run_shell_mode("history.txt"),
+    }
+}
 
 fn main() {
-    run_shell_mode("history.txt");
+    let shell_thread = thread::Builder::new()
+        .stack_size(SHELL_STACK_SIZE)
+        .spawn(run)
+        .expect("failed to start the shell thread");
+    block_signals_in_thread();
+    process::exit(shell_thread.join().unwrap_or(1));
 }
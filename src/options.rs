@@ -0,0 +1,308 @@
+use builtins::{single_quote, Streams};
+use shell::{Shell, Value};
+
+/* The options `set` turns on and off, along with the letter that stands
+for each of them, if there is one. */
+const SET_OPTIONS: [(&str, Option<char>); 6] = [
+    ("errexit", Some('e')),
+    ("noclobber", Some('C')),
+    ("noglob", Some('f')),
+    ("nounset", Some('u')),
+    ("pipefail", None),
+    ("xtrace", Some('x')),
+];
+
+/* The options that only `shopt` changes. */
+const SHOPT_OPTIONS: [&str; 2] = ["dotglob", "nullglob"];
+
+/* The options of a shell. Everything starts out turned off. */
+#[derive(Default, Clone, Debug)]
+pub struct Options {
+    pub errexit: bool,
+    pub noclobber: bool,
+    pub noglob: bool,
+    pub nounset: bool,
+    pub pipefail: bool,
+    pub xtrace: bool,
+    pub dotglob: bool,
+    pub nullglob: bool,
+}
+
+impl Options {
+    pub fn get(&self, name: &str) -> Option<bool> {
+        match name {
+            "errexit" => Some(self.errexit),
+            "noclobber" => Some(self.noclobber),
+            "noglob" => Some(self.noglob),
+            "nounset" => Some(self.nounset),
+            "pipefail" => Some(self.pipefail),
+            "xtrace" => Some(self.xtrace),
+            "dotglob" => Some(self.dotglob),
+            "nullglob" => Some(self.nullglob),
+            _ => None,
+        }
+    }
+
+    /* Turns an option on or off. Returns false if there is no option
+    with that name. */
+    pub fn set(&mut self, name: &str, value: bool) -> bool {
+        let option = match name {
+            "errexit" => &mut self.errexit,
+            "noclobber" => &mut self.noclobber,
+            "noglob" => &mut self.noglob,
+            "nounset" => &mut self.nounset,
+            "pipefail" => &mut self.pipefail,
+            "xtrace" => &mut self.xtrace,
+            "dotglob" => &mut self.dotglob,
+            "nullglob" => &mut self.nullglob,
+            _ => return false,
+        };
+        *option = value;
+        true
+    }
+
+    /* The letters of the options that are on, which is what `$-`
+    expands to. */
+    pub fn flags(&self) -> String {
+        SET_OPTIONS
+            .iter()
+            .filter(|(name, _)| self.get(name) == Some(true))
+            .filter_map(|(_, letter)| *letter)
+            .collect()
+    }
+}
+
+fn set_option_name(letter: char) -> Option<&'static str> {
+    SET_OPTIONS
+        .iter()
+        .find(|(_, known)| *known == Some(letter))
+        .map(|(name, _)| *name)
+}
+
+fn print_option(name: &str, value: bool, streams: &mut Streams) {
+    let value = if value { "on" } else { "off" };
+    let _ = writeln!(streams.stdout, "{:<15}\t{}", name, value);
+}
+
+/* Prints a variable the way it can be assigned again. */
+fn print_variable(name: &str, value: &Value, streams: &mut Streams) {
+    let _ = match value {
+        Value::Scalar(value) => writeln!(streams.stdout, "{}={}", name, quote_word(value)),
+        _ => {
+            let elements: Vec<String> = value
+                .keys()
+                .into_iter()
+                .zip(value.elements())
+                .map(|(key, element)| format!("[{}]={}", key, quote_word(&element)))
+                .collect();
+            writeln!(streams.stdout, "{}=({})", name, elements.join(" "))
+        }
+    };
+}
+
+/* Quotes a word only if it contains characters the shell would treat
+specially, for output that is meant to be read by people and by the
+shell alike. */
+pub fn quote_word(word: &str) -> String {
+    let is_plain =
+        |character: char| character.is_ascii_alphanumeric() || "_-./,:=+@%^".contains(character);
+    if !word.is_empty() && word.chars().all(is_plain) {
+        word.to_string()
+    } else {
+        single_quote(word)
+    }
+}
+
+fn set_usage(streams: &mut Streams) -> i32 {
+    let _ = writeln!(
+        streams.stderr,
+        "set: usage: set [-efuxC] [-o option-name] [--] [-] [arg ...]"
+    );
+    2
+}
+
+/* `set [-efuxC] [-o option-name] [--] [arg...]` turns options on with
+`-` and off with `+`, and makes the remaining arguments the positional
+parameters. `-o` without a name lists the options, and `+o` prints the
+commands that would set them again. Without arguments every variable
+is printed. */
+pub fn builtin_set(shell: &mut Shell, arguments: &[String], streams: &mut Streams) -> i32 {
+    if arguments.len() == 1 {
+        for name in shell.variable_names() {
+            if let Some(value) = shell.get_value(&name) {
+                print_variable(&name, value, streams);
+            }
+        }
+        return 0;
+    }
+    let mut position = 1;
+    let mut replace_parameters = false;
+    while let Some(argument) = arguments.get(position) {
+        let enable = match argument.chars().next() {
+            Some('-') => true,
+            Some('+') => false,
+            _ => break,
+        };
+        position += 1;
+        if argument == "--" {
+            replace_parameters = true;
+            break;
+        }
+        if argument == "-" || argument == "+" {
+            break;
+        }
+        for option in argument[1..].chars() {
+            if option != 'o' {
+                match set_option_name(option) {
+                    Some(name) => {
+                        shell.options.set(name, enable);
+                    }
+                    None => {
+                        streams.error(format!("set: {}{}: invalid option", &argument[..1], option));
+                        return set_usage(streams);
+                    }
+                }
+                continue;
+            }
+            let name = match arguments.get(position) {
+                Some(name) if !name.starts_with('-') && !name.starts_with('+') => name,
+                _ => {
+                    for (name, _) in SET_OPTIONS.iter() {
+                        let value = shell.options.get(name) == Some(true);
+                        if enable {
+                            print_option(name, value, streams);
+                        } else {
+                            let sign = if value { '-' } else { '+' };
+                            let _ = writeln!(streams.stdout, "set {}o {}", sign, name);
+                        }
+                    }
+                    continue;
+                }
+            };
+            position += 1;
+            if !SET_OPTIONS.iter().any(|(known, _)| known == name) {
+                streams.error(format!("set: {}: invalid option name", name));
+                return 2;
+            }
+            shell.options.set(name, enable);
+        }
+    }
+    if replace_parameters || position < arguments.len() {
+        shell.positional_parameters = arguments[position..].to_vec();
+    }
+    0
+}
+
+fn shopt_usage(streams: &mut Streams) -> i32 {
+    let _ = writeln!(
+        streams.stderr,
+        "shopt: usage: shopt [-pqsu] [-o] [optname ...]"
+    );
+    2
+}
+
+/* `shopt [-pqsu] [-o] [optname...]` turns options on with -s and off
+with -u. Otherwise it shows whether they are on, and its status tells
+whether all of them are. -q shows nothing and -p shows them as commands.
+Without -o it works on its own options, with -o on those of `set`. */
+pub fn builtin_shopt(shell: &mut Shell, arguments: &[String], streams: &mut Streams) -> i32 {
+    let (mut enable, mut disable, mut quiet, mut reusable, mut set_options) =
+        (false, false, false, false, false);
+    let mut position = 1;
+    while let Some(argument) = arguments.get(position) {
+        if !argument.starts_with('-') || argument == "-" {
+            break;
+        }
+        position += 1;
+        if argument == "--" {
+            break;
+        }
+        for option in argument[1..].chars() {
+            match option {
+                's' => enable = true,
+                'u' => disable = true,
+                'q' => quiet = true,
+                'p' => reusable = true,
+                'o' => set_options = true,
+                _ => {
+                    streams.error(format!("shopt: -{}: invalid option", option));
+                    return shopt_usage(streams);
+                }
+            }
+        }
+    }
+    if enable && disable {
+        streams.error("shopt: cannot set and unset shell options simultaneously");
+        return 1;
+    }
+    let known: Vec<&str> = if set_options {
+        SET_OPTIONS.iter().map(|(name, _)| *name).collect()
+    } else {
+        SHOPT_OPTIONS.to_vec()
+    };
+    let names: Vec<&str> = if position < arguments.len() {
+        arguments[position..]
+            .iter()
+            .map(|name| name.as_str())
+            .collect()
+    } else {
+        known.clone()
+    };
+    let listing_all = position == arguments.len();
+    let mut status = 0;
+    for name in names {
+        if !known.contains(&name) {
+            let kind = if set_options {
+                "option name"
+            } else {
+                "shell option name"
+            };
+            streams.error(format!("shopt: {}: invalid {}", name, kind));
+            status = 1;
+            continue;
+        }
+        if (enable || disable) && !listing_all {
+            shell.options.set(name, enable);
+            continue;
+        }
+        let value = shell.options.get(name) == Some(true);
+        if !value && !listing_all {
+            status = 1;
+        }
+        if quiet || (listing_all && ((enable && !value) || (disable && value))) {
+            continue;
+        }
+        if reusable {
+            let _ = match (set_options, value) {
+                (true, true) => writeln!(streams.stdout, "set -o {}", name),
+                (true, false) => writeln!(streams.stdout, "set +o {}", name),
+                (false, true) => writeln!(streams.stdout, "shopt -s {}", name),
+                (false, false) => writeln!(streams.stdout, "shopt -u {}", name),
+            };
+        } else {
+            print_option(name, value, streams);
+        }
+    }
+    status
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn options_test() {
+        let mut options = Options::default();
+        assert!(options.set("errexit", true));
+        assert!(options.set("xtrace", true));
+        assert!(options.set("pipefail", true));
+        assert!(!options.set("nosuchoption", true));
+        assert_eq!(options.get("errexit"), Some(true));
+        assert_eq!(options.get("nounset"), Some(false));
+        assert_eq!(options.get("nosuchoption"), None);
+        assert_eq!(options.flags(), "ex");
+        assert_eq!(quote_word("a/b-c.txt"), "a/b-c.txt");
+        assert_eq!(quote_word("a b"), "'a b'");
+        assert_eq!(quote_word(""), "''");
+    }
+}
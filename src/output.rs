@@ -0,0 +1,757 @@
+use builtins::Streams;
+use parser::is_valid_name;
+use shell::describe_io_error;
+use shell::Shell;
+
+/* Where a backslash escape is decoded, which changes how octal numbers
+are written and whether `\c` is special. */
+#[derive(Clone, Copy, PartialEq)]
+enum EscapeStyle {
+    /* `echo -e`: octal numbers start with 0, as in `\0101`. */
+    Echo,
+    /* Arguments of `%b`: octal numbers may start with 0 or not. */
+    Argument,
+    /* Formats of printf: `\101`, and `\c` is just text. */
+    Format,
+}
+
+enum Escape {
+    Bytes(Vec<u8>),
+    Stop,
+}
+
+fn parse_digits(text: &str, radix: u32, limit: usize) -> (u32, usize) {
+    let length = text
+        .chars()
+        .take(limit)
+        .take_while(|character| character.is_digit(radix))
+        .count();
+    (
+        u32::from_str_radix(&text[..length], radix).unwrap_or(0),
+        length,
+    )
+}
+
+fn encode(character: char) -> Vec<u8> {
+    character.to_string().into_bytes()
+}
+
+/* Decodes the escape sequence that follows a backslash at the start of
+`text`, returning the bytes it stands for, or Stop for `\c`, along with
+the length of the sequence. Unknown escapes keep their backslash. */
+fn decode_escape(text: &str, style: EscapeStyle) -> (Escape, usize) {
+    let character = match text.chars().next() {
+        Some(character) => character,
+        None => return (Escape::Bytes(b"\\".to_vec()), 0),
+    };
+    let byte = match character {
+        'a' => 0x07,
+        'b' => 0x08,
+        'e' | 'E' => 0x1b,
+        'f' => 0x0c,
+        'n' => b'\n',
+        'r' => b'\r',
+        't' => b'\t',
+        'v' => 0x0b,
+        '\\' => b'\\',
+        '\'' | '"' | '?' if style == EscapeStyle::Format => character as u8,
+        'c' if style != EscapeStyle::Format => return (Escape::Stop, 1),
+        '0' if style != EscapeStyle::Format => {
+            let (value, length) = parse_digits(&text[1..], 8, 3);
+            return (Escape::Bytes(vec![value as u8]), length + 1);
+        }
+        '0'..='7' if style != EscapeStyle::Echo => {
+            let (value, length) = parse_digits(text, 8, 3);
+            return (Escape::Bytes(vec![value as u8]), length);
+        }
+        'x' | 'u' | 'U' => {
+            let limit = match character {
+                'x' => 2,
+                'u' => 4,
+                _ => 8,
+            };
+            let (value, length) = parse_digits(&text[1..], 16, limit);
+            if length == 0 {
+                return (Escape::Bytes(format!("\\{}", character).into_bytes()), 1);
+            }
+            let bytes = if character == 'x' {
+                vec![value as u8]
+            } else {
+                encode(std::char::from_u32(value).unwrap_or('\u{fffd}'))
+            };
+            return (Escape::Bytes(bytes), length + 1);
+        }
+        _ => {
+            let mut bytes = b"\\".to_vec();
+            bytes.extend(encode(character));
+            return (Escape::Bytes(bytes), character.len_utf8());
+        }
+    };
+    (Escape::Bytes(vec![byte]), 1)
+}
+
+/* Expands the backslash escapes of a string. The flag tells whether
+`\c` asked for the output to stop there. */
+fn expand_escapes(text: &str, style: EscapeStyle) -> (Vec<u8>, bool) {
+    let mut bytes = Vec::new();
+    let mut position = 0;
+    while let Some(offset) = text[position..].find('\\') {
+        bytes.extend(&text.as_bytes()[position..position + offset]);
+        position += offset + 1;
+        let (escape, length) = decode_escape(&text[position..], style);
+        position += length;
+        match escape {
+            Escape::Bytes(escaped) => bytes.extend(escaped),
+            Escape::Stop => return (bytes, true),
+        }
+    }
+    bytes.extend(&text.as_bytes()[position..]);
+    (bytes, false)
+}
+
+/* Writes everything at once and reports if that fails, like when
+standard output is closed. */
+fn write_output(name: &str, bytes: &[u8], streams: &mut Streams) -> i32 {
+    let result = streams
+        .stdout
+        .write_all(bytes)
+        .and_then(|_| streams.stdout.flush());
+    match result {
+        Ok(()) => 0,
+        Err(err) => {
+            streams.error(format!(
+                "{}: write error: {}",
+                name,
+                describe_io_error(&err)
+            ));
+            1
+        }
+    }
+}
+
+/* `echo [-neE] [arg...]` prints its arguments separated by spaces and
+followed by a newline, which -n leaves out. -e expands backslash
+escapes, where `\c` ends the output, and -E turns that off again. Only
+arguments made entirely of these letters are options. */
+pub fn builtin_echo(_shell: &mut Shell, arguments: &[String], streams: &mut Streams) -> i32 {
+    let (mut newline, mut escapes) = (true, false);
+    let mut position = 1;
+    while let Some(argument) = arguments.get(position) {
+        let is_option = argument.len() > 1
+            && argument.starts_with('-')
+            && argument[1..].chars().all(|option| "neE".contains(option));
+        if !is_option {
+            break;
+        }
+        for option in argument[1..].chars() {
+            match option {
+                'n' => newline = false,
+                'e' => escapes = true,
+                _ => escapes = false,
+            }
+        }
+        position += 1;
+    }
+    let text = arguments[position..].join(" ");
+    let mut bytes = if escapes {
+        let (bytes, stopped) = expand_escapes(&text, EscapeStyle::Echo);
+        if stopped {
+            return write_output("echo", &bytes, streams);
+        }
+        bytes
+    } else {
+        text.into_bytes()
+    };
+    if newline {
+        bytes.push(b'\n');
+    }
+    write_output("echo", &bytes, streams)
+}
+
+/* A conversion specification of printf, like `%-*.3d`. */
+#[derive(Default)]
+struct Specification {
+    left: bool,
+    plus: bool,
+    space: bool,
+    alternate: bool,
+    zero: bool,
+    width: usize,
+    precision: Option<usize>,
+}
+
+impl Specification {
+    /* Pads a converted value to the width. Zeros go between the sign or
+    prefix and the digits. */
+    fn pad(&self, prefix: &str, body: &str, zero_allowed: bool) -> String {
+        let length = prefix.chars().count() + body.chars().count();
+        let padding = self.width.saturating_sub(length);
+        if self.left {
+            format!("{}{}{}", prefix, body, " ".repeat(padding))
+        } else if self.zero && zero_allowed {
+            format!("{}{}{}", prefix, "0".repeat(padding), body)
+        } else {
+            format!("{}{}{}", " ".repeat(padding), prefix, body)
+        }
+    }
+
+    fn sign(&self, negative: bool) -> &'static str {
+        if negative {
+            "-"
+        } else if self.plus {
+            "+"
+        } else if self.space {
+            " "
+        } else {
+            ""
+        }
+    }
+}
+
+/* Formats a float like C's %e, with at least two digits of exponent. */
+fn format_exponent(value: f64, precision: usize, alternate: bool) -> String {
+    let formatted = format!("{:.*e}", precision, value);
+    let (mantissa, exponent) = formatted.split_at(formatted.find('e').unwrap_or(formatted.len()));
+    let exponent: i32 = exponent[1..].parse().unwrap_or(0);
+    let point = if alternate && precision == 0 { "." } else { "" };
+    let sign = if exponent < 0 { '-' } else { '+' };
+    format!("{}{}e{}{:02}", mantissa, point, sign, exponent.abs())
+}
+
+/* Formats a float like C's %g, which picks %e for very large and very
+small numbers and %f for the others, without trailing zeros. */
+fn format_general(value: f64, precision: usize, alternate: bool) -> String {
+    let precision = precision.max(1);
+    let formatted = format!("{:.*e}", precision - 1, value);
+    let exponent: i32 = formatted[formatted.find('e').unwrap_or(0) + 1..]
+        .parse()
+        .unwrap_or(0);
+    let mut result = if exponent < -4 || exponent >= precision as i32 {
+        format_exponent(value, precision - 1, alternate)
+    } else {
+        let formatted = format!("{:.*}", (precision as i32 - 1 - exponent) as usize, value);
+        if alternate && !formatted.contains('.') {
+            formatted + "."
+        } else {
+            formatted
+        }
+    };
+    if !alternate && result.contains('.') {
+        let end = result.find('e').unwrap_or(result.len());
+        let mantissa = result[..end]
+            .trim_end_matches('0')
+            .trim_end_matches('.')
+            .to_string();
+        result = mantissa + &result[end..];
+    }
+    result
+}
+
+/* Quotes a string with backslashes so that the shell reads it back as
+it was. Strings with control characters are written as `$'...'`. */
+pub fn backslash_quote(value: &str) -> String {
+    if value.is_empty() {
+        return "''".to_string();
+    }
+    if value.chars().any(|character| character.is_control()) {
+        let mut quoted = "$'".to_string();
+        for character in value.chars() {
+            match character {
+                '\x07' => quoted.push_str("\\a"),
+                '\x08' => quoted.push_str("\\b"),
+                '\x0c' => quoted.push_str("\\f"),
+                '\n' => quoted.push_str("\\n"),
+                '\r' => quoted.push_str("\\r"),
+                '\t' => quoted.push_str("\\t"),
+                '\x0b' => quoted.push_str("\\v"),
+                '\x1b' => quoted.push_str("\\E"),
+                '\\' | '\'' => {
+                    quoted.push('\\');
+                    quoted.push(character);
+                }
+                _ if character.is_control() => {
+                    quoted.push_str(&format!("\\{:03o}", character as u32))
+                }
+                _ => quoted.push(character),
+            }
+        }
+        quoted.push('\'');
+        return quoted;
+    }
+    let mut quoted = String::new();
+    for (index, character) in value.char_indices() {
+        let special = " \t'\"\\|&;()<>!{}*[?]^$`,".contains(character)
+            || (index == 0 && (character == '~' || character == '#'));
+        if special {
+            quoted.push('\\');
+        }
+        quoted.push(character);
+    }
+    quoted
+}
+
+/* The state of one run of printf: the arguments the conversions take
+their values from, the output so far and whether something failed. */
+struct Printf<'a> {
+    arguments: &'a [String],
+    position: usize,
+    output: Vec<u8>,
+    errors: Vec<String>,
+    status: i32,
+}
+
+impl<'a> Printf<'a> {
+    fn next_argument(&mut self) -> Option<&'a str> {
+        let argument = self.arguments.get(self.position)?;
+        self.position += 1;
+        Some(argument)
+    }
+
+    fn invalid_number(&mut self, argument: &str) {
+        self.errors
+            .push(format!("printf: {}: invalid number", argument));
+        self.status = 1;
+    }
+
+    /* Takes an integer argument the way C reads one: with an optional
+    sign, in hex after 0x and in octal after 0. A quote in front stands
+    for the code of the character after it. Whatever number starts an
+    invalid argument is still used. */
+    fn next_integer(&mut self) -> i64 {
+        let argument = match self.next_argument() {
+            Some(argument) if !argument.is_empty() => argument,
+            _ => return 0,
+        };
+        if argument.starts_with('\'') || argument.starts_with('"') {
+            return argument[1..]
+                .chars()
+                .next()
+                .map_or(0, |character| character as i64);
+        }
+        let trimmed = argument.trim_start();
+        let (negative, digits) = match trimmed.chars().next() {
+            Some('-') => (true, &trimmed[1..]),
+            Some('+') => (false, &trimmed[1..]),
+            _ => (false, trimmed),
+        };
+        let (radix, digits) = if digits.starts_with("0x") || digits.starts_with("0X") {
+            (16, &digits[2..])
+        } else if digits.starts_with('0') && digits.len() > 1 {
+            (8, &digits[1..])
+        } else {
+            (10, digits)
+        };
+        let length = digits
+            .chars()
+            .take_while(|character| character.is_digit(radix))
+            .count();
+        let mut value: i64 = 0;
+        let mut overflow = false;
+        for character in digits[..length].chars() {
+            let digit = character.to_digit(radix).unwrap_or(0) as i64;
+            match value
+                .checked_mul(radix as i64)
+                .and_then(|value| value.checked_sub(digit))
+            {
+                Some(next) => value = next,
+                None => overflow = true,
+            }
+        }
+        if !negative {
+            value = value.checked_neg().unwrap_or_else(|| {
+                overflow = true;
+                i64::MAX
+            });
+        }
+        if overflow {
+            value = if negative { i64::MIN } else { i64::MAX };
+        }
+        if length == 0 || length < digits.len() {
+            self.invalid_number(argument);
+        } else if overflow {
+            self.errors.push(format!(
+                "printf: warning: {}: Numerical result out of range",
+                argument
+            ));
+        }
+        value
+    }
+
+    /* Takes a floating point argument. The longest number at the start
+    of an invalid argument is used. */
+    fn next_float(&mut self) -> f64 {
+        let argument = match self.next_argument() {
+            Some(argument) if !argument.is_empty() => argument,
+            _ => return 0.0,
+        };
+        if argument.starts_with('\'') || argument.starts_with('"') {
+            return argument[1..]
+                .chars()
+                .next()
+                .map_or(0.0, |character| character as u32 as f64);
+        }
+        let trimmed = argument.trim_start();
+        let hex = trimmed.trim_start_matches(['-', '+']);
+        if hex.starts_with("0x") || hex.starts_with("0X") {
+            self.position -= 1;
+            return self.next_integer() as f64;
+        }
+        if let Ok(value) = trimmed.parse::<f64>() {
+            return value;
+        }
+        self.invalid_number(argument);
+        (1..trimmed.len())
+            .rev()
+            .filter(|end| trimmed.is_char_boundary(*end))
+            .find_map(|end| trimmed[..end].parse::<f64>().ok())
+            .unwrap_or(0.0)
+    }
+
+    fn format_integer(&mut self, specification: &Specification, conversion: char) -> String {
+        let value = self.next_integer();
+        let (negative, mut digits) = match conversion {
+            'd' | 'i' => (value < 0, value.unsigned_abs().to_string()),
+            'u' => (false, (value as u64).to_string()),
+            'o' => (false, format!("{:o}", value as u64)),
+            'x' => (false, format!("{:x}", value as u64)),
+            _ => (false, format!("{:X}", value as u64)),
+        };
+        if let Some(precision) = specification.precision {
+            if precision == 0 && value == 0 {
+                digits.clear();
+            }
+            if digits.len() < precision {
+                digits = "0".repeat(precision - digits.len()) + &digits;
+            }
+        }
+        let prefix = match conversion {
+            'o' if specification.alternate && !digits.starts_with('0') => "0",
+            'x' if specification.alternate && value != 0 => "0x",
+            'X' if specification.alternate && value != 0 => "0X",
+            _ => specification.sign(negative),
+        };
+        specification.pad(prefix, &digits, specification.precision.is_none())
+    }
+
+    fn format_float(&mut self, specification: &Specification, conversion: char) -> String {
+        let value = self.next_float();
+        let precision = specification.precision.unwrap_or(6);
+        let alternate = specification.alternate;
+        let magnitude = value.abs();
+        let body = if value.is_nan() {
+            "nan".to_string()
+        } else if value.is_infinite() {
+            "inf".to_string()
+        } else {
+            match conversion.to_ascii_lowercase() {
+                'f' if alternate && precision == 0 => format!("{:.0}.", magnitude),
+                'f' => format!("{:.*}", precision, magnitude),
+                'e' => format_exponent(magnitude, precision, alternate),
+                _ => format_general(magnitude, precision, alternate),
+            }
+        };
+        let body = if conversion.is_ascii_uppercase() {
+            body.to_uppercase()
+        } else {
+            body
+        };
+        let sign = specification.sign(value.is_sign_negative() && !value.is_nan());
+        specification.pad(sign, &body, value.is_finite())
+    }
+
+    fn push_text(&mut self, specification: &Specification, text: &str) {
+        let text: String = match specification.precision {
+            Some(precision) => text.chars().take(precision).collect(),
+            None => text.to_string(),
+        };
+        let padded = specification.pad("", &text, false);
+        self.output.extend(padded.into_bytes());
+    }
+
+    /* The same for the bytes of %b, which needn't be valid UTF-8, so the
+    precision and width count bytes. */
+    fn push_bytes(&mut self, specification: &Specification, bytes: &[u8]) {
+        let bytes = match specification.precision {
+            Some(precision) => &bytes[..precision.min(bytes.len())],
+            None => bytes,
+        };
+        let padding = vec![b' '; specification.width.saturating_sub(bytes.len())];
+        if specification.left {
+            self.output.extend_from_slice(bytes);
+            self.output.extend(padding);
+        } else {
+            self.output.extend(padding);
+            self.output.extend_from_slice(bytes);
+        }
+    }
+
+    /* Converts one argument for a conversion character. Returns false
+    when the output has to stop, after `\c` in an argument of %b. */
+    fn convert(&mut self, specification: &Specification, conversion: char) -> bool {
+        match conversion {
+            's' => {
+                let argument = self.next_argument().unwrap_or("");
+                self.push_text(specification, argument);
+            }
+            'q' => {
+                let argument = self.next_argument().unwrap_or("");
+                self.push_text(specification, &backslash_quote(argument));
+            }
+            'c' => match self
+                .next_argument()
+                .and_then(|argument| argument.chars().next())
+            {
+                Some(character) => self.push_text(specification, &character.to_string()),
+                None => {
+                    let padding = specification.width.saturating_sub(1);
+                    if !specification.left {
+                        self.output.extend(" ".repeat(padding).into_bytes());
+                    }
+                    self.output.push(0);
+                    if specification.left {
+                        self.output.extend(" ".repeat(padding).into_bytes());
+                    }
+                }
+            },
+            'b' => {
+                let argument = self.next_argument().unwrap_or("");
+                let (bytes, stopped) = expand_escapes(argument, EscapeStyle::Argument);
+                self.push_bytes(specification, &bytes);
+                if stopped {
+                    return false;
+                }
+            }
+            'd' | 'i' | 'u' | 'o' | 'x' | 'X' => {
+                let converted = self.format_integer(specification, conversion);
+                self.output.extend(converted.into_bytes());
+            }
+            _ => {
+                let converted = self.format_float(specification, conversion);
+                self.output.extend(converted.into_bytes());
+            }
+        }
+        true
+    }
+
+    /* Reads the number of a width or precision, which `*` takes from
+    the arguments instead. */
+    fn number(&mut self, format: &str, index: &mut usize) -> Option<i64> {
+        if format[*index..].starts_with('*') {
+            *index += 1;
+            return Some(self.next_integer());
+        }
+        let length = format[*index..]
+            .chars()
+            .take_while(|character| character.is_ascii_digit())
+            .count();
+        let digits = &format[*index..*index + length];
+        *index += length;
+        digits.parse().ok()
+    }
+
+    /* Goes through the format once. Returns false when the output has
+    to stop, because of `\c` or an invalid conversion. */
+    fn run(&mut self, format: &str) -> bool {
+        let mut index = 0;
+        while index < format.len() {
+            let rest = &format[index..];
+            let character = rest.chars().next().unwrap_or('\0');
+            if character == '\\' {
+                let (escape, length) = decode_escape(&rest[1..], EscapeStyle::Format);
+                if let Escape::Bytes(bytes) = escape {
+                    self.output.extend(bytes);
+                }
+                index += length + 1;
+                continue;
+            }
+            if character != '%' {
+                self.output.extend(encode(character));
+                index += character.len_utf8();
+                continue;
+            }
+            if rest.starts_with("%%") {
+                self.output.push(b'%');
+                index += 2;
+                continue;
+            }
+            index += 1;
+            let mut specification = Specification::default();
+            while let Some(flag) = format[index..].chars().next() {
+                match flag {
+                    '-' => specification.left = true,
+                    '+' => specification.plus = true,
+                    ' ' => specification.space = true,
+                    '#' => specification.alternate = true,
+                    '0' => specification.zero = true,
+                    _ => break,
+                }
+                index += 1;
+            }
+            if let Some(width) = self.number(format, &mut index) {
+                if width < 0 {
+                    specification.left = true;
+                }
+                specification.width = width.unsigned_abs() as usize;
+            }
+            if format[index..].starts_with('.') {
+                index += 1;
+                let precision = self.number(format, &mut index).unwrap_or(0);
+                specification.precision = if precision < 0 {
+                    None
+                } else {
+                    Some(precision as usize)
+                };
+            }
+            index += format[index..]
+                .chars()
+                .take_while(|character| "hlLjzt".contains(*character))
+                .count();
+            let conversion = match format[index..].chars().next() {
+                Some(conversion) => conversion,
+                None => {
+                    self.errors
+                        .push("printf: `%': missing format character".to_string());
+                    self.status = 1;
+                    return false;
+                }
+            };
+            index += conversion.len_utf8();
+            if !"sqcbdiuoxXfFeEgG".contains(conversion) {
+                self.errors.push(format!(
+                    "printf: `{}': invalid format character",
+                    conversion
+                ));
+                self.status = 1;
+                return false;
+            }
+            if !self.convert(&specification, conversion) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+fn printf_usage(streams: &mut Streams) -> i32 {
+    let _ = writeln!(
+        streams.stderr,
+        "printf: usage: printf [-v var] format [arguments]"
+    );
+    2
+}
+
+/* `printf [-v var] format [argument...]` prints the arguments as the
+format describes, with the conversions %s, %b (with backslash escapes),
+%q (quoted for the shell), %c, %d, %i, %u, %o, %x, %X, %f, %e and %g.
+Widths and precisions can come from the arguments with `*`. The format
+is used again as long as there are arguments left. With -v the output
+goes into a variable instead. */
+pub fn builtin_printf(shell: &mut Shell, arguments: &[String], streams: &mut Streams) -> i32 {
+    let mut variable = None;
+    let mut position = 1;
+    while let Some(argument) = arguments.get(position) {
+        if argument == "--" {
+            position += 1;
+            break;
+        }
+        if argument != "-v" {
+            if argument.starts_with('-') && argument.len() > 1 {
+                streams.error(format!("printf: {}: invalid option", argument));
+                return printf_usage(streams);
+            }
+            break;
+        }
+        match arguments.get(position + 1) {
+            Some(name) => variable = Some(name),
+            None => {
+                streams.error("printf: -v: option requires an argument");
+                return printf_usage(streams);
+            }
+        }
+        position += 2;
+    }
+    if let Some(name) = variable {
+        if !is_valid_name(name) {
+            streams.error(format!("printf: `{}': not a valid identifier", name));
+            return 2;
+        }
+    }
+    let format = match arguments.get(position) {
+        Some(format) => format,
+        None => return printf_usage(streams),
+    };
+    let mut printf = Printf {
+        arguments: &arguments[position + 1..],
+        position: 0,
+        output: Vec::new(),
+        errors: Vec::new(),
+        status: 0,
+    };
+    loop {
+        let start = printf.position;
+        if !printf.run(format)
+            || printf.position == start
+            || printf.position >= printf.arguments.len()
+        {
+            break;
+        }
+    }
+    for error in &printf.errors {
+        streams.error(error);
+    }
+    match variable {
+        Some(name) => {
+            shell.set_variable(name, String::from_utf8_lossy(&printf.output).into_owned());
+            printf.status
+        }
+        None => match write_output("printf", &printf.output, streams) {
+            0 => printf.status,
+            status => status,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expand_escapes_test() {
+        assert_eq!(
+            expand_escapes("a\\tb\\x41\\0101", EscapeStyle::Echo),
+            (b"a\tbAA".to_vec(), false)
+        );
+        assert_eq!(
+            expand_escapes("\\101\\q", EscapeStyle::Echo),
+            (b"\\101\\q".to_vec(), false)
+        );
+        assert_eq!(
+            expand_escapes("\\101\\0102", EscapeStyle::Format),
+            (b"A\x082".to_vec(), false)
+        );
+        assert_eq!(
+            expand_escapes("x\\cy", EscapeStyle::Argument),
+            (b"x".to_vec(), true)
+        );
+        assert_eq!(format_exponent(12345.678, 6, false), "1.234568e+04");
+        assert_eq!(format_general(0.0001, 6, false), "0.0001");
+        assert_eq!(format_general(1e-5, 6, false), "1e-05");
+        assert_eq!(format_general(1.5, 6, true), "1.50000");
+        assert_eq!(backslash_quote("it's a,b"), "it\\'s\\ a\\,b");
+        assert_eq!(backslash_quote("a\nb"), "$'a\\nb'");
+    }
+
+    #[test]
+    fn printf_bytes_test() {
+        let arguments = vec!["\\xff".to_string(), "\\xc3\\xa9z".to_string()];
+        let mut printf = Printf {
+            arguments: &arguments,
+            position: 0,
+            output: Vec::new(),
+            errors: Vec::new(),
+            status: 0,
+        };
+        printf.run("[%3b][%-4.2b]");
+        assert_eq!(printf.output, b"[  \xff][\xc3\xa9  ]".to_vec());
+    }
+}
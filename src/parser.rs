@@ -0,0 +1,1576 @@
+use conditional::is_binary_operator;
+use conditional::is_unary_operator;
+use lexer::tokenize_string;
+use lexer::HereDocument;
+use lexer::Operator;
+use lexer::ParseError;
+use lexer::Token;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum RedirectionKind {
+    Input(String),
+    Output(String),
+    Clobber(String),
+    Append(String),
+    ReadWrite(String),
+    DuplicateInput(String),
+    DuplicateOutput(String),
+    HereDocument(HereDocument),
+    HereString(String),
+}
+
+/* A redirection of `fd`, or of a new descriptor whose number goes to
+`variable` for `{name}>` and the like. */
+#[derive(PartialEq, Debug, Clone)]
+pub struct Redirection {
+    pub fd: Option<i32>,
+    pub variable: Option<String>,
+    pub kind: RedirectionKind,
+}
+
+impl Redirection {
+    /* The file descriptor being redirected, falling back to stdin or
+    stdout depending on the direction of the operator. */
+    pub fn target_fd(&self) -> i32 {
+        match (self.fd, &self.kind) {
+            (Some(fd), _) => fd,
+            (None, RedirectionKind::Input(_))
+            | (None, RedirectionKind::ReadWrite(_))
+            | (None, RedirectionKind::DuplicateInput(_))
+            | (None, RedirectionKind::HereDocument(_))
+            | (None, RedirectionKind::HereString(_)) => 0,
+            (None, _) => 1,
+        }
+    }
+}
+
+/* The value of an assignment is a single word or, for `name=(...)`, the
+words making up an array. */
+#[derive(PartialEq, Debug, Clone)]
+pub enum AssignmentValue {
+    Scalar(String),
+    Array(Vec<String>),
+}
+
+/* `name=value` or `name[subscript]=value`. With `+=` the value is
+appended to what the variable already holds. */
+#[derive(PartialEq, Debug, Clone)]
+pub struct Assignment {
+    pub name: String,
+    pub subscript: Option<String>,
+    pub append: bool,
+    pub value: AssignmentValue,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct SimpleCommand {
+    pub assignments: Vec<Assignment>,
+    pub words: Vec<String>,
+    pub redirections: Vec<Redirection>,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum CompoundCommand {
+    Subshell(List),
+    BraceGroup(List),
+    If {
+        branches: Vec<(List, List)>,
+        else_branch: Option<List>,
+    },
+    While {
+        condition: List,
+        body: List,
+        until: bool,
+    },
+    For {
+        name: String,
+        words: Option<Vec<String>>,
+        body: List,
+    },
+    ArithmeticFor {
+        initialization: String,
+        condition: String,
+        update: String,
+        body: List,
+    },
+    Arithmetic(String),
+    Conditional(ConditionalExpression),
+    Case {
+        word: String,
+        items: Vec<CaseItem>,
+    },
+}
+
+/* The expression inside `[[ ... ]]`. Operands stay unexpanded words,
+since they are expanded without field splitting or pathname expansion,
+and only if `&&` and `||` get to them. */
+#[derive(PartialEq, Debug, Clone)]
+pub enum ConditionalExpression {
+    Word(String),
+    Unary(String, String),
+    Binary(String, String, String),
+    Not(Box<ConditionalExpression>),
+    And(Box<ConditionalExpression>, Box<ConditionalExpression>),
+    Or(Box<ConditionalExpression>, Box<ConditionalExpression>),
+}
+
+/* What happens after the body of a `case` item has run: `;;` ends the
+`case`, `;&` runs the next body as well, and `;;&` goes on testing the
+patterns of the following items. */
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum CaseTerminator {
+    Break,
+    FallThrough,
+    Continue,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct CaseItem {
+    pub patterns: Vec<String>,
+    pub body: List,
+    pub terminator: CaseTerminator,
+}
+
+/* Words that end a construct. They are only special where a command
+could start, and a command can never be named after one of them. */
+const CLOSING_RESERVED_WORDS: [&str; 8] = ["then", "elif", "else", "fi", "do", "done", "esac", "}"];
+
+/* Every word the parser gives a meaning of its own where a command
+could start. */
+const RESERVED_WORDS: [&str; 19] = [
+    "!", "{", "}", "[[", "]]", "case", "do", "done", "elif", "else", "esac", "fi", "for",
+    "function", "if", "in", "then", "until", "while",
+];
+
+pub fn is_reserved_word(word: &str) -> bool {
+    RESERVED_WORDS.contains(&word)
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum Command {
+    Simple(SimpleCommand),
+    Compound(CompoundCommand, Vec<Redirection>),
+    FunctionDefinition(String, Rc<Command>),
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct Pipeline {
+    pub negated: bool,
+    pub commands: Vec<Command>,
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum AndOrOperator {
+    And,
+    Or,
+}
+
+/* Pipelines joined by `&&` and `||`, which are evaluated left to right. */
+#[derive(PartialEq, Debug, Clone)]
+pub struct AndOr {
+    pub first: Pipeline,
+    pub rest: Vec<(AndOrOperator, Pipeline)>,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct ListItem {
+    pub and_or: AndOr,
+    pub background: bool,
+}
+
+pub type List = Vec<ListItem>;
+
+pub fn parse_string(string: &str) -> Result<List, ParseError> {
+    parse_string_with_aliases(string, &BTreeMap::new())
+}
+
+/* Parses a string the way the shell runs it, with the first word of
+every simple command replaced by the alias of that name, if any. */
+pub fn parse_string_with_aliases(
+    string: &str,
+    aliases: &BTreeMap<String, String>,
+) -> Result<List, ParseError> {
+    let tokens = tokenize_string(string)?;
+    let mut parser = Parser {
+        tokens,
+        position: 0,
+        aliases,
+        active_aliases: Vec::new(),
+        alias_word_position: None,
+    };
+    let list = parser.parse_list(&[])?;
+    match parser.peek() {
+        Some(token) => Err(ParseError::UnexpectedToken(describe_token(token))),
+        None => Ok(list),
+    }
+}
+
+/* A recursive descent parser over the tokens of the lexer. Reserved
+words like `{` and `}` arrive as ordinary words, and are only treated
+specially where a command could start. */
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    position: usize,
+    aliases: &'a BTreeMap<String, String>,
+    /* The aliases whose replacement is still ahead, with the position
+    where their replacement ends. An alias is not expanded again inside
+    its own replacement, which keeps `alias ls='ls -F'` from looping. */
+    active_aliases: Vec<(String, usize)>,
+    /* The word after an alias whose value ends in a blank, which is
+    checked for an alias too. */
+    alias_word_position: Option<usize>,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn peek_operator(&self) -> Option<Operator> {
+        match self.peek() {
+            Some(Token::Operator(operator)) => Some(*operator),
+            _ => None,
+        }
+    }
+
+    fn peek_reserved_word(&self, reserved_word: &str) -> bool {
+        match self.peek() {
+            Some(Token::Word(word)) => word == reserved_word,
+            _ => false,
+        }
+    }
+
+    /* Replaces the word at the current position by the tokens of its
+    alias, and the first word of those by its own alias in turn. */
+    fn expand_alias(&mut self) -> Result<(), ParseError> {
+        let position = self.position;
+        self.active_aliases.retain(|(_, end)| *end > position);
+        loop {
+            let word = match self.peek() {
+                Some(Token::Word(word)) => word.clone(),
+                _ => return Ok(()),
+            };
+            if self.active_aliases.iter().any(|(name, _)| *name == word) {
+                return Ok(());
+            }
+            let value = match self.aliases.get(&word) {
+                Some(value) => value.clone(),
+                None => return Ok(()),
+            };
+            let tokens = tokenize_string(&value)?;
+            let count = tokens.len();
+            for (_, end) in &mut self.active_aliases {
+                *end = (*end + count).saturating_sub(1);
+            }
+            self.tokens.splice(position..(position + 1), tokens);
+            self.active_aliases.push((word, position + count));
+            if value.ends_with(' ') || value.ends_with('\t') {
+                self.alias_word_position = Some(position + count);
+            }
+        }
+    }
+
+    fn skip_newlines(&mut self) {
+        while self.peek_operator() == Some(Operator::Newline) {
+            self.position += 1;
+        }
+    }
+
+    fn unexpected(&self) -> ParseError {
+        match self.peek() {
+            Some(token) => ParseError::UnexpectedToken(describe_token(token)),
+            None => ParseError::UnexpectedEof,
+        }
+    }
+
+    fn expect_reserved_word(&mut self, reserved_word: &str) -> Result<(), ParseError> {
+        if self.peek_reserved_word(reserved_word) {
+            self.position += 1;
+            Ok(())
+        } else {
+            Err(self.unexpected())
+        }
+    }
+
+    fn expect_operator(&mut self, operator: Operator) -> Result<(), ParseError> {
+        if self.peek_operator() == Some(operator) {
+            self.position += 1;
+            Ok(())
+        } else {
+            Err(self.unexpected())
+        }
+    }
+
+    /* Parses and-or lists separated by `;`, `&` or newlines, until the
+    input runs out, a `)` shows up, or one of `terminators` appears where
+    a command would start. */
+    fn parse_list(&mut self, terminators: &[&str]) -> Result<List, ParseError> {
+        let mut list: List = Vec::new();
+        loop {
+            self.skip_newlines();
+            match self.peek() {
+                None | Some(Token::Operator(Operator::RightParen)) => break,
+                Some(Token::Operator(operator)) if is_case_terminator(*operator) => break,
+                Some(Token::Word(word)) if terminators.contains(&word.as_str()) => break,
+                _ => (),
+            }
+            let and_or = self.parse_and_or()?;
+            let background = match self.peek_operator() {
+                Some(Operator::Ampersand) => {
+                    self.position += 1;
+                    true
+                }
+                Some(Operator::Separator) | Some(Operator::Newline) => {
+                    self.position += 1;
+                    false
+                }
+                _ => {
+                    list.push(ListItem {
+                        and_or,
+                        background: false,
+                    });
+                    break;
+                }
+            };
+            list.push(ListItem { and_or, background });
+        }
+        Ok(list)
+    }
+
+    fn parse_and_or(&mut self) -> Result<AndOr, ParseError> {
+        let first = self.parse_pipeline()?;
+        let mut rest: Vec<(AndOrOperator, Pipeline)> = Vec::new();
+        loop {
+            let operator = match self.peek_operator() {
+                Some(Operator::AndIf) => AndOrOperator::And,
+                Some(Operator::OrIf) => AndOrOperator::Or,
+                _ => break,
+            };
+            self.position += 1;
+            self.skip_newlines();
+            rest.push((operator, self.parse_pipeline()?));
+        }
+        Ok(AndOr { first, rest })
+    }
+
+    fn parse_pipeline(&mut self) -> Result<Pipeline, ParseError> {
+        let negated = self.peek_reserved_word("!");
+        if negated {
+            self.position += 1;
+        }
+        let mut commands = vec![self.parse_command()?];
+        while self.peek_operator() == Some(Operator::Pipe) {
+            self.position += 1;
+            self.skip_newlines();
+            commands.push(self.parse_command()?);
+        }
+        Ok(Pipeline { negated, commands })
+    }
+
+    fn parse_command(&mut self) -> Result<Command, ParseError> {
+        self.expand_alias()?;
+        let compound_command = if self.peek_reserved_word("{") {
+            self.position += 1;
+            let list = self.parse_non_empty_list(&["}"])?;
+            self.expect_reserved_word("}")?;
+            CompoundCommand::BraceGroup(list)
+        } else if self.peek_operator() == Some(Operator::LeftParen) {
+            self.position += 1;
+            let list = self.parse_non_empty_list(&[])?;
+            self.expect_operator(Operator::RightParen)?;
+            CompoundCommand::Subshell(list)
+        } else if self.peek_reserved_word("if") {
+            self.position += 1;
+            self.parse_if()?
+        } else if self.peek_reserved_word("while") || self.peek_reserved_word("until") {
+            let until = self.peek_reserved_word("until");
+            self.position += 1;
+            let condition = self.parse_non_empty_list(&["do"])?;
+            let body = self.parse_do_group()?;
+            CompoundCommand::While {
+                condition,
+                body,
+                until,
+            }
+        } else if self.peek_reserved_word("for") {
+            self.position += 1;
+            self.parse_for()?
+        } else if self.peek_reserved_word("case") {
+            self.position += 1;
+            self.parse_case()?
+        } else if self.peek_reserved_word("[[") {
+            self.position += 1;
+            let expression = self.parse_conditional_or()?;
+            self.skip_newlines();
+            self.expect_reserved_word("]]")?;
+            CompoundCommand::Conditional(expression)
+        } else if let Some(expression) = self.peek_arithmetic_command() {
+            self.position += 1;
+            CompoundCommand::Arithmetic(expression)
+        } else if self.peek_reserved_word("function") {
+            self.position += 1;
+            return self.parse_function_definition(true);
+        } else if self.peek_function_definition() {
+            return self.parse_function_definition(false);
+        } else if CLOSING_RESERVED_WORDS
+            .iter()
+            .any(|reserved_word| self.peek_reserved_word(reserved_word))
+        {
+            return Err(self.unexpected());
+        } else {
+            return self.parse_simple_command();
+        };
+        let mut redirections: Vec<Redirection> = Vec::new();
+        while let Some(redirection) = self.parse_redirection()? {
+            redirections.push(redirection);
+        }
+        Ok(Command::Compound(compound_command, redirections))
+    }
+
+    /* Whether the next tokens are `name ( )`, which starts a function
+    definition. */
+    fn peek_function_definition(&self) -> bool {
+        match (
+            self.peek(),
+            self.tokens.get(self.position + 1),
+            self.tokens.get(self.position + 2),
+        ) {
+            (
+                Some(Token::Word(name)),
+                Some(Token::Operator(Operator::LeftParen)),
+                Some(Token::Operator(Operator::RightParen)),
+            ) => is_valid_function_name(name),
+            _ => false,
+        }
+    }
+
+    /* Parses `name() body` or, after the reserved word `function`,
+    `name [()] body`. The body has to be a compound command. */
+    fn parse_function_definition(&mut self, after_keyword: bool) -> Result<Command, ParseError> {
+        let name = match self.peek() {
+            Some(Token::Word(name)) if is_valid_function_name(name) => name.clone(),
+            _ => return Err(self.unexpected()),
+        };
+        self.position += 1;
+        if !after_keyword || self.peek_operator() == Some(Operator::LeftParen) {
+            self.expect_operator(Operator::LeftParen)?;
+            self.expect_operator(Operator::RightParen)?;
+        }
+        self.skip_newlines();
+        let start = self.position;
+        match self.parse_command()? {
+            body @ Command::Compound(..) => Ok(Command::FunctionDefinition(name, Rc::new(body))),
+            _ => Err(ParseError::UnexpectedToken(describe_token(
+                &self.tokens[start],
+            ))),
+        }
+    }
+
+    /* Parses the rest of an `if` after the reserved word itself. Every
+    `elif` adds another condition and body to the list of branches. */
+    fn parse_if(&mut self) -> Result<CompoundCommand, ParseError> {
+        let mut branches: Vec<(List, List)> = Vec::new();
+        let mut else_branch: Option<List> = None;
+        loop {
+            let condition = self.parse_non_empty_list(&["then"])?;
+            self.expect_reserved_word("then")?;
+            let body = self.parse_non_empty_list(&["elif", "else", "fi"])?;
+            branches.push((condition, body));
+            if self.peek_reserved_word("elif") {
+                self.position += 1;
+                continue;
+            }
+            if self.peek_reserved_word("else") {
+                self.position += 1;
+                else_branch = Some(self.parse_non_empty_list(&["fi"])?);
+            }
+            self.expect_reserved_word("fi")?;
+            return Ok(CompoundCommand::If {
+                branches,
+                else_branch,
+            });
+        }
+    }
+
+    /* Parses the rest of a `for` loop. Without `in`, the loop runs over
+    the positional parameters. The C-style form takes three arithmetic
+    expressions in place of the name and the words. */
+    fn parse_for(&mut self) -> Result<CompoundCommand, ParseError> {
+        if let Some(expression) = self.peek_arithmetic_command() {
+            self.position += 1;
+            let parts: Vec<&str> = expression.split(';').collect();
+            if parts.len() != 3 {
+                return Err(ParseError::UnexpectedToken(format!("(({}))", expression)));
+            }
+            if self.peek_operator() == Some(Operator::Separator) {
+                self.position += 1;
+            }
+            self.skip_newlines();
+            let body = self.parse_do_group()?;
+            return Ok(CompoundCommand::ArithmeticFor {
+                initialization: parts[0].to_string(),
+                condition: parts[1].to_string(),
+                update: parts[2].to_string(),
+                body,
+            });
+        }
+        let name = match self.peek() {
+            Some(Token::Word(name)) if is_valid_name(name) => name.clone(),
+            _ => return Err(self.unexpected()),
+        };
+        self.position += 1;
+        self.skip_newlines();
+        let words = if self.peek_reserved_word("in") {
+            self.position += 1;
+            let mut words: Vec<String> = Vec::new();
+            while let Some(Token::Word(word)) = self.peek() {
+                words.push(word.clone());
+                self.position += 1;
+            }
+            match self.peek_operator() {
+                Some(Operator::Separator) | Some(Operator::Newline) => self.position += 1,
+                _ => return Err(self.unexpected()),
+            }
+            Some(words)
+        } else {
+            if self.peek_operator() == Some(Operator::Separator) {
+                self.position += 1;
+            }
+            None
+        };
+        self.skip_newlines();
+        let body = self.parse_do_group()?;
+        Ok(CompoundCommand::For { name, words, body })
+    }
+
+    /* Parses the rest of a `case` after the reserved word itself. Every
+    item is an optionally parenthesized list of patterns separated by `|`,
+    and the terminator may be left out after the last item. */
+    fn parse_case(&mut self) -> Result<CompoundCommand, ParseError> {
+        let word = match self.peek() {
+            Some(Token::Word(word)) => word.clone(),
+            _ => return Err(self.unexpected()),
+        };
+        self.position += 1;
+        self.skip_newlines();
+        self.expect_reserved_word("in")?;
+        let mut items: Vec<CaseItem> = Vec::new();
+        loop {
+            self.skip_newlines();
+            if self.peek_reserved_word("esac") {
+                self.position += 1;
+                return Ok(CompoundCommand::Case { word, items });
+            }
+            if self.peek_operator() == Some(Operator::LeftParen) {
+                self.position += 1;
+            }
+            let mut patterns: Vec<String> = Vec::new();
+            loop {
+                match self.peek() {
+                    Some(Token::Word(pattern)) => patterns.push(pattern.clone()),
+                    _ => return Err(self.unexpected()),
+                }
+                self.position += 1;
+                if self.peek_operator() != Some(Operator::Pipe) {
+                    break;
+                }
+                self.position += 1;
+            }
+            self.expect_operator(Operator::RightParen)?;
+            let body = self.parse_list(&["esac"])?;
+            let terminator = match self.peek_operator() {
+                Some(Operator::DoubleSemicolon) => CaseTerminator::Break,
+                Some(Operator::SemicolonAnd) => CaseTerminator::FallThrough,
+                Some(Operator::DoubleSemicolonAnd) => CaseTerminator::Continue,
+                _ if self.peek_reserved_word("esac") => {
+                    items.push(CaseItem {
+                        patterns,
+                        body,
+                        terminator: CaseTerminator::Break,
+                    });
+                    continue;
+                }
+                _ => return Err(self.unexpected()),
+            };
+            self.position += 1;
+            items.push(CaseItem {
+                patterns,
+                body,
+                terminator,
+            });
+        }
+    }
+
+    /* The lexer keeps `((...))` together as a single word. Returns the
+    expression inside if that's the next token. */
+    fn peek_arithmetic_command(&self) -> Option<String> {
+        match self.peek() {
+            Some(Token::Word(word)) if word.starts_with("((") && word.ends_with("))") => {
+                Some(word[2..(word.len() - 2)].to_string())
+            }
+            _ => None,
+        }
+    }
+
+    fn parse_conditional_or(&mut self) -> Result<ConditionalExpression, ParseError> {
+        let mut expression = self.parse_conditional_and()?;
+        while self.peek_operator() == Some(Operator::OrIf) {
+            self.position += 1;
+            let right = self.parse_conditional_and()?;
+            expression = ConditionalExpression::Or(Box::new(expression), Box::new(right));
+        }
+        Ok(expression)
+    }
+
+    fn parse_conditional_and(&mut self) -> Result<ConditionalExpression, ParseError> {
+        let mut expression = self.parse_conditional_term()?;
+        while self.peek_operator() == Some(Operator::AndIf) {
+            self.position += 1;
+            let right = self.parse_conditional_term()?;
+            expression = ConditionalExpression::And(Box::new(expression), Box::new(right));
+        }
+        Ok(expression)
+    }
+
+    /* A term is a negation, a parenthesized expression, a test with a
+    unary or binary operator, or a single word that is true when it
+    isn't empty. `<` and `>` compare strings here instead of being
+    redirections. */
+    fn parse_conditional_term(&mut self) -> Result<ConditionalExpression, ParseError> {
+        self.skip_newlines();
+        if self.peek_reserved_word("!") {
+            self.position += 1;
+            let term = self.parse_conditional_term()?;
+            return Ok(ConditionalExpression::Not(Box::new(term)));
+        }
+        if self.peek_operator() == Some(Operator::LeftParen) {
+            self.position += 1;
+            let expression = self.parse_conditional_or()?;
+            self.skip_newlines();
+            self.expect_operator(Operator::RightParen)?;
+            return Ok(expression);
+        }
+        let word = self.next_conditional_operand()?;
+        if is_unary_operator(&word) {
+            if let Some(Token::Word(operand)) = self.peek() {
+                if operand != "]]" {
+                    let operand = operand.clone();
+                    self.position += 1;
+                    return Ok(ConditionalExpression::Unary(word, operand));
+                }
+            }
+        }
+        let operator = match self.peek() {
+            Some(Token::Word(operator)) if is_binary_operator(operator) || operator == "=~" => {
+                operator.clone()
+            }
+            Some(Token::Operator(Operator::Less)) => "<".to_string(),
+            Some(Token::Operator(Operator::Great)) => ">".to_string(),
+            _ => return Ok(ConditionalExpression::Word(word)),
+        };
+        self.position += 1;
+        let right = self.next_conditional_operand()?;
+        Ok(ConditionalExpression::Binary(word, operator, right))
+    }
+
+    fn next_conditional_operand(&mut self) -> Result<String, ParseError> {
+        match self.peek() {
+            Some(Token::Word(word)) if word != "]]" => {
+                let word = word.clone();
+                self.position += 1;
+                Ok(word)
+            }
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    /* Parses `do list done`, the body shared by all loops. */
+    fn parse_do_group(&mut self) -> Result<List, ParseError> {
+        self.expect_reserved_word("do")?;
+        let body = self.parse_non_empty_list(&["done"])?;
+        self.expect_reserved_word("done")?;
+        Ok(body)
+    }
+
+    fn parse_non_empty_list(&mut self, terminators: &[&str]) -> Result<List, ParseError> {
+        let list = self.parse_list(terminators)?;
+        if list.is_empty() {
+            return Err(self.unexpected());
+        }
+        Ok(list)
+    }
+
+    /* Parses a redirection if the next tokens form one. */
+    fn parse_redirection(&mut self) -> Result<Option<Redirection>, ParseError> {
+        let (fd, variable) = match self.peek() {
+            Some(Token::IoNumber(fd)) => (Some(*fd), None),
+            Some(Token::IoVariable(name)) => (None, Some(name.clone())),
+            _ => (None, None),
+        };
+        let offset = if fd.is_some() || variable.is_some() {
+            1
+        } else {
+            0
+        };
+        match self.tokens.get(self.position + offset).cloned() {
+            Some(Token::HereDocument(here_document)) => {
+                self.position += offset + 1;
+                Ok(Some(Redirection {
+                    fd,
+                    variable,
+                    kind: RedirectionKind::HereDocument(here_document),
+                }))
+            }
+            Some(Token::Operator(operator)) if is_redirection_operator(operator) => {
+                self.position += offset + 1;
+                let target = expect_redirection_target(self.next())?;
+                let mut redirection = make_redirection(fd, operator, target)?;
+                redirection.variable = variable;
+                Ok(Some(redirection))
+            }
+            _ if offset > 0 => Err(ParseError::TokenOutOfPlace),
+            _ => Ok(None),
+        }
+    }
+
+    /* Parses the `(...)` of an array assignment, which may span several
+    lines. */
+    fn parse_array_words(&mut self) -> Result<Vec<String>, ParseError> {
+        self.expect_operator(Operator::LeftParen)?;
+        let mut words: Vec<String> = Vec::new();
+        loop {
+            match self.peek() {
+                Some(Token::Word(word)) => words.push(word.clone()),
+                Some(Token::Operator(Operator::Newline)) => (),
+                Some(Token::Operator(Operator::RightParen)) => {
+                    self.position += 1;
+                    return Ok(words);
+                }
+                _ => return Err(self.unexpected()),
+            }
+            self.position += 1;
+        }
+    }
+
+    fn parse_simple_command(&mut self) -> Result<Command, ParseError> {
+        let mut simple_command = SimpleCommand {
+            assignments: Vec::new(),
+            words: Vec::new(),
+            redirections: Vec::new(),
+        };
+        loop {
+            if let Some(redirection) = self.parse_redirection()? {
+                simple_command.redirections.push(redirection);
+                continue;
+            }
+            if self.alias_word_position == Some(self.position) {
+                self.alias_word_position = None;
+                self.expand_alias()?;
+            } else if simple_command.words.is_empty() {
+                self.expand_alias()?;
+            }
+            let word = match self.peek() {
+                Some(Token::Word(word)) => word.clone(),
+                _ => break,
+            };
+            self.position += 1;
+            let assignment = parse_assignment(&word);
+            let starts_array = word.ends_with('=')
+                && assignment.is_some()
+                && self.peek_operator() == Some(Operator::LeftParen);
+            if simple_command.words.is_empty() {
+                if let Some(mut assignment) = assignment {
+                    if starts_array {
+                        assignment.value = AssignmentValue::Array(self.parse_array_words()?);
+                    }
+                    simple_command.assignments.push(assignment);
+                    continue;
+                }
+            }
+            if starts_array {
+                let elements = self.parse_array_words()?;
+                simple_command
+                    .words
+                    .push(format!("{}({})", word, elements.join(" ")));
+            } else {
+                simple_command.words.push(word);
+            }
+        }
+        if simple_command.words.is_empty()
+            && simple_command.assignments.is_empty()
+            && simple_command.redirections.is_empty()
+        {
+            return Err(self.unexpected());
+        }
+        Ok(Command::Simple(simple_command))
+    }
+}
+
+fn is_redirection_operator(operator: Operator) -> bool {
+    matches!(
+        operator,
+        Operator::Less
+            | Operator::Great
+            | Operator::DoubleGreat
+            | Operator::LessGreat
+            | Operator::LessAnd
+            | Operator::GreatAnd
+            | Operator::Clobber
+            | Operator::TripleLess
+    )
+}
+
+fn is_case_terminator(operator: Operator) -> bool {
+    matches!(
+        operator,
+        Operator::DoubleSemicolon | Operator::SemicolonAnd | Operator::DoubleSemicolonAnd
+    )
+}
+
+fn expect_redirection_target(token: Option<Token>) -> Result<String, ParseError> {
+    match token {
+        Some(Token::Word(word)) => Ok(word),
+        Some(token) => Err(ParseError::UnexpectedToken(describe_token(&token))),
+        None => Err(ParseError::UnexpectedToken("newline".to_string())),
+    }
+}
+
+fn make_redirection(
+    fd: Option<i32>,
+    operator: Operator,
+    target: String,
+) -> Result<Redirection, ParseError> {
+    let kind = match operator {
+        Operator::Less => RedirectionKind::Input(target),
+        Operator::Great => RedirectionKind::Output(target),
+        Operator::DoubleGreat => RedirectionKind::Append(target),
+        Operator::LessGreat => RedirectionKind::ReadWrite(target),
+        Operator::LessAnd => RedirectionKind::DuplicateInput(target),
+        Operator::GreatAnd => RedirectionKind::DuplicateOutput(target),
+        Operator::Clobber => RedirectionKind::Clobber(target),
+        Operator::TripleLess => RedirectionKind::HereString(target),
+        _ => {
+            return Err(ParseError::UnexpectedToken(
+                describe_operator(operator).to_string(),
+            ))
+        }
+    };
+    Ok(Redirection {
+        fd,
+        variable: None,
+        kind,
+    })
+}
+
+/* An assignment is a word of the form NAME=value where NAME is a valid
+variable name, optionally followed by a subscript in brackets. */
+pub fn is_assignment(word: &str) -> bool {
+    parse_assignment(word).is_some()
+}
+
+/* Splits an assignment word into its parts. A value written as `(...)`
+can only come from an array assignment passed as an argument, like
+`local a=(1 2)`, because the lexer never puts an unquoted parenthesis
+into a word. */
+pub fn parse_assignment(word: &str) -> Option<Assignment> {
+    let mut depth = 0;
+    let position = word.find(|c| {
+        match c {
+            '[' => depth += 1,
+            ']' if depth > 0 => depth -= 1,
+            '=' if depth == 0 => return true,
+            _ => (),
+        }
+        false
+    })?;
+    let (target, append) = match word[..position].strip_suffix('+') {
+        Some(target) => (target, true),
+        None => (&word[..position], false),
+    };
+    let (name, subscript) = match target.find('[') {
+        Some(open) if target.ends_with(']') && open + 1 < target.len() - 1 => (
+            &target[..open],
+            Some(target[(open + 1)..(target.len() - 1)].to_string()),
+        ),
+        Some(_) => return None,
+        None => (target, None),
+    };
+    if !is_valid_name(name) {
+        return None;
+    }
+    let value = &word[(position + 1)..];
+    let value = if value.starts_with('(') && value.ends_with(')') && subscript.is_none() {
+        let words = match tokenize_string(&value[1..(value.len() - 1)]) {
+            Ok(tokens) => tokens
+                .into_iter()
+                .filter_map(|token| match token {
+                    Token::Word(word) => Some(word),
+                    _ => None,
+                })
+                .collect(),
+            Err(_) => return None,
+        };
+        AssignmentValue::Array(words)
+    } else {
+        AssignmentValue::Scalar(value.to_string())
+    };
+    Some(Assignment {
+        name: name.to_string(),
+        subscript,
+        append,
+        value,
+    })
+}
+
+pub fn is_valid_name(name: &str) -> bool {
+    let mut characters = name.chars();
+    match characters.next() {
+        Some(first) if first == '_' || first.is_ascii_alphabetic() => {
+            characters.all(|c| c == '_' || c.is_ascii_alphanumeric())
+        }
+        _ => false,
+    }
+}
+
+/* Function names are more permissive than variable names, so that
+names like `git-status` can be defined. */
+fn is_valid_function_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.chars().all(|c| c.is_ascii_digit())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_-.:".contains(c))
+}
+
+fn describe_token(token: &Token) -> String {
+    match token {
+        Token::Word(word) => word.clone(),
+        Token::IoNumber(number) => number.to_string(),
+        Token::IoVariable(name) => format!("{{{}}}", name),
+        Token::HereDocument(_) => "<<".to_string(),
+        Token::Operator(operator) => describe_operator(*operator).to_string(),
+    }
+}
+
+fn describe_operator(operator: Operator) -> &'static str {
+    match operator {
+        Operator::Separator => ";",
+        Operator::DoubleSemicolon => ";;",
+        Operator::SemicolonAnd => ";&",
+        Operator::DoubleSemicolonAnd => ";;&",
+        Operator::Newline => "newline",
+        Operator::Ampersand => "&",
+        Operator::AndIf => "&&",
+        Operator::Pipe => "|",
+        Operator::OrIf => "||",
+        Operator::LeftParen => "(",
+        Operator::RightParen => ")",
+        Operator::Less => "<",
+        Operator::Great => ">",
+        Operator::DoubleGreat => ">>",
+        Operator::LessGreat => "<>",
+        Operator::LessAnd => "<&",
+        Operator::GreatAnd => ">&",
+        Operator::Clobber => ">|",
+        Operator::TripleLess => "<<<",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn simple(words: Vec<&str>) -> Command {
+        Command::Simple(SimpleCommand {
+            assignments: Vec::new(),
+            words: words.iter().map(|word| word.to_string()).collect(),
+            redirections: Vec::new(),
+        })
+    }
+
+    fn pipeline(commands: Vec<Command>) -> Pipeline {
+        Pipeline {
+            negated: false,
+            commands,
+        }
+    }
+
+    fn item(command: Command) -> ListItem {
+        ListItem {
+            and_or: AndOr {
+                first: pipeline(vec![command]),
+                rest: Vec::new(),
+            },
+            background: false,
+        }
+    }
+
+    #[test]
+    fn parse_string_test() {
+        let string = "ls";
+        let expected_result = Ok(vec![item(simple(vec!["ls"]))]);
+        assert_eq!(parse_string(string), expected_result);
+
+        let string = "exit; ls -l\nexit 3\n";
+        let expected_result = Ok(vec![
+            item(simple(vec!["exit"])),
+            item(simple(vec!["ls", "-l"])),
+            item(simple(vec!["exit", "3"])),
+        ]);
+        assert_eq!(parse_string(string), expected_result);
+
+        let string = "echo exit }";
+        let expected_result = Ok(vec![item(simple(vec!["echo", "exit", "}"]))]);
+        assert_eq!(parse_string(string), expected_result);
+
+        let string = "; ls";
+        let expected_result = Err(ParseError::UnexpectedToken(";".to_string()));
+        assert_eq!(parse_string(string), expected_result);
+
+        let string = "ls )";
+        let expected_result = Err(ParseError::UnexpectedToken(")".to_string()));
+        assert_eq!(parse_string(string), expected_result);
+    }
+
+    #[test]
+    fn parse_and_or_test() {
+        let string = "! a | b && c || d & e";
+        let expected_result = Ok(vec![
+            ListItem {
+                and_or: AndOr {
+                    first: Pipeline {
+                        negated: true,
+                        commands: vec![simple(vec!["a"]), simple(vec!["b"])],
+                    },
+                    rest: vec![
+                        (AndOrOperator::And, pipeline(vec![simple(vec!["c"])])),
+                        (AndOrOperator::Or, pipeline(vec![simple(vec!["d"])])),
+                    ],
+                },
+                background: true,
+            },
+            item(simple(vec!["e"])),
+        ]);
+        assert_eq!(parse_string(string), expected_result);
+
+        let string = "a |\n b";
+        let expected_result = Ok(vec![ListItem {
+            and_or: AndOr {
+                first: pipeline(vec![simple(vec!["a"]), simple(vec!["b"])]),
+                rest: Vec::new(),
+            },
+            background: false,
+        }]);
+        assert_eq!(parse_string(string), expected_result);
+
+        let string = "a &&";
+        let expected_result = Err(ParseError::UnexpectedEof);
+        assert_eq!(parse_string(string), expected_result);
+
+        let string = "a | | b";
+        let expected_result = Err(ParseError::UnexpectedToken("|".to_string()));
+        assert_eq!(parse_string(string), expected_result);
+    }
+
+    #[test]
+    fn parse_group_test() {
+        let string = "{ a; b\n} > out; (c) 2>&1";
+        let expected_result = Ok(vec![
+            item(Command::Compound(
+                CompoundCommand::BraceGroup(vec![item(simple(vec!["a"])), item(simple(vec!["b"]))]),
+                vec![Redirection {
+                    fd: None,
+                    variable: None,
+                    kind: RedirectionKind::Output("out".to_string()),
+                }],
+            )),
+            item(Command::Compound(
+                CompoundCommand::Subshell(vec![item(simple(vec!["c"]))]),
+                vec![Redirection {
+                    fd: Some(2),
+                    variable: None,
+                    kind: RedirectionKind::DuplicateOutput("1".to_string()),
+                }],
+            )),
+        ]);
+        assert_eq!(parse_string(string), expected_result);
+
+        let string = "{ a; }; { b; }";
+        assert!(parse_string(string).is_ok());
+
+        let string = "{ a }";
+        let expected_result = Err(ParseError::UnexpectedEof);
+        assert_eq!(parse_string(string), expected_result);
+
+        let string = "( a";
+        let expected_result = Err(ParseError::UnexpectedEof);
+        assert_eq!(parse_string(string), expected_result);
+
+        let string = "()";
+        let expected_result = Err(ParseError::UnexpectedToken(")".to_string()));
+        assert_eq!(parse_string(string), expected_result);
+
+        let string = "{ }";
+        let expected_result = Err(ParseError::UnexpectedToken("}".to_string()));
+        assert_eq!(parse_string(string), expected_result);
+    }
+
+    #[test]
+    fn parse_if_test() {
+        let string = "if a; then b; elif c\nthen d\nelse e; fi > out";
+        let expected_result = Ok(vec![item(Command::Compound(
+            CompoundCommand::If {
+                branches: vec![
+                    (vec![item(simple(vec!["a"]))], vec![item(simple(vec!["b"]))]),
+                    (vec![item(simple(vec!["c"]))], vec![item(simple(vec!["d"]))]),
+                ],
+                else_branch: Some(vec![item(simple(vec!["e"]))]),
+            },
+            vec![Redirection {
+                fd: None,
+                variable: None,
+                kind: RedirectionKind::Output("out".to_string()),
+            }],
+        ))]);
+        assert_eq!(parse_string(string), expected_result);
+
+        let string = "if a; then echo then fi; fi";
+        let expected_result = Ok(vec![item(Command::Compound(
+            CompoundCommand::If {
+                branches: vec![(
+                    vec![item(simple(vec!["a"]))],
+                    vec![item(simple(vec!["echo", "then", "fi"]))],
+                )],
+                else_branch: None,
+            },
+            Vec::new(),
+        ))]);
+        assert_eq!(parse_string(string), expected_result);
+
+        let string = "if a; then b;";
+        let expected_result = Err(ParseError::UnexpectedEof);
+        assert_eq!(parse_string(string), expected_result);
+
+        let string = "if a; then fi";
+        let expected_result = Err(ParseError::UnexpectedToken("fi".to_string()));
+        assert_eq!(parse_string(string), expected_result);
+
+        let string = "fi";
+        let expected_result = Err(ParseError::UnexpectedToken("fi".to_string()));
+        assert_eq!(parse_string(string), expected_result);
+    }
+
+    #[test]
+    fn parse_while_test() {
+        let string = "while a; do b; done; until c\ndo\nd\ndone";
+        let expected_result = Ok(vec![
+            item(Command::Compound(
+                CompoundCommand::While {
+                    condition: vec![item(simple(vec!["a"]))],
+                    body: vec![item(simple(vec!["b"]))],
+                    until: false,
+                },
+                Vec::new(),
+            )),
+            item(Command::Compound(
+                CompoundCommand::While {
+                    condition: vec![item(simple(vec!["c"]))],
+                    body: vec![item(simple(vec!["d"]))],
+                    until: true,
+                },
+                Vec::new(),
+            )),
+        ]);
+        assert_eq!(parse_string(string), expected_result);
+
+        let string = "while a; b; done";
+        let expected_result = Err(ParseError::UnexpectedToken("done".to_string()));
+        assert_eq!(parse_string(string), expected_result);
+
+        let string = "while a; do b; done done";
+        let expected_result = Err(ParseError::UnexpectedToken("done".to_string()));
+        assert_eq!(parse_string(string), expected_result);
+    }
+
+    #[test]
+    fn parse_for_test() {
+        let string = "for x in a \"b c\"; do d; done; for y\ndo e; done";
+        let expected_result = Ok(vec![
+            item(Command::Compound(
+                CompoundCommand::For {
+                    name: "x".to_string(),
+                    words: Some(vec!["a".to_string(), "\"b c\"".to_string()]),
+                    body: vec![item(simple(vec!["d"]))],
+                },
+                Vec::new(),
+            )),
+            item(Command::Compound(
+                CompoundCommand::For {
+                    name: "y".to_string(),
+                    words: None,
+                    body: vec![item(simple(vec!["e"]))],
+                },
+                Vec::new(),
+            )),
+        ]);
+        assert_eq!(parse_string(string), expected_result);
+
+        let string = "for ((i = 0; i < 3; i++)); do a; done; ((i++))";
+        let expected_result = Ok(vec![
+            item(Command::Compound(
+                CompoundCommand::ArithmeticFor {
+                    initialization: "i = 0".to_string(),
+                    condition: " i < 3".to_string(),
+                    update: " i++".to_string(),
+                    body: vec![item(simple(vec!["a"]))],
+                },
+                Vec::new(),
+            )),
+            item(Command::Compound(
+                CompoundCommand::Arithmetic("i++".to_string()),
+                Vec::new(),
+            )),
+        ]);
+        assert_eq!(parse_string(string), expected_result);
+
+        let string = "for x in a b";
+        let expected_result = Err(ParseError::UnexpectedEof);
+        assert_eq!(parse_string(string), expected_result);
+
+        let string = "for 1 in a; do b; done";
+        let expected_result = Err(ParseError::UnexpectedToken("1".to_string()));
+        assert_eq!(parse_string(string), expected_result);
+
+        let string = "for ((i = 0)); do a; done";
+        let expected_result = Err(ParseError::UnexpectedToken("((i = 0))".to_string()));
+        assert_eq!(parse_string(string), expected_result);
+    }
+
+    #[test]
+    fn parse_case_test() {
+        let string = "case $x in\n(a | \"b\") c;;\n*.log) ;&\nd) e\nf;;& esac";
+        let expected_result = Ok(vec![item(Command::Compound(
+            CompoundCommand::Case {
+                word: "$x".to_string(),
+                items: vec![
+                    CaseItem {
+                        patterns: vec!["a".to_string(), "\"b\"".to_string()],
+                        body: vec![item(simple(vec!["c"]))],
+                        terminator: CaseTerminator::Break,
+                    },
+                    CaseItem {
+                        patterns: vec!["*.log".to_string()],
+                        body: Vec::new(),
+                        terminator: CaseTerminator::FallThrough,
+                    },
+                    CaseItem {
+                        patterns: vec!["d".to_string()],
+                        body: vec![item(simple(vec!["e"])), item(simple(vec!["f"]))],
+                        terminator: CaseTerminator::Continue,
+                    },
+                ],
+            },
+            Vec::new(),
+        ))]);
+        assert_eq!(parse_string(string), expected_result);
+
+        let string = "case a in b) c\nesac; case a in esac";
+        let expected_result = Ok(vec![
+            item(Command::Compound(
+                CompoundCommand::Case {
+                    word: "a".to_string(),
+                    items: vec![CaseItem {
+                        patterns: vec!["b".to_string()],
+                        body: vec![item(simple(vec!["c"]))],
+                        terminator: CaseTerminator::Break,
+                    }],
+                },
+                Vec::new(),
+            )),
+            item(Command::Compound(
+                CompoundCommand::Case {
+                    word: "a".to_string(),
+                    items: Vec::new(),
+                },
+                Vec::new(),
+            )),
+        ]);
+        assert_eq!(parse_string(string), expected_result);
+
+        let string = "case a in b) c;; d";
+        let expected_result = Err(ParseError::UnexpectedEof);
+        assert_eq!(parse_string(string), expected_result);
+
+        let string = "a;; b";
+        let expected_result = Err(ParseError::UnexpectedToken(";;".to_string()));
+        assert_eq!(parse_string(string), expected_result);
+    }
+
+    #[test]
+    fn parse_conditional_test() {
+        let string = "[[ ! -f $a && ( b < c || $d =~ ^(x|y)$ ) || e ]] > f";
+        let word = |word: &str| word.to_string();
+        let expression = ConditionalExpression::Or(
+            Box::new(ConditionalExpression::And(
+                Box::new(ConditionalExpression::Not(Box::new(
+                    ConditionalExpression::Unary(word("-f"), word("$a")),
+                ))),
+                Box::new(ConditionalExpression::Or(
+                    Box::new(ConditionalExpression::Binary(
+                        word("b"),
+                        word("<"),
+                        word("c"),
+                    )),
+                    Box::new(ConditionalExpression::Binary(
+                        word("$d"),
+                        word("=~"),
+                        word("^(x|y)$"),
+                    )),
+                )),
+            )),
+            Box::new(ConditionalExpression::Word(word("e"))),
+        );
+        let redirection = Redirection {
+            fd: None,
+            variable: None,
+            kind: RedirectionKind::Output(word("f")),
+        };
+        let expected_result = Ok(vec![item(Command::Compound(
+            CompoundCommand::Conditional(expression),
+            vec![redirection],
+        ))]);
+        assert_eq!(parse_string(string), expected_result);
+
+        let string = "[[ -n ]]";
+        let expected_result = Ok(vec![item(Command::Compound(
+            CompoundCommand::Conditional(ConditionalExpression::Word(word("-n"))),
+            Vec::new(),
+        ))]);
+        assert_eq!(parse_string(string), expected_result);
+
+        let string = "[[ ]]";
+        let expected_result = Err(ParseError::UnexpectedToken(word("]]")));
+        assert_eq!(parse_string(string), expected_result);
+
+        let string = "[[ a == b";
+        let expected_result = Err(ParseError::UnexpectedEof);
+        assert_eq!(parse_string(string), expected_result);
+    }
+
+    #[test]
+    fn parse_function_test() {
+        let body = Command::Compound(
+            CompoundCommand::BraceGroup(vec![item(simple(vec!["a"]))]),
+            Vec::new(),
+        );
+        let string = "f() { a; }; function g-h\n{ a; }; function i () { a; }";
+        let expected_result = Ok(vec![
+            item(Command::FunctionDefinition(
+                "f".to_string(),
+                Rc::new(body.clone()),
+            )),
+            item(Command::FunctionDefinition(
+                "g-h".to_string(),
+                Rc::new(body.clone()),
+            )),
+            item(Command::FunctionDefinition("i".to_string(), Rc::new(body))),
+        ]);
+        assert_eq!(parse_string(string), expected_result);
+
+        let string = "f() a";
+        let expected_result = Err(ParseError::UnexpectedToken("a".to_string()));
+        assert_eq!(parse_string(string), expected_result);
+
+        let string = "f() {";
+        let expected_result = Err(ParseError::UnexpectedEof);
+        assert_eq!(parse_string(string), expected_result);
+
+        let string = "f(a)";
+        let expected_result = Err(ParseError::UnexpectedToken("(".to_string()));
+        assert_eq!(parse_string(string), expected_result);
+    }
+
+    #[test]
+    fn parse_alias_test() {
+        let mut aliases = BTreeMap::new();
+        for (name, value) in &[
+            ("ls", "ls -F"),
+            ("ll", "ls -l"),
+            ("sudo", "sudo "),
+            ("a", "b"),
+            ("b", "a x"),
+            ("e", ""),
+            ("f", ""),
+        ] {
+            aliases.insert(name.to_string(), value.to_string());
+        }
+        let parse = |string: &str| parse_string_with_aliases(string, &aliases);
+        let expected_result = Ok(vec![item(simple(vec!["ls", "-F", "-l", "ls"]))]);
+        assert_eq!(parse("ll ls"), expected_result);
+        let expected_result = Ok(vec![item(simple(vec!["sudo", "ls", "-F", "-l"]))]);
+        assert_eq!(parse("sudo ll"), expected_result);
+        let expected_result = Ok(vec![
+            item(simple(vec!["\\ls"])),
+            item(simple(vec!["a", "x"])),
+        ]);
+        assert_eq!(parse("\\ls; a"), expected_result);
+        let expected_result = Ok(vec![item(simple(vec!["echo", "hi"]))]);
+        assert_eq!(parse("e f echo hi"), expected_result);
+    }
+
+    fn assignment(
+        name: &str,
+        subscript: Option<&str>,
+        append: bool,
+        value: AssignmentValue,
+    ) -> Assignment {
+        Assignment {
+            name: name.to_string(),
+            subscript: subscript.map(|subscript| subscript.to_string()),
+            append,
+            value,
+        }
+    }
+
+    fn scalar(value: &str) -> AssignmentValue {
+        AssignmentValue::Scalar(value.to_string())
+    }
+
+    #[test]
+    fn parse_assignment_test() {
+        let string = "a=1 b=2 env c=3";
+        let expected_result = Ok(vec![item(Command::Simple(SimpleCommand {
+            assignments: vec![
+                assignment("a", None, false, scalar("1")),
+                assignment("b", None, false, scalar("2")),
+            ],
+            words: vec!["env".to_string(), "c=3".to_string()],
+            redirections: Vec::new(),
+        }))]);
+        assert_eq!(parse_string(string), expected_result);
+
+        let string = "a=(1 \"2 3\"\n[x]=4) b[i+1]=x c+=y d[k]+=z; local e=( f )";
+        let array = vec!["1".to_string(), "\"2 3\"".to_string(), "[x]=4".to_string()];
+        let expected_result = Ok(vec![
+            item(Command::Simple(SimpleCommand {
+                assignments: vec![
+                    assignment("a", None, false, AssignmentValue::Array(array)),
+                    assignment("b", Some("i+1"), false, scalar("x")),
+                    assignment("c", None, true, scalar("y")),
+                    assignment("d", Some("k"), true, scalar("z")),
+                ],
+                words: Vec::new(),
+                redirections: Vec::new(),
+            })),
+            item(simple(vec!["local", "e=(f)"])),
+        ]);
+        assert_eq!(parse_string(string), expected_result);
+
+        let array = AssignmentValue::Array(vec!["f".to_string(), "'g h'".to_string()]);
+        assert_eq!(
+            parse_assignment("e=(f 'g h')"),
+            Some(assignment("e", None, false, array))
+        );
+        assert_eq!(
+            parse_assignment("a[x=1]=2"),
+            Some(assignment("a", Some("x=1"), false, scalar("2")))
+        );
+        assert_eq!(parse_assignment("a[]=2"), None);
+        assert_eq!(parse_assignment("a[1]x=2"), None);
+
+        assert!(is_assignment("_a1=x=y"));
+        assert!(!is_assignment("1a=x"));
+        assert!(!is_assignment("=x"));
+        assert!(!is_assignment("ab"));
+    }
+
+    #[test]
+    fn parse_redirection_test() {
+        let string = "cat < \"$x\" 2>>log";
+        let redirections = vec![
+            Redirection {
+                fd: None,
+                variable: None,
+                kind: RedirectionKind::Input("\"$x\"".to_string()),
+            },
+            Redirection {
+                fd: Some(2),
+                variable: None,
+                kind: RedirectionKind::Append("log".to_string()),
+            },
+        ];
+        assert_eq!(redirections[0].target_fd(), 0);
+        assert_eq!(redirections[1].target_fd(), 2);
+        let expected_result = Ok(vec![item(Command::Simple(SimpleCommand {
+            assignments: Vec::new(),
+            words: vec!["cat".to_string()],
+            redirections,
+        }))]);
+        assert_eq!(parse_string(string), expected_result);
+
+        let string = "read v <<< \"$x\"";
+        let redirections = vec![Redirection {
+            fd: None,
+            variable: None,
+            kind: RedirectionKind::HereString("\"$x\"".to_string()),
+        }];
+        assert_eq!(redirections[0].target_fd(), 0);
+        let expected_result = Ok(vec![item(Command::Simple(SimpleCommand {
+            assignments: Vec::new(),
+            words: vec!["read".to_string(), "v".to_string()],
+            redirections,
+        }))]);
+        assert_eq!(parse_string(string), expected_result);
+
+        let string = "exec 3>out 5<>rw {fd}<>rw 4>&- {fd}>&-";
+        let redirections = vec![
+            Redirection {
+                fd: Some(3),
+                variable: None,
+                kind: RedirectionKind::Output("out".to_string()),
+            },
+            Redirection {
+                fd: Some(5),
+                variable: None,
+                kind: RedirectionKind::ReadWrite("rw".to_string()),
+            },
+            Redirection {
+                fd: None,
+                variable: Some("fd".to_string()),
+                kind: RedirectionKind::ReadWrite("rw".to_string()),
+            },
+            Redirection {
+                fd: Some(4),
+                variable: None,
+                kind: RedirectionKind::DuplicateOutput("-".to_string()),
+            },
+            Redirection {
+                fd: None,
+                variable: Some("fd".to_string()),
+                kind: RedirectionKind::DuplicateOutput("-".to_string()),
+            },
+        ];
+        let expected_result = Ok(vec![item(Command::Simple(SimpleCommand {
+            assignments: Vec::new(),
+            words: vec!["exec".to_string()],
+            redirections,
+        }))]);
+        assert_eq!(parse_string(string), expected_result);
+
+        let string = "cat <";
+        let expected_result = Err(ParseError::UnexpectedToken("newline".to_string()));
+        assert_eq!(parse_string(string), expected_result);
+
+        let string = "cat > ;";
+        let expected_result = Err(ParseError::UnexpectedToken(";".to_string()));
+        assert_eq!(parse_string(string), expected_result);
+    }
+}
@@ -0,0 +1,243 @@
+use std::fs;
+use std::path::Path;
+
+/* Patterns are strings where `*`, `?` and `[...]` are special, unless
+they are preceded by a backslash. Quoted parts of a word are turned into
+escaped characters before they end up in a pattern. */
+pub fn escape_pattern(text: &str) -> String {
+    let mut pattern = String::new();
+    for character in text.chars() {
+        if is_pattern_special(character) {
+            pattern.push('\\');
+        }
+        pattern.push(character);
+    }
+    pattern
+}
+
+pub fn is_pattern_special(character: char) -> bool {
+    "*?[]\\".contains(character)
+}
+
+/* Whether the pattern contains an unescaped `*`, `?` or `[`. Anything
+else only ever matches itself. */
+pub fn has_wildcards(pattern: &str) -> bool {
+    let mut characters = pattern.chars();
+    while let Some(character) = characters.next() {
+        match character {
+            '\\' => {
+                characters.next();
+            }
+            '*' | '?' | '[' => return true,
+            _ => (),
+        }
+    }
+    false
+}
+
+pub fn unescape_pattern(pattern: &str) -> String {
+    let mut text = String::new();
+    let mut characters = pattern.chars();
+    while let Some(character) = characters.next() {
+        match character {
+            '\\' => text.extend(characters.next()),
+            _ => text.push(character),
+        }
+    }
+    text
+}
+
+/* Matches the whole of `text` against the pattern. A `*` backtracks to
+the last star seen, which is enough since every star can absorb what a
+later star would have matched. */
+pub fn pattern_matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let mut pattern_position = 0;
+    let mut text_position = 0;
+    let mut last_star: Option<(usize, usize)> = None;
+    while text_position < text.len() {
+        let step = match pattern.get(pattern_position) {
+            Some('*') => {
+                pattern_position += 1;
+                last_star = Some((pattern_position, text_position));
+                continue;
+            }
+            Some('?') => Some(1),
+            Some('[') => match match_bracket(&pattern, pattern_position, text[text_position]) {
+                Some((true, length)) => Some(length),
+                Some((false, _)) => None,
+                None if text[text_position] == '[' => Some(1),
+                None => None,
+            },
+            Some('\\') => match pattern.get(pattern_position + 1) {
+                Some(escaped) if *escaped == text[text_position] => Some(2),
+                None if text[text_position] == '\\' => Some(1),
+                _ => None,
+            },
+            Some(character) if *character == text[text_position] => Some(1),
+            _ => None,
+        };
+        match step {
+            Some(length) => {
+                pattern_position += length;
+                text_position += 1;
+            }
+            None => match last_star {
+                Some((star_pattern, star_text)) => {
+                    pattern_position = star_pattern;
+                    text_position = star_text + 1;
+                    last_star = Some((star_pattern, star_text + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[pattern_position..].iter().all(|c| *c == '*')
+}
+
+/* Matches a single character against the bracket expression starting
+at `open`. Returns whether it matched and the length of the expression,
+or None if the bracket is never closed and is just a literal `[`. */
+fn match_bracket(pattern: &[char], open: usize, character: char) -> Option<(bool, usize)> {
+    let mut position = open + 1;
+    let negated = match pattern.get(position) {
+        Some('!') | Some('^') => {
+            position += 1;
+            true
+        }
+        _ => false,
+    };
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        let current = *pattern.get(position)?;
+        if current == ']' && !first {
+            return Some((matched != negated, position + 1 - open));
+        }
+        first = false;
+        if current == '[' && pattern.get(position + 1) == Some(&':') {
+            let rest: String = pattern[(position + 2)..].iter().collect();
+            if let Some(end) = rest.find(":]") {
+                let class = &rest[..end];
+                matched |= matches_class(class, character);
+                position += 2 + rest[..end].chars().count() + 2;
+                continue;
+            }
+        }
+        let (low, length) = match current {
+            '\\' => (*pattern.get(position + 1)?, 2),
+            _ => (current, 1),
+        };
+        position += length;
+        if pattern.get(position) == Some(&'-') && pattern.get(position + 1) != Some(&']') {
+            let (high, length) = match *pattern.get(position + 1)? {
+                '\\' => (*pattern.get(position + 2)?, 3),
+                high => (high, 2),
+            };
+            position += length;
+            matched |= low <= character && character <= high;
+        } else {
+            matched |= low == character;
+        }
+    }
+}
+
+fn matches_class(class: &str, character: char) -> bool {
+    match class {
+        "alpha" => character.is_ascii_alphabetic(),
+        "digit" => character.is_ascii_digit(),
+        "alnum" => character.is_ascii_alphanumeric(),
+        "upper" => character.is_ascii_uppercase(),
+        "lower" => character.is_ascii_lowercase(),
+        "space" => character.is_ascii_whitespace(),
+        "blank" => character == ' ' || character == '\t',
+        "punct" => character.is_ascii_punctuation(),
+        "xdigit" => character.is_ascii_hexdigit(),
+        _ => false,
+    }
+}
+
+/* Expands a pattern into the sorted list of existing paths matching it.
+Every component of the path is matched separately, and names starting
+with a dot are only matched by a pattern that starts with a dot too,
+unless `dotglob` is set. */
+pub fn glob(pattern: &str, dotglob: bool) -> Vec<String> {
+    let (mut paths, rest) = match pattern.strip_prefix('/') {
+        Some(rest) => (vec!["/".to_string()], rest),
+        None => (vec![String::new()], pattern),
+    };
+    let components: Vec<&str> = rest.split('/').collect();
+    for (index, component) in components.iter().enumerate() {
+        let is_last = index + 1 == components.len();
+        let mut next_paths: Vec<String> = Vec::new();
+        for path in &paths {
+            if component.is_empty() {
+                if is_last && Path::new(path).is_dir() {
+                    next_paths.push(path.clone());
+                }
+                continue;
+            }
+            let separator = if is_last { "" } else { "/" };
+            if !has_wildcards(component) {
+                let candidate = format!("{}{}", path, unescape_pattern(component));
+                if fs::symlink_metadata(&candidate).is_ok() {
+                    next_paths.push(candidate + separator);
+                }
+                continue;
+            }
+            let directory = if path.is_empty() { "." } else { path.as_str() };
+            let entries = match fs::read_dir(directory) {
+                Ok(entries) => entries,
+                Err(_) => continue,
+            };
+            let mut names: Vec<String> = entries
+                .filter_map(|entry| entry.ok())
+                .filter_map(|entry| entry.file_name().into_string().ok())
+                .filter(|name| !name.starts_with('.') || component.starts_with('.') || dotglob)
+                .filter(|name| pattern_matches(component, name))
+                .collect();
+            names.sort();
+            for name in names {
+                next_paths.push(format!("{}{}{}", path, name, separator));
+            }
+        }
+        paths = next_paths;
+    }
+    paths.retain(|path| !path.is_empty());
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pattern_matches_test() {
+        assert!(pattern_matches("*.log", "a.log"));
+        assert!(pattern_matches("*.log", ".log"));
+        assert!(!pattern_matches("*.log", "a.log.gz"));
+        assert!(pattern_matches("a*b*c", "aXbYbZc"));
+        assert!(pattern_matches("?", "x"));
+        assert!(!pattern_matches("?", ""));
+        assert!(pattern_matches("[a-c]x", "bx"));
+        assert!(!pattern_matches("[!a-c]x", "bx"));
+        assert!(pattern_matches("[]]", "]"));
+        assert!(pattern_matches("[[:digit:]]*", "1abc"));
+        assert!(pattern_matches("[", "["));
+        assert!(pattern_matches("\\*", "*"));
+        assert!(!pattern_matches("\\*", "a"));
+        assert!(pattern_matches("", ""));
+        assert!(pattern_matches("*", ""));
+    }
+
+    #[test]
+    fn escape_pattern_test() {
+        let pattern = escape_pattern("a*[b]?");
+        assert_eq!(pattern, "a\\*\\[b\\]\\?");
+        assert!(!has_wildcards(&pattern));
+        assert!(has_wildcards("a*"));
+        assert_eq!(unescape_pattern(&pattern), "a*[b]?");
+        assert!(pattern_matches(&pattern, "a*[b]?"));
+    }
+}
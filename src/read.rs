@@ -0,0 +1,419 @@
+extern crate nix;
+
+use builtins::Streams;
+use executor::run_pending_traps;
+use parser::is_valid_name;
+use read::nix::errno::Errno;
+use read::nix::poll::{poll, EventFlags, PollFd};
+use read::nix::sys::signal::Signal;
+use read::nix::sys::termios::{
+    tcgetattr, tcsetattr, LocalFlags, SetArg, SpecialCharacterIndices, Termios,
+};
+use read::nix::unistd::isatty;
+use shell::describe_io_error;
+use shell::{Shell, Value};
+use signals;
+use std::io::ErrorKind;
+use std::os::unix::io::RawFd;
+use std::time::{Duration, Instant};
+
+/* Builtins read from the shell's own standard input, which is where
+polling and the terminal settings apply. */
+const STDIN: RawFd = 0;
+
+/* The status of a read that times out, as if SIGALRM had ended it. */
+const TIMEOUT_STATUS: i32 = 142;
+
+const DEFAULT_IFS: &str = " \t\n";
+
+/* A character of the input, and whether a backslash escaped it, which
+keeps it from separating fields. */
+type InputCharacter = (char, bool);
+
+enum Input<T> {
+    Read(T),
+    End,
+    TimedOut,
+    Interrupted(Signal),
+}
+
+/* Changes the terminal settings for as long as it lives: -s turns off
+echo, and -n takes characters as they are typed instead of lines. */
+struct TerminalMode {
+    saved: Option<Termios>,
+}
+
+impl TerminalMode {
+    fn new(silent: bool, by_character: bool) -> TerminalMode {
+        if !(silent || by_character) || isatty(STDIN) != Ok(true) {
+            return TerminalMode { saved: None };
+        }
+        let saved = match tcgetattr(STDIN) {
+            Ok(saved) => saved,
+            Err(_) => return TerminalMode { saved: None },
+        };
+        let mut termios = saved.clone();
+        if silent {
+            termios.local_flags.remove(LocalFlags::ECHO);
+        }
+        if by_character {
+            termios.local_flags.remove(LocalFlags::ICANON);
+            termios.control_chars[SpecialCharacterIndices::VMIN as usize] = 1;
+            termios.control_chars[SpecialCharacterIndices::VTIME as usize] = 0;
+        }
+        let _ = tcsetattr(STDIN, SetArg::TCSANOW, &termios);
+        TerminalMode { saved: Some(saved) }
+    }
+}
+
+impl Drop for TerminalMode {
+    fn drop(&mut self) {
+        if let Some(ref saved) = self.saved {
+            let _ = tcsetattr(STDIN, SetArg::TCSANOW, saved);
+        }
+    }
+}
+
+/* The signal that interrupted a blocking read, if the shell has to act
+on it: a Ctrl-C, or a signal that has a trap. */
+fn caught_signal() -> Option<Signal> {
+    if signals::interrupted() {
+        Some(Signal::SIGINT)
+    } else {
+        signals::pending_signal()
+    }
+}
+
+/* Waits until there is input, returning None, or until the deadline
+passes or a caught signal arrives. */
+fn wait_for_input<T>(deadline: Instant) -> Option<Input<T>> {
+    loop {
+        let now = Instant::now();
+        if now >= deadline {
+            return Some(Input::TimedOut);
+        }
+        let remaining = deadline - now;
+        let milliseconds =
+            (remaining.as_secs() * 1000) as i32 + remaining.subsec_millis() as i32 + 1;
+        let mut fds = [PollFd::new(STDIN, EventFlags::POLLIN)];
+        match poll(&mut fds, milliseconds) {
+            Ok(0) => continue,
+            Err(nix::Error::Sys(Errno::EINTR)) => match caught_signal() {
+                Some(signal) => return Some(Input::Interrupted(signal)),
+                None => continue,
+            },
+            _ => return None,
+        }
+    }
+}
+
+/* Reads a single byte, so that nothing after the delimiter is taken
+from a pipe that the next command reads. */
+fn read_byte(streams: &mut Streams, deadline: Option<Instant>) -> Result<Input<u8>, String> {
+    let mut byte = [0];
+    loop {
+        if let Some(input) = deadline.and_then(wait_for_input) {
+            return Ok(input);
+        }
+        match streams.stdin.read(&mut byte) {
+            Ok(0) => return Ok(Input::End),
+            Ok(_) => return Ok(Input::Read(byte[0])),
+            Err(ref err) if err.kind() == ErrorKind::Interrupted => {
+                if let Some(signal) = caught_signal() {
+                    return Ok(Input::Interrupted(signal));
+                }
+            }
+            Err(err) => return Err(describe_io_error(&err)),
+        }
+    }
+}
+
+/* Reads the bytes of one UTF-8 character, which the first byte tells
+the length of. Invalid sequences end up as replacement characters. */
+fn read_character(streams: &mut Streams, deadline: Option<Instant>) -> Result<Input<char>, String> {
+    let first = match read_byte(streams, deadline)? {
+        Input::Read(first) => first,
+        Input::End => return Ok(Input::End),
+        Input::TimedOut => return Ok(Input::TimedOut),
+        Input::Interrupted(signal) => return Ok(Input::Interrupted(signal)),
+    };
+    let length = match first {
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf7 => 4,
+        _ => 1,
+    };
+    let mut bytes = vec![first];
+    while bytes.len() < length {
+        match read_byte(streams, deadline)? {
+            Input::Read(byte) => bytes.push(byte),
+            _ => break,
+        }
+    }
+    let decoded = String::from_utf8_lossy(&bytes).into_owned();
+    Ok(Input::Read(decoded.chars().next().unwrap_or('\u{fffd}')))
+}
+
+fn is_separator(ifs: &str, character: &InputCharacter) -> bool {
+    !character.1 && ifs.contains(character.0)
+}
+
+fn is_whitespace_separator(ifs: &str, character: &InputCharacter) -> bool {
+    is_separator(ifs, character) && DEFAULT_IFS.contains(character.0)
+}
+
+fn skip_whitespace<'a>(input: &'a [InputCharacter], ifs: &str) -> &'a [InputCharacter] {
+    let start = input
+        .iter()
+        .position(|character| !is_whitespace_separator(ifs, character))
+        .unwrap_or(input.len());
+    &input[start..]
+}
+
+fn to_string(input: &[InputCharacter]) -> String {
+    input.iter().map(|(character, _)| *character).collect()
+}
+
+/* Takes the next field from the input, together with the separators
+that end it: whitespace in IFS, at most one other IFS character and
+more whitespace. */
+fn next_field<'a>(input: &'a [InputCharacter], ifs: &str) -> (String, &'a [InputCharacter]) {
+    let input = skip_whitespace(input, ifs);
+    let end = input
+        .iter()
+        .position(|character| is_separator(ifs, character))
+        .unwrap_or(input.len());
+    let field = to_string(&input[..end]);
+    let mut rest = skip_whitespace(&input[end..], ifs);
+    if rest
+        .first()
+        .is_some_and(|character| is_separator(ifs, character))
+    {
+        rest = skip_whitespace(&rest[1..], ifs);
+    }
+    (field, rest)
+}
+
+/* Splits the input into the values of `count` variables. The last one
+gets the rest of the input, separators included, unless all that is
+left is a single field. */
+fn split_into(input: &[InputCharacter], ifs: &str, count: usize) -> Vec<String> {
+    let mut rest = skip_whitespace(input, ifs);
+    let mut values = Vec::new();
+    while values.len() + 1 < count {
+        let (field, remaining) = next_field(rest, ifs);
+        values.push(field);
+        rest = remaining;
+    }
+    let (field, remaining) = next_field(rest, ifs);
+    if remaining.is_empty() {
+        values.push(field);
+    } else {
+        let end = rest
+            .iter()
+            .rposition(|character| !is_whitespace_separator(ifs, character))
+            .map_or(0, |position| position + 1);
+        values.push(to_string(&rest[..end]));
+    }
+    values
+}
+
+/* Splits the input into as many fields as there are, for `read -a`. */
+fn split_fields(input: &[InputCharacter], ifs: &str) -> Vec<String> {
+    let mut rest = skip_whitespace(input, ifs);
+    let mut fields = Vec::new();
+    while !rest.is_empty() {
+        let (field, remaining) = next_field(rest, ifs);
+        fields.push(field);
+        rest = remaining;
+    }
+    fields
+}
+
+fn read_usage(streams: &mut Streams) -> i32 {
+    let _ = writeln!(
+        streams.stderr,
+        "read: usage: read [-rs] [-a array] [-d delim] [-n nchars] [-p prompt] \
+         [-t timeout] [name ...]"
+    );
+    2
+}
+
+/* `read [-rs] [-a array] [-d delim] [-n nchars] [-p prompt] [-t timeout]
+[name...]` reads a line and splits it on IFS into the variables, the
+last of which gets the rest of the line. Without names the whole line
+goes into REPLY, and with -a the fields go into an array. Backslashes
+escape the next character unless -r is given. -d ends the input at
+another character than newline, -n after a number of characters and -t
+after a number of seconds. -p shows a prompt and -s hides what is typed
+when reading from a terminal. The status is 1 at the end of the input
+and 142 on a timeout. A Ctrl-C or a signal with a trap interrupts the
+read, which then runs the trap and leaves the variables alone. */
+pub fn builtin_read(shell: &mut Shell, arguments: &[String], streams: &mut Streams) -> i32 {
+    let (mut raw, mut silent) = (false, false);
+    let (mut array, mut count, mut timeout, mut prompt) = (None, None, None, None);
+    let mut delimiter = '\n';
+    let mut position = 1;
+    while let Some(argument) = arguments.get(position) {
+        if !argument.starts_with('-') || argument == "-" {
+            break;
+        }
+        position += 1;
+        if argument == "--" {
+            break;
+        }
+        for (index, option) in argument.char_indices().skip(1) {
+            if option == 'r' || option == 's' {
+                raw |= option == 'r';
+                silent |= option == 's';
+                continue;
+            }
+            if !"adnpt".contains(option) {
+                streams.error(format!("read: -{}: invalid option", option));
+                return read_usage(streams);
+            }
+            let value = if index + 1 < argument.len() {
+                argument[index + 1..].to_string()
+            } else if let Some(value) = arguments.get(position) {
+                position += 1;
+                value.clone()
+            } else {
+                streams.error(format!("read: -{}: option requires an argument", option));
+                return read_usage(streams);
+            };
+            match option {
+                'a' => array = Some(value),
+                'd' => delimiter = value.chars().next().unwrap_or('\0'),
+                'n' => match value.parse::<usize>() {
+                    Ok(value) => count = Some(value),
+                    Err(_) => {
+                        streams.error(format!("read: {}: invalid number", value));
+                        return 1;
+                    }
+                },
+                'p' => prompt = Some(value),
+                _ => match value.parse::<f64>() {
+                    Ok(seconds) if seconds >= 0.0 && seconds.is_finite() => {
+                        timeout = Some(Duration::from_micros((seconds * 1_000_000.0) as u64))
+                    }
+                    _ => {
+                        streams.error(format!("read: {}: invalid timeout specification", value));
+                        return 1;
+                    }
+                },
+            }
+            break;
+        }
+    }
+    let names = &arguments[position..];
+    for name in array.iter().chain(names) {
+        if !is_valid_name(name) {
+            streams.error(format!("read: `{}': not a valid identifier", name));
+            return 1;
+        }
+    }
+
+    /* A timeout of zero only tells whether there is input to read. */
+    if timeout == Some(Duration::from_secs(0)) {
+        return match wait_for_input::<()>(Instant::now() + Duration::from_millis(1)) {
+            None => 0,
+            Some(Input::Interrupted(signal)) => {
+                run_pending_traps(shell);
+                128 + signal as i32
+            }
+            Some(_) => 1,
+        };
+    }
+    let terminal = isatty(STDIN) == Ok(true);
+    if let (Some(prompt), true) = (prompt, terminal) {
+        let _ = write!(streams.stderr, "{}", prompt);
+        let _ = streams.stderr.flush();
+    }
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let mut input = Vec::new();
+    let mut escaped = false;
+    let mut interrupted = false;
+    let status = {
+        let _mode = TerminalMode::new(silent, count.is_some());
+        loop {
+            if count.is_some_and(|count| input.len() >= count) {
+                break 0;
+            }
+            let character = match read_character(streams, deadline) {
+                Ok(Input::Read(character)) => character,
+                Ok(Input::End) => break 1,
+                Ok(Input::TimedOut) => break TIMEOUT_STATUS,
+                Ok(Input::Interrupted(signal)) => {
+                    interrupted = true;
+                    break 128 + signal as i32;
+                }
+                Err(err) => {
+                    streams.error(format!("read: read error: 0: {}", err));
+                    break 1;
+                }
+            };
+            if escaped {
+                escaped = false;
+                if character != '\n' {
+                    input.push((character, true));
+                }
+            } else if character == '\\' && !raw {
+                escaped = true;
+            } else if character == delimiter {
+                break 0;
+            } else {
+                input.push((character, false));
+            }
+        }
+    };
+    if interrupted {
+        run_pending_traps(shell);
+        return status;
+    }
+
+    let ifs = shell.get_variable("IFS").unwrap_or(DEFAULT_IFS).to_string();
+    if let Some(array) = array {
+        let elements = split_fields(&input, &ifs).into_iter().enumerate().collect();
+        shell.set_value(&array, Value::Indexed(elements));
+    } else if names.is_empty() {
+        shell.set_variable("REPLY", to_string(&input));
+    } else {
+        for (name, value) in names.iter().zip(split_into(&input, &ifs, names.len())) {
+            shell.set_variable(name, value);
+        }
+    }
+    status
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn characters(string: &str) -> Vec<InputCharacter> {
+        string
+            .chars()
+            .map(|character| (character, character == '#'))
+            .collect()
+    }
+
+    #[test]
+    fn split_into_test() {
+        let input = characters("  one   two  three  ");
+        assert_eq!(
+            split_into(&input, DEFAULT_IFS, 2),
+            vec!["one", "two  three"]
+        );
+        assert_eq!(
+            split_into(&input, DEFAULT_IFS, 4),
+            vec!["one", "two", "three", ""]
+        );
+        assert_eq!(split_into(&characters("x:y:"), ":", 2), vec!["x", "y"]);
+        assert_eq!(split_into(&characters("x:y:z:"), ":", 2), vec!["x", "y:z:"]);
+        assert_eq!(split_into(&characters("x::"), ":", 3), vec!["x", "", ""]);
+        assert_eq!(split_into(&characters("a#b c"), "# ", 2), vec!["a#b", "c"]);
+        assert_eq!(
+            split_fields(&characters(" a b  c "), DEFAULT_IFS),
+            vec!["a", "b", "c"]
+        );
+    }
+}
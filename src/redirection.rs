@@ -0,0 +1,352 @@
+extern crate nix;
+
+use executor::fork_child;
+use expansion::expand_here_document;
+use expansion::expand_word_to_string;
+use parser::Redirection;
+use parser::RedirectionKind;
+use redirection::nix::errno::Errno;
+use redirection::nix::fcntl::{fcntl, open, FcntlArg, OFlag};
+use redirection::nix::libc::PIPE_BUF;
+use redirection::nix::sys::stat::Mode;
+use redirection::nix::unistd::*;
+use shell::Shell;
+use shell::ShellError;
+use std::fs;
+use std::os::unix::io::RawFd;
+use std::process;
+
+/* A file descriptor that was replaced by a redirection, along with a
+copy of what it pointed to before. A copy of None means it was closed. */
+pub struct SavedFd {
+    fd: RawFd,
+    copy: Option<RawFd>,
+}
+
+enum Source {
+    Open(RawFd),
+    Duplicate(RawFd),
+    Close,
+}
+
+/* Performs the redirections of a command, in order. In a forked child
+the old descriptors can simply be overwritten, but when running in the
+shell itself `save` should be set, and the returned list has to be
+passed to `restore_redirections` afterwards. Descriptors opened for
+`{name}>` and the like are never saved, they stay open until they are
+closed explicitly. */
+pub fn apply_redirections(
+    shell: &mut Shell,
+    redirections: &[Redirection],
+    save: bool,
+) -> Result<Vec<SavedFd>, ShellError> {
+    let mut saved: Vec<SavedFd> = Vec::new();
+    for redirection in redirections {
+        if let Some(ref name) = redirection.variable {
+            if let Err(err) = redirect_variable(shell, redirection, name) {
+                restore_redirections(saved);
+                return Err(err);
+            }
+            continue;
+        }
+        let fd = redirection.target_fd();
+        let source = match open_source(shell, redirection) {
+            Ok(source) => source,
+            Err(err) => {
+                restore_redirections(saved);
+                return Err(err);
+            }
+        };
+        /* The source is opened first so that the copy can't take the
+        number of a closed descriptor that `N>&M` names. A file opened
+        as the target itself means the target was closed before. */
+        if save && saved.iter().all(|saved_fd| saved_fd.fd != fd) {
+            let copy = match source {
+                Source::Open(new_fd) if new_fd == fd => None,
+                _ => save_fd(fd),
+            };
+            saved.push(SavedFd { fd, copy });
+        }
+        /* A failed `N>&M` is reported against M, the descriptor that
+        couldn't be duplicated. */
+        let result = match source {
+            Source::Open(new_fd) if new_fd == fd => Ok(()),
+            Source::Open(new_fd) => {
+                let result = dup2(new_fd, fd).map(|_| ());
+                let _ = close(new_fd);
+                result.map_err(|err| (fd, err))
+            }
+            Source::Duplicate(new_fd) if new_fd == fd => Ok(()),
+            Source::Duplicate(new_fd) => dup2(new_fd, fd).map(|_| ()).map_err(|err| (new_fd, err)),
+            Source::Close => {
+                let _ = close(fd);
+                Ok(())
+            }
+        };
+        if let Err((failed_fd, err)) = result {
+            restore_redirections(saved);
+            return Err(ShellError::Redirection(failed_fd.to_string(), err));
+        }
+    }
+    Ok(saved)
+}
+
+/* Opens a new descriptor of 10 or above for a redirection and stores
+its number in the variable, or closes the descriptor whose number the
+variable holds for `{name}>&-`. */
+fn redirect_variable(
+    shell: &mut Shell,
+    redirection: &Redirection,
+    name: &str,
+) -> Result<(), ShellError> {
+    let new_fd = match open_source(shell, redirection)? {
+        Source::Close => {
+            let value = shell.get_variable(name).unwrap_or("").to_string();
+            return match value.parse::<RawFd>() {
+                Ok(fd) if fd >= 0 => {
+                    let _ = close(fd);
+                    Ok(())
+                }
+                _ => Err(ShellError::AmbiguousRedirect(name.to_string())),
+            };
+        }
+        Source::Open(fd) => {
+            let result = fcntl(fd, FcntlArg::F_DUPFD(10));
+            let _ = close(fd);
+            result.map_err(|err| ShellError::Redirection(name.to_string(), err))
+        }
+        Source::Duplicate(fd) => fcntl(fd, FcntlArg::F_DUPFD(10))
+            .map_err(|err| ShellError::Redirection(fd.to_string(), err)),
+    }?;
+    shell.set_variable(name, new_fd.to_string());
+    Ok(())
+}
+
+/* Undoes the redirections recorded by `apply_redirections`. */
+pub fn restore_redirections(saved: Vec<SavedFd>) {
+    for saved_fd in saved.into_iter().rev() {
+        match saved_fd.copy {
+            Some(copy) => {
+                let _ = dup2(copy, saved_fd.fd);
+                let _ = close(copy);
+            }
+            None => {
+                let _ = close(saved_fd.fd);
+            }
+        }
+    }
+}
+
+/* Copies a descriptor out of the way, above the range users normally
+redirect, and marks the copy close-on-exec so children never see it. */
+fn save_fd(fd: RawFd) -> Option<RawFd> {
+    fcntl(fd, FcntlArg::F_DUPFD_CLOEXEC(10)).ok()
+}
+
+fn open_source(shell: &mut Shell, redirection: &Redirection) -> Result<Source, ShellError> {
+    let mode = Mode::from_bits_truncate(0o666);
+    let (target, flags) = match redirection.kind {
+        RedirectionKind::Input(ref word) => (word, OFlag::O_RDONLY),
+        RedirectionKind::Output(ref word) if shell.options.noclobber => {
+            let path = expand_word_to_string(shell, word)?;
+            return open_without_clobbering(path);
+        }
+        RedirectionKind::Output(ref word) | RedirectionKind::Clobber(ref word) => {
+            (word, OFlag::O_WRONLY | OFlag::O_CREAT | OFlag::O_TRUNC)
+        }
+        RedirectionKind::Append(ref word) => {
+            (word, OFlag::O_WRONLY | OFlag::O_CREAT | OFlag::O_APPEND)
+        }
+        RedirectionKind::ReadWrite(ref word) => (word, OFlag::O_RDWR | OFlag::O_CREAT),
+        RedirectionKind::DuplicateInput(ref word) | RedirectionKind::DuplicateOutput(ref word) => {
+            let target = expand_word_to_string(shell, word)?;
+            if target == "-" {
+                return Ok(Source::Close);
+            }
+            return match target.parse::<RawFd>() {
+                Ok(fd) if fd >= 0 => match fcntl(fd, FcntlArg::F_GETFD) {
+                    Ok(_) => Ok(Source::Duplicate(fd)),
+                    Err(err) => Err(ShellError::Redirection(target, err)),
+                },
+                _ => Err(ShellError::AmbiguousRedirect(target)),
+            };
+        }
+        RedirectionKind::HereDocument(ref here_document) => {
+            let content = if here_document.is_quoted() {
+                here_document.body.clone()
+            } else {
+                expand_here_document(shell, &here_document.body)?
+            };
+            return Ok(Source::Open(stdin_fd_for(shell, content.as_bytes())?));
+        }
+        RedirectionKind::HereString(ref word) => {
+            let mut content = expand_word_to_string(shell, word)?;
+            content.push('\n');
+            return Ok(Source::Open(stdin_fd_for(shell, content.as_bytes())?));
+        }
+    };
+    let path = expand_word_to_string(shell, target)?;
+    match open(path.as_str(), flags, mode) {
+        Ok(fd) => Ok(Source::Open(fd)),
+        Err(err) => Err(ShellError::Redirection(path, err)),
+    }
+}
+
+/* With noclobber, `>` refuses to truncate an existing regular file.
+Anything else, like a device, is still opened, and a new file is
+created exclusively so that one made in the meantime isn't truncated
+either. */
+fn open_without_clobbering(path: String) -> Result<Source, ShellError> {
+    let mode = Mode::from_bits_truncate(0o666);
+    let flags = match fs::metadata(&path) {
+        Ok(ref metadata) if metadata.is_file() => return Err(ShellError::Clobber(path)),
+        Ok(_) => OFlag::O_WRONLY,
+        Err(_) => OFlag::O_WRONLY | OFlag::O_CREAT | OFlag::O_EXCL,
+    };
+    match open(path.as_str(), flags, mode) {
+        Ok(fd) => Ok(Source::Open(fd)),
+        Err(nix::Error::Sys(Errno::EEXIST)) => Err(ShellError::Clobber(path)),
+        Err(err) => Err(ShellError::Redirection(path, err)),
+    }
+}
+
+/* Returns a descriptor that reads back `content`. This is how here-
+documents and here-strings feed a command's stdin, whether the command
+is a builtin or an external program. POSIX only promises that a write
+of up to PIPE_BUF bytes is atomic, so only contents that small are
+written into the pipe up front. Anything larger is fed by a forked
+writer, so the shell never blocks on a full pipe. */
+pub fn stdin_fd_for(shell: &mut Shell, content: &[u8]) -> nix::Result<RawFd> {
+    let (read_end, write_end) = pipe()?;
+    if content.len() <= PIPE_BUF {
+        let result = write_all(write_end, content);
+        close(write_end)?;
+        return match result {
+            Ok(()) => Ok(read_end),
+            Err(err) => {
+                let _ = close(read_end);
+                Err(err)
+            }
+        };
+    }
+    match fork_child(shell, false) {
+        Ok(ForkResult::Parent { child }) => {
+            let _ = close(write_end);
+            shell.unreaped_children.push(child);
+            Ok(read_end)
+        }
+        Ok(ForkResult::Child) => {
+            let _ = close(read_end);
+            let status = match write_all(write_end, content) {
+                Ok(()) => 0,
+                Err(_) => 1,
+            };
+            process::exit(status);
+        }
+        Err(err) => {
+            let _ = close(read_end);
+            let _ = close(write_end);
+            Err(err)
+        }
+    }
+}
+
+fn write_all(fd: RawFd, content: &[u8]) -> nix::Result<()> {
+    let mut written = 0;
+    while written < content.len() {
+        match write(fd, &content[written..]) {
+            Ok(count) => written += count,
+            Err(nix::Error::Sys(Errno::EINTR)) => (),
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_back(fd: RawFd) -> Vec<u8> {
+        let mut content = Vec::new();
+        let mut buffer = [0u8; 1024];
+        loop {
+            match read(fd, &mut buffer) {
+                Ok(0) => break,
+                Ok(count) => content.extend_from_slice(&buffer[..count]),
+                Err(err) => panic!("{:?}", err),
+            }
+        }
+        close(fd).unwrap();
+        content
+    }
+
+    fn redirection(fd: Option<i32>, variable: Option<&str>, kind: RedirectionKind) -> Redirection {
+        Redirection {
+            fd,
+            variable: variable.map(|name| name.to_string()),
+            kind,
+        }
+    }
+
+    fn is_open(fd: RawFd) -> bool {
+        fcntl(fd, FcntlArg::F_GETFD).is_ok()
+    }
+
+    /* What `exec` does with its redirections: they stay in place when
+    they aren't saved, while saved ones are undone afterwards. */
+    #[test]
+    fn apply_redirections_test() {
+        let mut shell = Shell::new();
+        let path = ::std::env::temp_dir().join(format!("sheller-test-{}", getpid()));
+        let path = path.to_str().unwrap().to_string();
+        let output = |fd| redirection(fd, None, RedirectionKind::Output(path.clone()));
+        let close_fd = |fd| redirection(fd, None, RedirectionKind::DuplicateOutput("-".into()));
+
+        apply_redirections(&mut shell, &[output(Some(57))], false).unwrap();
+        assert!(is_open(57));
+        apply_redirections(&mut shell, &[close_fd(Some(57))], false).unwrap();
+        assert!(!is_open(57));
+
+        let saved = apply_redirections(&mut shell, &[output(Some(57))], true).unwrap();
+        assert!(is_open(57));
+        restore_redirections(saved);
+        assert!(!is_open(57));
+
+        let open_variable = redirection(None, Some("fd"), RedirectionKind::Output(path.clone()));
+        let saved = apply_redirections(&mut shell, &[open_variable], true).unwrap();
+        assert!(saved.is_empty());
+        let fd: RawFd = shell.get_variable("fd").unwrap().parse().unwrap();
+        assert!(fd >= 10 && is_open(fd));
+        let close_variable = redirection(
+            None,
+            Some("fd"),
+            RedirectionKind::DuplicateOutput("-".into()),
+        );
+        apply_redirections(&mut shell, &[close_variable], false).unwrap();
+        assert!(!is_open(fd));
+
+        let duplicate_closed = redirection(
+            Some(57),
+            None,
+            RedirectionKind::DuplicateOutput(fd.to_string()),
+        );
+        assert!(apply_redirections(&mut shell, &[output(Some(57))], false).is_ok());
+        assert!(apply_redirections(&mut shell, &[duplicate_closed], true).is_err());
+        assert!(is_open(57) && !is_open(fd));
+        apply_redirections(&mut shell, &[close_fd(Some(57))], false).unwrap();
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn stdin_fd_for_test() {
+        let mut shell = Shell::new();
+
+        let small = b"hello\n".to_vec();
+        assert_eq!(read_back(stdin_fd_for(&mut shell, &small).unwrap()), small);
+
+        let large = vec![b'x'; PIPE_BUF * 20];
+        assert_eq!(read_back(stdin_fd_for(&mut shell, &large).unwrap()), large);
+    }
+}
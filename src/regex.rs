@@ -0,0 +1,196 @@
+extern crate nix;
+
+use regex::nix::libc::{self, c_char, c_int, c_void, size_t};
+use std::ffi::CString;
+use std::mem;
+use std::sync::Once;
+
+/* POSIX extended regular expressions, as used by `=~`. They are
+compiled and matched by the C library, which finds the leftmost longest
+match like bash does. */
+pub struct Regex {
+    compiled: Box<RegexT>,
+}
+
+const REG_EXTENDED: c_int = 1;
+
+static LOCALE: Once = Once::new();
+
+/* The layout of regex_t differs between C libraries, and only the
+number of groups is read from it. */
+#[cfg(all(target_os = "linux", not(target_env = "musl")))]
+#[repr(C)]
+struct RegexT {
+    buffer: *mut c_void,
+    allocated: size_t,
+    used: size_t,
+    syntax: size_t,
+    fastmap: *mut c_char,
+    translate: *mut c_void,
+    re_nsub: size_t,
+    flags: [u8; 8],
+}
+
+#[cfg(target_env = "musl")]
+#[repr(C)]
+struct RegexT {
+    re_nsub: size_t,
+    opaque: *mut c_void,
+    padding: [*mut c_void; 4],
+    nsub: size_t,
+    padding2: c_char,
+}
+
+#[cfg(not(target_os = "linux"))]
+#[repr(C)]
+struct RegexT {
+    re_magic: c_int,
+    re_nsub: size_t,
+    re_endp: *const c_char,
+    re_g: *mut c_void,
+}
+
+#[cfg(all(target_os = "linux", not(target_env = "musl")))]
+type RegOff = c_int;
+
+#[cfg(not(all(target_os = "linux", not(target_env = "musl"))))]
+type RegOff = isize;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct RegMatch {
+    rm_so: RegOff,
+    rm_eo: RegOff,
+}
+
+extern "C" {
+    fn regcomp(regex: *mut RegexT, pattern: *const c_char, flags: c_int) -> c_int;
+    fn regexec(
+        regex: *const RegexT,
+        text: *const c_char,
+        count: size_t,
+        matches: *mut RegMatch,
+        flags: c_int,
+    ) -> c_int;
+    fn regerror(code: c_int, regex: *const RegexT, buffer: *mut c_char, size: size_t) -> size_t;
+    fn regfree(regex: *mut RegexT);
+}
+
+impl Regex {
+    /* The character type of the locale from the environment decides
+    whether `.` and brackets match characters or single bytes, as in
+    bash. */
+    pub fn new(pattern: &str) -> Result<Regex, String> {
+        LOCALE.call_once(|| unsafe {
+            libc::setlocale(libc::LC_CTYPE, b"\0".as_ptr() as *const c_char);
+        });
+        let pattern = CString::new(pattern).map_err(|_| "null byte in pattern".to_string())?;
+        let mut compiled: Box<RegexT> = Box::new(unsafe { mem::zeroed() });
+        let code = unsafe { regcomp(&mut *compiled, pattern.as_ptr(), REG_EXTENDED) };
+        if code != 0 {
+            let mut buffer = vec![0u8; 256];
+            let length = unsafe {
+                regerror(
+                    code,
+                    &*compiled,
+                    buffer.as_mut_ptr() as *mut c_char,
+                    buffer.len(),
+                )
+            };
+            buffer.truncate(length.min(buffer.len()).saturating_sub(1));
+            return Err(String::from_utf8_lossy(&buffer).into_owned());
+        }
+        Ok(Regex { compiled })
+    }
+
+    /* Finds the leftmost longest match in `text`. Returns the text of
+    the whole match followed by that of every group, or None for groups
+    that didn't take part in it. */
+    pub fn captures(&self, text: &str) -> Option<Vec<Option<String>>> {
+        let text = CString::new(text).ok()?;
+        let mut matches = vec![
+            RegMatch {
+                rm_so: -1,
+                rm_eo: -1
+            };
+            self.compiled.re_nsub + 1
+        ];
+        let code = unsafe {
+            regexec(
+                &*self.compiled,
+                text.as_ptr(),
+                matches.len(),
+                matches.as_mut_ptr(),
+                0,
+            )
+        };
+        if code != 0 {
+            return None;
+        }
+        let bytes = text.as_bytes();
+        Some(
+            matches
+                .iter()
+                .map(|range| {
+                    if range.rm_so < 0 {
+                        return None;
+                    }
+                    let range = &bytes[range.rm_so as usize..range.rm_eo as usize];
+                    Some(String::from_utf8_lossy(range).into_owned())
+                })
+                .collect(),
+        )
+    }
+}
+
+impl Drop for Regex {
+    fn drop(&mut self) {
+        unsafe { regfree(&mut *self.compiled) }
+    }
+}
+
+pub fn is_regex_special(character: char) -> bool {
+    "\\^$.|?*+()[]{}".contains(character)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn captures(pattern: &str, text: &str) -> Option<Vec<Option<String>>> {
+        Regex::new(pattern).unwrap().captures(text)
+    }
+
+    fn strings(values: Vec<&str>) -> Option<Vec<Option<String>>> {
+        Some(values.iter().map(|value| Some(value.to_string())).collect())
+    }
+
+    #[test]
+    fn regex_test() {
+        assert_eq!(captures("b+", "abbbc"), strings(vec!["bbb"]));
+        assert_eq!(captures("^a.c$", "abc"), strings(vec!["abc"]));
+        assert_eq!(captures("^a.c$", "abcd"), None);
+        assert_eq!(
+            captures("([a-z]+)-([0-9]{2,3})", "id: abc-1234"),
+            strings(vec!["abc-123", "abc", "123"])
+        );
+        assert_eq!(
+            captures("(a|ab)(c|bcd)$", "abcd"),
+            strings(vec!["abcd", "a", "bcd"])
+        );
+        assert_eq!(captures("a|ab|abc", "abcd"), strings(vec!["abc"]));
+        assert_eq!(
+            captures("a(b)?c", "ac"),
+            Some(vec![Some("ac".to_string()), None])
+        );
+        assert_eq!(captures("[[:digit:]]\\.x", "v1.x"), strings(vec!["1.x"]));
+        assert_eq!(captures("", "abc"), strings(vec![""]));
+        let text = format!("{}!", "a".repeat(32));
+        assert_eq!(captures("^(a|aa)+$", &text), None);
+
+        assert!(Regex::new("(a").is_err());
+        assert!(Regex::new("*a").is_err());
+        assert!(Regex::new("[a").is_err());
+        assert!(Regex::new("a{3,1}").is_err());
+    }
+}
@@ -0,0 +1,474 @@
+extern crate nix;
+
+use builtins::Builtins;
+use directory::initial_directory;
+use hash::HashedCommand;
+use options::Options;
+use parser::Command;
+use shell::nix::unistd::Pid;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::env;
+use std::fmt;
+use std::io;
+use std::os::unix::io::RawFd;
+use std::process;
+use std::rc::Rc;
+
+/* Indexed arrays can be sparse, so both kinds of arrays are maps. */
+#[derive(PartialEq, Debug, Clone)]
+pub enum Value {
+    Scalar(String),
+    Indexed(BTreeMap<usize, String>),
+    Associative(BTreeMap<String, String>),
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct Variable {
+    pub value: Value,
+    pub exported: bool,
+}
+
+impl Value {
+    /* What the variable expands to without a subscript. For arrays this
+    is the element at index 0. */
+    pub fn as_scalar(&self) -> Option<&str> {
+        match self {
+            Value::Scalar(value) => Some(value),
+            Value::Indexed(elements) => elements.get(&0).map(|value| value.as_str()),
+            Value::Associative(elements) => elements.get("0").map(|value| value.as_str()),
+        }
+    }
+
+    pub fn elements(&self) -> Vec<String> {
+        match self {
+            Value::Scalar(value) => vec![value.clone()],
+            Value::Indexed(elements) => elements.values().cloned().collect(),
+            Value::Associative(elements) => elements.values().cloned().collect(),
+        }
+    }
+
+    pub fn keys(&self) -> Vec<String> {
+        match self {
+            Value::Scalar(_) => vec!["0".to_string()],
+            Value::Indexed(elements) => elements.keys().map(|key| key.to_string()).collect(),
+            Value::Associative(elements) => elements.keys().cloned().collect(),
+        }
+    }
+}
+
+/* A process substitution whose end of the pipe is still open in the
+shell, waiting for the command that uses it to finish. */
+pub struct ProcessSubstitution {
+    pub fd: RawFd,
+    pub pid: Pid,
+}
+
+/* Set by `break` and `continue` to tell the enclosing loops what to do
+once the current command is done. The count is the number of enclosing
+loops still affected. `return` unwinds everything up to the function
+call, and `exit` everything up to the shell with the status to exit
+with. */
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum ControlFlow {
+    Normal,
+    Break(usize),
+    Continue(usize),
+    Return,
+    Exit(i32),
+}
+
+/* Everything the shell needs to remember between commands. */
+pub struct Shell {
+    variables: HashMap<String, Variable>,
+    pending_exports: HashSet<String>,
+    local_scopes: Vec<HashMap<String, Option<Variable>>>,
+    pub functions: HashMap<String, Rc<Command>>,
+    pub builtins: Builtins,
+    pub function_depth: usize,
+    pub source_depth: usize,
+    pub last_status: i32,
+    pub substitution_status: Option<i32>,
+    pub shell_pid: u32,
+    pub script_name: String,
+    pub positional_parameters: Vec<String>,
+    pub last_background_pid: Option<Pid>,
+    pub exit_warned: bool,
+    pub control_flow: ControlFlow,
+    pub loop_depth: usize,
+    pub process_substitutions: Vec<ProcessSubstitution>,
+    pub unreaped_children: Vec<Pid>,
+    pub stopped_children: Vec<Pid>,
+    pub directory_stack: Vec<String>,
+    pub command_hash: BTreeMap<String, HashedCommand>,
+    pub aliases: BTreeMap<String, String>,
+    pub traps: BTreeMap<String, String>,
+    pub in_trap: bool,
+    pub condition_depth: usize,
+    pub options: Options,
+    pub interactive: bool,
+}
+
+#[derive(Debug)]
+pub enum ShellError {
+    BadSubstitution(String),
+    Redirection(String, nix::Error),
+    Exec(String, nix::Error),
+    AmbiguousRedirect(String),
+    CommandNotFound(String),
+    NullByte(String),
+    Arithmetic(String, String),
+    FunctionNesting(String, usize),
+    BadArraySubscript(String),
+    MissingSubscript(String),
+    InvalidRegex(String, String),
+    UnboundVariable(String),
+    ParameterNotSet(String, String),
+    CannotAssign(String),
+    Clobber(String),
+    Nix(nix::Error),
+}
+
+impl Shell {
+    pub fn new() -> Shell {
+        let mut variables = HashMap::new();
+        for (name, value) in env::vars() {
+            variables.insert(
+                name,
+                Variable {
+                    value: Value::Scalar(value),
+                    exported: true,
+                },
+            );
+        }
+        let pwd = variables
+            .get("PWD")
+            .and_then(|variable| variable.value.as_scalar());
+        if let Some(directory) = initial_directory(pwd) {
+            variables.insert(
+                "PWD".to_string(),
+                Variable {
+                    value: Value::Scalar(directory),
+                    exported: true,
+                },
+            );
+        }
+        let mut aliases = BTreeMap::new();
+        aliases.insert("quit".to_string(), "exit".to_string());
+        Shell {
+            variables,
+            pending_exports: HashSet::new(),
+            local_scopes: Vec::new(),
+            functions: HashMap::new(),
+            builtins: Builtins::new(),
+            function_depth: 0,
+            source_depth: 0,
+            last_status: 0,
+            substitution_status: None,
+            shell_pid: process::id(),
+            script_name: "sheller".to_string(),
+            positional_parameters: Vec::new(),
+            last_background_pid: None,
+            exit_warned: false,
+            control_flow: ControlFlow::Normal,
+            loop_depth: 0,
+            process_substitutions: Vec::new(),
+            unreaped_children: Vec::new(),
+            stopped_children: Vec::new(),
+            directory_stack: Vec::new(),
+            command_hash: BTreeMap::new(),
+            aliases,
+            traps: BTreeMap::new(),
+            in_trap: false,
+            condition_depth: 0,
+            options: Options::default(),
+            interactive: false,
+        }
+    }
+
+    pub fn get_variable(&self, name: &str) -> Option<&str> {
+        self.variables
+            .get(name)
+            .and_then(|variable| variable.value.as_scalar())
+    }
+
+    /* Setting an array without a subscript sets its element 0. */
+    pub fn set_variable(&mut self, name: &str, value: String) {
+        self.variable_changed(name);
+        if let Some(variable) = self.variables.get_mut(name) {
+            match variable.value {
+                Value::Scalar(_) => variable.value = Value::Scalar(value),
+                Value::Indexed(ref mut elements) => {
+                    elements.insert(0, value);
+                }
+                Value::Associative(ref mut elements) => {
+                    elements.insert("0".to_string(), value);
+                }
+            }
+            return;
+        }
+        self.set_value(name, Value::Scalar(value));
+    }
+
+    pub fn get_value(&self, name: &str) -> Option<&Value> {
+        self.variables.get(name).map(|variable| &variable.value)
+    }
+
+    pub fn get_value_mut(&mut self, name: &str) -> Option<&mut Value> {
+        self.variables
+            .get_mut(name)
+            .map(|variable| &mut variable.value)
+    }
+
+    /* Replaces the whole value of a variable, keeping it exported if it
+    was. */
+    pub fn set_value(&mut self, name: &str, value: Value) {
+        self.variable_changed(name);
+        if let Some(variable) = self.variables.get_mut(name) {
+            variable.value = value;
+            return;
+        }
+        let exported = self.pending_exports.remove(name);
+        self.variables
+            .insert(name.to_string(), Variable { value, exported });
+    }
+
+    pub fn unset_variable(&mut self, name: &str) {
+        self.variable_changed(name);
+        self.variables.remove(name);
+        self.pending_exports.remove(name);
+    }
+
+    /* Commands found in the old PATH may not be the ones the new PATH
+    finds, so changing it forgets them all. */
+    fn variable_changed(&mut self, name: &str) {
+        if name == "PATH" {
+            self.command_hash.clear();
+        }
+    }
+
+    pub fn is_exported(&self, name: &str) -> bool {
+        match self.variables.get(name) {
+            Some(variable) => variable.exported,
+            None => self.pending_exports.contains(name),
+        }
+    }
+
+    /* Marks a variable for the environment of commands, or takes it out
+    of it. A variable that isn't set yet is exported once it gets a
+    value. */
+    pub fn set_exported(&mut self, name: &str, exported: bool) {
+        match self.variables.get_mut(name) {
+            Some(variable) => variable.exported = exported,
+            None if exported => {
+                self.pending_exports.insert(name.to_string());
+            }
+            None => {
+                self.pending_exports.remove(name);
+            }
+        }
+    }
+
+    /* The names of all variables that are set, in sorted order. */
+    pub fn variable_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.variables.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /* The names of exported variables, set or not, in sorted order. */
+    pub fn exported_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .variables
+            .iter()
+            .filter(|(_, variable)| variable.exported)
+            .map(|(name, _)| name.clone())
+            .chain(self.pending_exports.iter().cloned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    /* The environment commands are started with. Arrays can't be
+    exported, so only scalars are part of it. */
+    pub fn environment(&self) -> Vec<(String, String)> {
+        let mut environment: Vec<(String, String)> = self
+            .variables
+            .iter()
+            .filter_map(|(name, variable)| match variable.value {
+                Value::Scalar(ref value) if variable.exported => {
+                    Some((name.clone(), value.clone()))
+                }
+                _ => None,
+            })
+            .collect();
+        environment.sort();
+        environment
+    }
+
+    /* Every function call gets a scope for its local variables. Leaving
+    it puts back whatever the variables were before they were made
+    local, so a function sees the locals of the functions calling it. */
+    pub fn push_scope(&mut self) {
+        self.local_scopes.push(HashMap::new());
+    }
+
+    pub fn pop_scope(&mut self) {
+        if let Some(scope) = self.local_scopes.pop() {
+            for (name, previous) in scope {
+                match previous {
+                    Some(variable) => self.variables.insert(name, variable),
+                    None => self.variables.remove(&name),
+                };
+            }
+        }
+    }
+
+    /* Makes a variable local to the innermost scope. Returns false when
+    no function is running. */
+    pub fn make_local(&mut self, name: &str) -> bool {
+        match self.local_scopes.last_mut() {
+            Some(scope) => {
+                if !scope.contains_key(name) {
+                    scope.insert(name.to_string(), self.variables.get(name).cloned());
+                }
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl Default for Shell {
+    fn default() -> Shell {
+        Shell::new()
+    }
+}
+
+impl From<nix::Error> for ShellError {
+    fn from(err: nix::Error) -> ShellError {
+        ShellError::Nix(err)
+    }
+}
+
+/* Turns a nix error into the short description a user expects, like
+"No such file or directory". */
+pub fn describe_nix_error(err: &nix::Error) -> String {
+    match err {
+        nix::Error::Sys(errno) => errno.desc().to_string(),
+        _ => err.to_string(),
+    }
+}
+
+/* The same for errors of the standard library, which would otherwise
+end in "(os error 9)". */
+pub fn describe_io_error(err: &io::Error) -> String {
+    match err.raw_os_error() {
+        Some(errno) => nix::errno::Errno::from_i32(errno).desc().to_string(),
+        None => err.to_string(),
+    }
+}
+
+impl fmt::Display for ShellError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ShellError::BadSubstitution(word) => write!(f, "{}: bad substitution", word),
+            ShellError::Redirection(target, err) => {
+                write!(f, "{}: {}", target, describe_nix_error(err))
+            }
+            ShellError::Exec(program, err) => write!(f, "{}: {}", program, describe_nix_error(err)),
+            ShellError::AmbiguousRedirect(word) => write!(f, "{}: ambiguous redirect", word),
+            ShellError::CommandNotFound(name) => write!(f, "{}: command not found", name),
+            ShellError::NullByte(word) => write!(f, "{}: argument contains a null byte", word),
+            ShellError::Arithmetic(expression, message) => write!(f, "{}: {}", expression, message),
+            ShellError::FunctionNesting(name, limit) => write!(
+                f,
+                "{}: maximum function nesting level exceeded ({})",
+                name, limit
+            ),
+            ShellError::BadArraySubscript(word) => write!(f, "{}: bad array subscript", word),
+            ShellError::MissingSubscript(word) => {
+                write!(
+                    f,
+                    "{}: must use subscript when assigning associative array",
+                    word
+                )
+            }
+            ShellError::InvalidRegex(pattern, message) => write!(f, "{}: {}", pattern, message),
+            ShellError::UnboundVariable(name) => write!(f, "{}: unbound variable", name),
+            ShellError::ParameterNotSet(name, message) => write!(f, "{}: {}", name, message),
+            ShellError::CannotAssign(name) => write!(f, "{}: cannot assign in this way", name),
+            ShellError::Clobber(path) => write!(f, "{}: cannot overwrite existing file", path),
+            ShellError::Nix(err) => write!(f, "{}", describe_nix_error(err)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn local_scope_test() {
+        let mut shell = Shell::new();
+        shell.set_variable("x", "global".to_string());
+        assert!(!shell.make_local("x"));
+
+        shell.push_scope();
+        assert!(shell.make_local("x"));
+        assert!(shell.make_local("y"));
+        shell.set_variable("x", "outer".to_string());
+        shell.set_variable("y", "outer".to_string());
+
+        shell.push_scope();
+        shell.make_local("x");
+        shell.unset_variable("x");
+        shell.set_variable("y", "changed".to_string());
+        assert_eq!(shell.get_variable("x"), None);
+        shell.pop_scope();
+
+        assert_eq!(shell.get_variable("x"), Some("outer"));
+        assert_eq!(shell.get_variable("y"), Some("changed"));
+        shell.pop_scope();
+        assert_eq!(shell.get_variable("x"), Some("global"));
+        assert_eq!(shell.get_variable("y"), None);
+    }
+
+    #[test]
+    fn array_value_test() {
+        let mut shell = Shell::new();
+        let mut elements = BTreeMap::new();
+        elements.insert(1, "b".to_string());
+        elements.insert(5, "c".to_string());
+        shell.set_value("a", Value::Indexed(elements));
+        assert_eq!(shell.get_variable("a"), None);
+
+        shell.set_variable("a", "x".to_string());
+        let value = shell.get_value("a").unwrap();
+        assert_eq!(value.elements(), vec!["x", "b", "c"]);
+        assert_eq!(value.keys(), vec!["0", "1", "5"]);
+        assert_eq!(shell.get_variable("a"), Some("x"));
+    }
+
+    #[test]
+    fn export_test() {
+        let mut shell = Shell::new();
+        shell.set_variable("x", "1".to_string());
+        assert!(!shell.is_exported("x"));
+        shell.set_exported("x", true);
+        shell.set_exported("later", true);
+        assert!(shell
+            .environment()
+            .contains(&("x".to_string(), "1".to_string())));
+        assert!(shell.exported_names().contains(&"later".to_string()));
+        assert!(!shell.environment().iter().any(|(name, _)| name == "later"));
+
+        shell.set_variable("later", "2".to_string());
+        assert!(shell
+            .environment()
+            .contains(&("later".to_string(), "2".to_string())));
+        shell.set_exported("x", false);
+        assert!(!shell.environment().iter().any(|(name, _)| name == "x"));
+    }
+}
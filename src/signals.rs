@@ -0,0 +1,192 @@
+extern crate nix;
+
+use signals::nix::libc::c_int;
+use signals::nix::sys::signal::*;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+static CATCHING_INTERRUPTS: AtomicBool = AtomicBool::new(false);
+
+/* One flag for every signal number, set when a caught signal arrives
+and cleared when the shell gets around to acting on it. */
+static PENDING: [AtomicBool; 32] = [const { AtomicBool::new(false) }; 32];
+
+/* The signals that can be named, without their `SIG` prefix. */
+pub const SIGNALS: [(&str, Signal); 29] = [
+    ("HUP", Signal::SIGHUP),
+    ("INT", Signal::SIGINT),
+    ("QUIT", Signal::SIGQUIT),
+    ("ILL", Signal::SIGILL),
+    ("TRAP", Signal::SIGTRAP),
+    ("ABRT", Signal::SIGABRT),
+    ("BUS", Signal::SIGBUS),
+    ("FPE", Signal::SIGFPE),
+    ("KILL", Signal::SIGKILL),
+    ("USR1", Signal::SIGUSR1),
+    ("SEGV", Signal::SIGSEGV),
+    ("USR2", Signal::SIGUSR2),
+    ("PIPE", Signal::SIGPIPE),
+    ("ALRM", Signal::SIGALRM),
+    ("TERM", Signal::SIGTERM),
+    ("CHLD", Signal::SIGCHLD),
+    ("CONT", Signal::SIGCONT),
+    ("STOP", Signal::SIGSTOP),
+    ("TSTP", Signal::SIGTSTP),
+    ("TTIN", Signal::SIGTTIN),
+    ("TTOU", Signal::SIGTTOU),
+    ("URG", Signal::SIGURG),
+    ("XCPU", Signal::SIGXCPU),
+    ("XFSZ", Signal::SIGXFSZ),
+    ("VTALRM", Signal::SIGVTALRM),
+    ("PROF", Signal::SIGPROF),
+    ("WINCH", Signal::SIGWINCH),
+    ("IO", Signal::SIGIO),
+    ("SYS", Signal::SIGSYS),
+];
+
+extern "C" fn handle_interrupt(_signal: c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+extern "C" fn handle_signal(signal: c_int) {
+    if let Some(pending) = PENDING.get(signal as usize) {
+        pending.store(true, Ordering::SeqCst);
+    }
+}
+
+/* Blocks every signal in the calling thread, so that the signals sent
+to the shell go to the thread running it and interrupt what it waits
+for, like a `read`. */
+pub fn block_signals_in_thread() {
+    let _ = SigSet::all().thread_block();
+}
+
+/* Makes Ctrl-C interrupt whatever the shell is running instead of
+killing the shell itself. The handler only records the interrupt, and
+the executor checks for it between commands. */
+pub fn catch_interrupts() {
+    let action = SigAction::new(
+        SigHandler::Handler(handle_interrupt),
+        SaFlags::empty(),
+        SigSet::empty(),
+    );
+    unsafe {
+        let _ = sigaction(Signal::SIGINT, &action);
+    }
+    CATCHING_INTERRUPTS.store(true, Ordering::SeqCst);
+}
+
+/* Forked children start out with the signal handling of a normal
+process, except for background jobs which shouldn't be stopped by a
+Ctrl-C meant for the foreground. */
+pub fn reset_child_signals(background: bool) {
+    if !background && !CATCHING_INTERRUPTS.load(Ordering::SeqCst) {
+        return;
+    }
+    let handler = if background {
+        SigHandler::SigIgn
+    } else {
+        SigHandler::SigDfl
+    };
+    let action = SigAction::new(handler, SaFlags::empty(), SigSet::empty());
+    unsafe {
+        let _ = sigaction(Signal::SIGINT, &action);
+    }
+    CATCHING_INTERRUPTS.store(false, Ordering::SeqCst);
+}
+
+/* The Rust runtime ignores SIGPIPE, which children mustn't inherit: a
+writer to a closed pipe should end quietly instead of writing forever or
+reporting the error. */
+pub fn reset_pipe_signal() {
+    set_handler(Signal::SIGPIPE, SigHandler::SigDfl);
+}
+
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+pub fn clear_interrupt() {
+    INTERRUPTED.store(false, Ordering::SeqCst);
+}
+
+pub fn signal_name(signal: Signal) -> &'static str {
+    SIGNALS
+        .iter()
+        .find(|(_, known)| *known == signal)
+        .map_or("?", |(name, _)| name)
+}
+
+/* Finds a signal by name, with or without the `SIG` prefix and in any
+case, or by number. */
+pub fn find_signal(specification: &str) -> Option<Signal> {
+    if let Ok(number) = specification.parse::<c_int>() {
+        return Signal::from_c_int(number).ok();
+    }
+    let name = specification.to_uppercase();
+    let name = name.strip_prefix("SIG").unwrap_or(&name);
+    SIGNALS
+        .iter()
+        .find(|(known, _)| *known == name)
+        .map(|(_, signal)| *signal)
+}
+
+fn set_handler(signal: Signal, handler: SigHandler) {
+    let action = SigAction::new(handler, SaFlags::empty(), SigSet::empty());
+    unsafe {
+        let _ = sigaction(signal, &action);
+    }
+}
+
+/* Makes a signal only be recorded, for the shell to act on between
+commands, where it is safe to run a trap. */
+pub fn catch_signal(signal: Signal) {
+    set_handler(signal, SigHandler::Handler(handle_signal));
+}
+
+pub fn ignore_signal(signal: Signal) {
+    set_handler(signal, SigHandler::SigIgn);
+}
+
+/* Gives a signal back the handling the shell started out with. */
+pub fn restore_signal(signal: Signal) {
+    if signal == Signal::SIGINT && CATCHING_INTERRUPTS.load(Ordering::SeqCst) {
+        set_handler(signal, SigHandler::Handler(handle_interrupt));
+    } else {
+        set_handler(signal, SigHandler::SigDfl);
+    }
+}
+
+/* Whether interrupts are caught for the prompt, in which case they stop
+the running command instead of ending the shell. */
+pub fn catching_interrupts() -> bool {
+    CATCHING_INTERRUPTS.load(Ordering::SeqCst)
+}
+
+/* The first signal that arrived and hasn't been acted on yet, which is
+left pending. */
+pub fn pending_signal() -> Option<Signal> {
+    PENDING
+        .iter()
+        .position(|pending| pending.load(Ordering::SeqCst))
+        .and_then(|number| Signal::from_c_int(number as c_int).ok())
+}
+
+/* Returns the signals that arrived since the last call. */
+pub fn take_pending_signals() -> Vec<Signal> {
+    PENDING
+        .iter()
+        .enumerate()
+        .filter(|(_, pending)| pending.swap(false, Ordering::SeqCst))
+        .filter_map(|(number, _)| Signal::from_c_int(number as c_int).ok())
+        .collect()
+}
+
+/* Ends the shell the way a signal it caught would have, for other
+processes to see that it was killed by it. */
+pub fn reraise(signal: Signal) -> ! {
+    set_handler(signal, SigHandler::SigDfl);
+    let _ = raise(signal);
+    process::exit(128 + signal as i32);
+}
@@ -0,0 +1,251 @@
+extern crate nix;
+
+use builtins::single_quote;
+use builtins::Streams;
+use shell::Shell;
+use signals::{catch_signal, catching_interrupts, find_signal, ignore_signal, restore_signal};
+use signals::{signal_name, SIGNALS};
+use trap::nix::sys::signal::Signal;
+
+/* Conditions that aren't signals but can still have a trap. EXIT comes
+first and the rest last when traps are listed. */
+const PSEUDO_SIGNALS: [&str; 4] = ["EXIT", "DEBUG", "ERR", "RETURN"];
+
+/* Finds the name a trap is kept under for a signal specification, which
+is a signal or pseudo-signal name with or without `SIG` in any case, or
+a signal number where 0 stands for EXIT. */
+pub fn trap_name(specification: &str) -> Option<String> {
+    let name = specification.to_uppercase();
+    let name = name.strip_prefix("SIG").unwrap_or(&name);
+    if name == "0" {
+        return Some("EXIT".to_string());
+    }
+    if PSEUDO_SIGNALS.contains(&name) {
+        return Some(name.to_string());
+    }
+    find_signal(specification).map(|signal| signal_name(signal).to_string())
+}
+
+/* The names that can have a trap, in the order they are listed. */
+fn trap_names() -> Vec<&'static str> {
+    let mut names = vec![PSEUDO_SIGNALS[0]];
+    names.extend(SIGNALS.iter().map(|(name, _)| *name));
+    names.extend(&PSEUDO_SIGNALS[1..]);
+    names
+}
+
+/* Signals that would end the shell without running the EXIT trap, so
+they are caught while there is one. Interrupts are left alone when the
+prompt catches them itself. */
+fn fatal_signals() -> Vec<Signal> {
+    let mut signals = vec![Signal::SIGHUP, Signal::SIGTERM];
+    if !catching_interrupts() {
+        signals.push(Signal::SIGINT);
+    }
+    signals
+}
+
+/* Sets up how the shell handles a signal according to the traps. */
+fn install_handler(shell: &Shell, signal: Signal) {
+    match shell.traps.get(signal_name(signal)) {
+        Some(command) if command.is_empty() => ignore_signal(signal),
+        Some(_) => catch_signal(signal),
+        None if shell.traps.contains_key("EXIT") && fatal_signals().contains(&signal) => {
+            catch_signal(signal)
+        }
+        None => restore_signal(signal),
+    }
+}
+
+fn install_handlers(shell: &Shell, names: &[String]) {
+    let mut signals: Vec<Signal> = names.iter().filter_map(|name| find_signal(name)).collect();
+    if names.iter().any(|name| name == "EXIT") {
+        signals.extend(fatal_signals());
+    }
+    for signal in signals {
+        install_handler(shell, signal);
+    }
+}
+
+/* A forked copy of the shell starts without the traps of its parent,
+except for the signals the parent ignores. */
+pub fn reset_traps(shell: &mut Shell) {
+    let names: Vec<String> = shell.traps.keys().cloned().collect();
+    shell.traps.retain(|_, command| command.is_empty());
+    install_handlers(shell, &names);
+}
+
+fn print_trap(name: &str, command: &str, streams: &mut Streams) {
+    let specification = match find_signal(name) {
+        Some(_) => format!("SIG{}", name),
+        None => name.to_string(),
+    };
+    let _ = writeln!(
+        streams.stdout,
+        "trap -- {} {}",
+        single_quote(command),
+        specification
+    );
+}
+
+/* `trap [-lp] [[action] sigspec...]` sets the command that runs when a
+signal arrives or one of the conditions EXIT, ERR, DEBUG or RETURN
+happens. An empty action ignores the signals and `-` puts back what they
+did before. Without an action, or with -p, the traps are printed in a
+form that can be run again, and -l lists the signals. */
+pub fn builtin_trap(shell: &mut Shell, arguments: &[String], streams: &mut Streams) -> i32 {
+    let (mut list, mut print) = (false, false);
+    let mut position = 1;
+    while let Some(argument) = arguments.get(position) {
+        if !argument.starts_with('-') || argument == "-" {
+            break;
+        }
+        position += 1;
+        if argument == "--" {
+            break;
+        }
+        for option in argument[1..].chars() {
+            match option {
+                'l' => list = true,
+                'p' => print = true,
+                _ => {
+                    streams.error(format!("trap: -{}: invalid option", option));
+                    let _ = writeln!(
+                        streams.stderr,
+                        "trap: usage: trap [-lp] [[arg] signal_spec ...]"
+                    );
+                    return 2;
+                }
+            }
+        }
+    }
+    let operands = &arguments[position..];
+    if list {
+        for (index, (name, signal)) in SIGNALS.iter().enumerate() {
+            let separator = if index % 5 == 4 || index + 1 == SIGNALS.len() {
+                "\n"
+            } else {
+                "\t"
+            };
+            let _ = write!(
+                streams.stdout,
+                "{:2}) SIG{}{}",
+                *signal as i32, name, separator
+            );
+        }
+        return 0;
+    }
+    if print || operands.is_empty() {
+        let mut status = 0;
+        let names: Vec<String> = if operands.is_empty() {
+            trap_names().iter().map(|name| name.to_string()).collect()
+        } else {
+            let mut names = Vec::new();
+            for operand in operands {
+                match trap_name(operand) {
+                    Some(name) => names.push(name),
+                    None => {
+                        streams.error(format!("trap: {}: invalid signal specification", operand));
+                        status = 1;
+                    }
+                }
+            }
+            names
+        };
+        for name in names {
+            if let Some(command) = shell.traps.get(&name) {
+                print_trap(&name, command, streams);
+            }
+        }
+        return status;
+    }
+
+    /* A lone specification, or one that starts with a number, resets
+    the signals instead of being taken as the action. */
+    let (action, specifications) = if operands.len() == 1 || operands[0].parse::<u32>().is_ok() {
+        (None, operands)
+    } else if operands[0] == "-" {
+        (None, &operands[1..])
+    } else {
+        (Some(&operands[0]), &operands[1..])
+    };
+    let mut status = 0;
+    let mut names = Vec::new();
+    for specification in specifications {
+        match trap_name(specification) {
+            Some(name) => {
+                match action {
+                    Some(command) => shell.traps.insert(name.clone(), command.clone()),
+                    None => shell.traps.remove(&name),
+                };
+                names.push(name);
+            }
+            None => {
+                streams.error(format!(
+                    "trap: {}: invalid signal specification",
+                    specification
+                ));
+                status = 1;
+            }
+        }
+    }
+    install_handlers(shell, &names);
+    status
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use executor::capture_command_output;
+
+    /* Runs a script in a forked copy of the shell, returning what it
+    wrote to standard output and the status it exited with. */
+    fn run(script: &str) -> (String, Option<i32>) {
+        let mut shell = Shell::new();
+        let output = capture_command_output(&mut shell, script).unwrap();
+        (output, shell.substitution_status)
+    }
+
+    #[test]
+    fn trap_name_test() {
+        assert_eq!(trap_name("INT"), Some("INT".to_string()));
+        assert_eq!(trap_name("sigterm"), Some("TERM".to_string()));
+        assert_eq!(trap_name("1"), Some("HUP".to_string()));
+        assert_eq!(trap_name("0"), Some("EXIT".to_string()));
+        assert_eq!(trap_name("err"), Some("ERR".to_string()));
+        assert_eq!(trap_name("SIGFOO"), None);
+        assert_eq!(trap_name("99"), None);
+    }
+
+    #[test]
+    fn exit_trap_test() {
+        let expected = ("bye 3".to_string(), Some(3));
+        assert_eq!(run("trap 'echo bye $?' EXIT; exit 3; echo no"), expected);
+        let expected = ("done\nbye 0".to_string(), Some(0));
+        assert_eq!(run("trap 'echo bye $?' EXIT; echo done"), expected);
+        let expected = ("bye".to_string(), Some(143));
+        let script = "trap 'echo bye' EXIT; sh -c 'kill -TERM $PPID'; echo no";
+        assert_eq!(run(script), expected);
+    }
+
+    #[test]
+    fn err_trap_test() {
+        let script = "trap 'echo err $?' ERR
+            if false; then :; fi; while false; do :; done; until ! false; do :; done
+            false || true; false && true; ! false
+            f() { false; echo f; }; if f; then :; fi
+            (exit 2); echo next";
+        assert_eq!(run(script).0, "f\nerr 2\nnext");
+    }
+
+    #[test]
+    fn return_and_debug_trap_test() {
+        let script = "f() { trap 'echo return' RETURN; echo in f; }; f; echo after";
+        assert_eq!(run(script).0, "in f\nreturn\nafter");
+        let script = "trap 'echo debug' DEBUG; echo a; f() { echo in f; }; f
+            trap - DEBUG; echo b";
+        assert_eq!(run(script).0, "debug\na\ndebug\nin f\ndebug\nb");
+        let script = "g() { trap 'echo debug' DEBUG; echo in g; }; g; echo after";
+        assert_eq!(run(script).0, "debug\nin g\ndebug\nafter");
+    }
+}